@@ -8,6 +8,9 @@ pub enum ConfigError {
     #[error("Required field '{0}' is not found; please set '{0}' in '{1}'.")]
     RequiredFieldNotFound(String, String),
 
+    #[error("Invalid value '{1}' for '{0}'; please fix it in '{2}'.")]
+    InvalidFieldValue(String, String, String),
+
     #[error("The $HOME environment variable is not set; please set it.")]
     HomeEnvironmentNotFoundError,
 
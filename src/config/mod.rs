@@ -3,7 +3,10 @@ pub mod templates;
 
 use crate::config::error::ConfigError;
 use crate::shared::utils;
-use mlua::{Lua, Table, Value};
+use crate::shared::utils::date::{parse_bound, parse_duration, parse_weekday, Bound};
+use chrono::{NaiveTime, Weekday};
+use chrono_tz::Tz;
+use mlua::{FromLua, Lua, Table, Value};
 use std::{
     env, fs,
     path::{Path, PathBuf},
@@ -21,6 +24,22 @@ pub struct Config {
 pub struct Settings {
     pub tz: String,
     pub oauth_file_path: String,
+    pub token_key_command: Option<String>,
+    pub token_refresh_leeway_seconds: i64,
+    pub week_start: Weekday,
+    pub workdays: Vec<Weekday>,
+    pub display_timezones: Vec<String>,
+    pub working_hours: Vec<WorkingHours>,
+    pub min_free_slot_minutes: i64,
+    pub meeting_budget_minutes: Option<i64>,
+}
+
+/// The hours considered available for meetings on a given weekday.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct WorkingHours {
+    pub weekday: Weekday,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -31,11 +50,24 @@ pub struct Source {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Output {
     pub template: String,
+    pub include_empty_days: bool,
+    /// Additional templates, offered as MCP prompts. Sorted by name.
+    pub templates: Vec<NamedTemplate>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct NamedTemplate {
+    pub name: String,
+    pub description: Option<String>,
+    pub template: String,
+    /// The date range expression used when the prompt is requested without one.
+    pub range: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct GoogleSource {
     pub oauth2: GoogleOAuth2,
+    pub service_account: Option<GoogleServiceAccount>,
     pub calendar: GoogleCalendar,
 }
 
@@ -47,6 +79,12 @@ pub struct GoogleOAuth2 {
     pub scopes: Vec<String>,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GoogleServiceAccount {
+    pub key_file: String,
+    pub subject: Option<String>,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct GoogleCalendar {
     pub get_events: GoogleCalendarGetEvents,
@@ -65,6 +103,102 @@ pub struct Experimental {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Mcp {
     pub insert_calendar_event: InsertCalendarEvent,
+    pub write: McpWrite,
+    pub resources: McpResources,
+    pub http: McpHttp,
+}
+
+/// Access control for `cal2prompt mcp --http`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct McpHttp {
+    /// Clients must send `Authorization: Bearer <token>` when set.
+    pub bearer_token: Option<String>,
+    /// Browser origins allowed besides localhost.
+    pub allowed_origins: Vec<String>,
+    /// New sessions are refused while this many are open.
+    pub max_sessions: usize,
+    /// Sessions without a request or open stream for this long are closed.
+    pub session_idle_timeout_seconds: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct McpResources {
+    /// How often subscribed resources are re-read to detect changes.
+    pub poll_interval_seconds: u64,
+}
+
+/// Guardrails for the MCP tools that change calendars.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct McpWrite {
+    /// Return the requests that would be sent instead of sending them.
+    pub dry_run: bool,
+    /// The calendars each tool may write to.
+    pub allowed_calendars: Vec<AllowedCalendars>,
+    pub max_events_per_session: Option<usize>,
+    /// Ranges no event may be created in, moved into or out of, or removed from.
+    pub forbidden_ranges: Vec<ForbiddenRange>,
+    /// Where every mutation is appended as a JSON line; `None` disables it.
+    pub audit_log_path: Option<String>,
+}
+
+impl McpWrite {
+    pub fn allowed_calendars(&self, tool: WriteTool) -> &[String] {
+        self.allowed_calendars
+            .iter()
+            .find(|allowed| allowed.tool == tool)
+            .map(|allowed| allowed.calendar_ids.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WriteTool {
+    Insert,
+    Update,
+    Move,
+    Delete,
+}
+
+impl WriteTool {
+    pub const ALL: [WriteTool; 4] = [
+        WriteTool::Insert,
+        WriteTool::Update,
+        WriteTool::Move,
+        WriteTool::Delete,
+    ];
+
+    /// The key under `experimental.mcp.write.allowedCalendars`.
+    pub fn config_key(self) -> &'static str {
+        match self {
+            WriteTool::Insert => "insertCalendarEvent",
+            WriteTool::Update => "updateCalendarEvent",
+            WriteTool::Move => "moveCalendarEvent",
+            WriteTool::Delete => "deleteCalendarEvent",
+        }
+    }
+
+    /// The MCP tool name.
+    pub fn tool_name(self) -> &'static str {
+        match self {
+            WriteTool::Insert => "insert_calendar_event",
+            WriteTool::Update => "update_calendar_event",
+            WriteTool::Move => "move_calendar_event",
+            WriteTool::Delete => "delete_calendar_event",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AllowedCalendars {
+    pub tool: WriteTool,
+    pub calendar_ids: Vec<String>,
+}
+
+/// `since`/`until` as accepted by `--since`/`--until`, in `settings.TZ`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ForbiddenRange {
+    pub since: String,
+    pub until: String,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -72,6 +206,22 @@ pub struct InsertCalendarEvent {
     pub calendar_id: Option<String>,
 }
 
+const DEFAULT_TOKEN_REFRESH_LEEWAY_SECONDS: i64 = 60;
+const DEFAULT_WORKDAYS: [Weekday; 5] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+];
+const DEFAULT_WORKING_HOURS: (&str, &str) = ("09:00", "18:00");
+const DEFAULT_MIN_FREE_SLOT_MINUTES: i64 = 30;
+const DEFAULT_RESOURCE_POLL_INTERVAL_SECONDS: u64 = 60;
+const DEFAULT_MCP_HTTP_MAX_SESSIONS: usize = 32;
+const DEFAULT_MCP_HTTP_SESSION_IDLE_TIMEOUT_SECONDS: u64 = 1800;
+// Port 0 lets the OS pick a free loopback port for the OAuth redirect.
+const AUTO_REDIRECT_URL: &str = "http://127.0.0.1:0";
+
 pub fn init() -> anyhow::Result<Config> {
     let path_buf = get_config_file_path()?;
     load_config(&path_buf)
@@ -110,7 +260,14 @@ fn get_oauth_path() -> anyhow::Result<PathBuf> {
     Ok(p)
 }
 
-fn load_config(config_file_path: &Path) -> anyhow::Result<Config> {
+fn get_audit_log_path() -> anyhow::Result<PathBuf> {
+    let home_dir = env::var("HOME").map_err(|_e| ConfigError::HomeEnvironmentNotFoundError)?;
+    let default_path = format!("{}/.local/share/cal2prompt/audit.jsonl", home_dir);
+
+    Ok(PathBuf::from(&default_path))
+}
+
+pub(crate) fn load_config(config_file_path: &Path) -> anyhow::Result<Config> {
     let lua = Lua::new();
 
     let config_path = config_file_path
@@ -131,6 +288,12 @@ fn load_config(config_file_path: &Path) -> anyhow::Result<Config> {
     template_google_sub_mod
         .set("standard", crate::config::templates::google::STANDARD)
         .map_err(|e| ConfigError::LuaRuntimeError(e.to_string()))?;
+    template_google_sub_mod
+        .set(
+            "weeklyReview",
+            crate::config::templates::google::WEEKLY_REVIEW,
+        )
+        .map_err(|e| ConfigError::LuaRuntimeError(e.to_string()))?;
     template_sub_mod.set("google", template_google_sub_mod)?;
     cal2prompt_mod.set("template", template_sub_mod)?;
 
@@ -146,23 +309,58 @@ fn load_config(config_file_path: &Path) -> anyhow::Result<Config> {
     if let Value::Table(config_tbl) = config_eval {
         let source_tbl: Table = config_tbl.get::<Table>("source")?;
         let google_tbl: Table = source_tbl.get::<Table>("google")?;
-        let google_oauth2_tbl: Table = google_tbl.get::<Table>("oauth2")?;
-        let google_oauth2_client_id: String = google_oauth2_tbl
-            .get::<Option<String>>("clientID")?
-            .ok_or_else(|| {
-                ConfigError::RequiredFieldNotFound(
-                    "source.google.oauth2.clientID".to_owned(),
-                    utils::path::contract_tilde(config_file_path),
-                )
-            })?;
-        let google_oauth2_client_secret: String = google_oauth2_tbl
-            .get::<Option<String>>("clientSecret")?
-            .ok_or_else(|| {
-                ConfigError::RequiredFieldNotFound(
-                    "source.google.oauth2.clientSecret".to_owned(),
-                    utils::path::contract_tilde(config_file_path),
-                )
-            })?;
+        let service_account = match google_tbl.get::<Option<Table>>("serviceAccount")? {
+            Some(service_account_tbl) => {
+                let key_file = service_account_tbl
+                    .get::<Option<String>>("keyFile")?
+                    .ok_or_else(|| {
+                        ConfigError::RequiredFieldNotFound(
+                            "source.google.serviceAccount.keyFile".to_owned(),
+                            utils::path::contract_tilde(config_file_path),
+                        )
+                    })?;
+                let subject = service_account_tbl.get::<Option<String>>("subject")?;
+
+                Some(GoogleServiceAccount {
+                    key_file: utils::path::expand_tilde(&key_file)
+                        .to_string_lossy()
+                        .to_string(),
+                    subject,
+                })
+            }
+            None => None,
+        };
+
+        // The OAuth2 client is only required when no service account is configured.
+        let google_oauth2_tbl: Table = match google_tbl.get::<Option<Table>>("oauth2")? {
+            Some(tbl) => tbl,
+            None if service_account.is_some() => lua.create_table()?,
+            None => google_tbl.get::<Table>("oauth2")?,
+        };
+        let google_oauth2_client_id: String =
+            match google_oauth2_tbl.get::<Option<String>>("clientID")? {
+                Some(client_id) => client_id,
+                None if service_account.is_some() => String::new(),
+                None => {
+                    return Err(ConfigError::RequiredFieldNotFound(
+                        "source.google.oauth2.clientID".to_owned(),
+                        utils::path::contract_tilde(config_file_path),
+                    )
+                    .into())
+                }
+            };
+        let google_oauth2_client_secret: String =
+            match google_oauth2_tbl.get::<Option<String>>("clientSecret")? {
+                Some(client_secret) => client_secret,
+                None if service_account.is_some() => String::new(),
+                None => {
+                    return Err(ConfigError::RequiredFieldNotFound(
+                        "source.google.oauth2.clientSecret".to_owned(),
+                        utils::path::contract_tilde(config_file_path),
+                    )
+                    .into())
+                }
+            };
 
         let default_scopes_table = lua.create_table()?;
         default_scopes_table.push("https://www.googleapis.com/auth/calendar.events")?;
@@ -182,6 +380,13 @@ fn load_config(config_file_path: &Path) -> anyhow::Result<Config> {
 
         let redirect_url: String = google_oauth2_tbl
             .get::<Option<String>>("redirectURL")?
+            .map(|url| {
+                if url == "auto" {
+                    AUTO_REDIRECT_URL.to_string()
+                } else {
+                    url
+                }
+            })
             .unwrap_or("http://127.0.0.1:9004".to_string());
 
         let output_tbl: Table = config_tbl.get::<Table>("output")?;
@@ -194,31 +399,183 @@ fn load_config(config_file_path: &Path) -> anyhow::Result<Config> {
                 )
             })?;
 
+        let include_empty_days = output_tbl
+            .get::<Option<bool>>("includeEmptyDays")?
+            .unwrap_or(false);
+
+        let mut templates = match output_tbl.get::<Option<Table>>("templates")? {
+            Some(templates_tbl) => templates_tbl
+                .pairs::<String, Value>()
+                .map(|pair| {
+                    let (name, value) = pair?;
+                    // Either the template itself or a table describing it.
+                    let named = match value {
+                        Value::Table(template_tbl) => NamedTemplate {
+                            template: template_tbl.get::<Option<String>>("template")?.ok_or_else(
+                                || {
+                                    ConfigError::RequiredFieldNotFound(
+                                        format!("output.templates.{}.template", name),
+                                        utils::path::contract_tilde(config_file_path),
+                                    )
+                                },
+                            )?,
+                            description: template_tbl.get::<Option<String>>("description")?,
+                            range: template_tbl.get::<Option<String>>("range")?,
+                            name,
+                        },
+                        value => NamedTemplate {
+                            template: String::from_lua(value, &lua)?,
+                            description: None,
+                            range: None,
+                            name,
+                        },
+                    };
+                    Ok(named)
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            None => vec![],
+        };
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+
         let oauth_default_path = get_oauth_path()?;
-        let settings: Settings = match config_tbl.get::<Option<Table>>("settings") {
-            Ok(settings_tbl) => match settings_tbl {
-                Some(table) => {
-                    let oauth_file_path = table
-                        .get::<Option<String>>("oauthFilePath")?
-                        .unwrap_or(oauth_default_path.to_string_lossy().to_string());
-                    let tz = table
-                        .get::<Option<String>>("TZ")?
-                        .unwrap_or("UTC".to_string());
-
-                    Settings {
-                        oauth_file_path,
-                        tz,
+        let settings_tbl = match config_tbl.get::<Option<Table>>("settings") {
+            Ok(settings_tbl) => settings_tbl,
+            Err(_) => Some(lua.create_table().expect("failed to create table")),
+        }
+        .unwrap_or_else(|| lua.create_table().expect("failed to create table"));
+
+        let oauth_file_path = settings_tbl
+            .get::<Option<String>>("oauthFilePath")?
+            .unwrap_or(oauth_default_path.to_string_lossy().to_string());
+        let tz = settings_tbl
+            .get::<Option<String>>("TZ")?
+            .unwrap_or("UTC".to_string());
+        let token_key_command = settings_tbl.get::<Option<String>>("tokenKeyCommand")?;
+        let token_refresh_leeway_seconds = settings_tbl
+            .get::<Option<i64>>("tokenRefreshLeewaySeconds")?
+            .unwrap_or(DEFAULT_TOKEN_REFRESH_LEEWAY_SECONDS);
+
+        let week_start = match settings_tbl.get::<Option<String>>("weekStart")? {
+            Some(name) => parse_weekday(&name).ok_or_else(|| {
+                ConfigError::InvalidFieldValue(
+                    "settings.weekStart".to_owned(),
+                    name.clone(),
+                    utils::path::contract_tilde(config_file_path),
+                )
+            })?,
+            None => Weekday::Mon,
+        };
+        let workdays = match settings_tbl.get::<Option<Table>>("workdays")? {
+            Some(workdays_tbl) => workdays_tbl
+                .sequence_values::<String>()
+                .map(|name| {
+                    let name = name?;
+                    parse_weekday(&name).ok_or_else(|| {
+                        ConfigError::InvalidFieldValue(
+                            "settings.workdays".to_owned(),
+                            name.clone(),
+                            utils::path::contract_tilde(config_file_path),
+                        )
+                        .into()
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            None => DEFAULT_WORKDAYS.to_vec(),
+        };
+
+        let display_timezones = match settings_tbl.get::<Option<Table>>("displayTimezones")? {
+            Some(display_timezones_tbl) => display_timezones_tbl
+                .sequence_values::<String>()
+                .map(|name| {
+                    let name = name?;
+                    match name.parse::<Tz>() {
+                        Ok(_) => Ok(name),
+                        Err(_) => Err(ConfigError::InvalidFieldValue(
+                            "settings.displayTimezones".to_owned(),
+                            name.clone(),
+                            utils::path::contract_tilde(config_file_path),
+                        )
+                        .into()),
                     }
-                }
-                None => Settings {
-                    oauth_file_path: oauth_default_path.to_string_lossy().to_string(),
-                    tz: "UTC".to_string(),
-                },
-            },
-            Err(_) => Settings {
-                oauth_file_path: oauth_default_path.to_string_lossy().to_string(),
-                tz: "UTC".to_string(),
-            },
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?,
+            None => vec![],
+        };
+
+        let working_hours = match settings_tbl.get::<Option<Table>>("workingHours")? {
+            Some(working_hours_tbl) => {
+                let mut working_hours = working_hours_tbl
+                    .pairs::<String, Table>()
+                    .map(|pair| {
+                        let (name, hours_tbl) = pair?;
+                        let invalid = |value: String| {
+                            ConfigError::InvalidFieldValue(
+                                format!("settings.workingHours.{}", name),
+                                value,
+                                utils::path::contract_tilde(config_file_path),
+                            )
+                        };
+                        let weekday = parse_weekday(&name).ok_or_else(|| invalid(name.clone()))?;
+                        let start: String = hours_tbl.get(1)?;
+                        let end: String = hours_tbl.get(2)?;
+                        let start = NaiveTime::parse_from_str(&start, "%H:%M")
+                            .map_err(|_| invalid(start.clone()))?;
+                        let end = NaiveTime::parse_from_str(&end, "%H:%M")
+                            .map_err(|_| invalid(end.clone()))?;
+                        if start >= end {
+                            return Err(invalid(format!("{} >= {}", start, end)).into());
+                        }
+                        Ok(WorkingHours {
+                            weekday,
+                            start,
+                            end,
+                        })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                working_hours.sort_by_key(|hours| hours.weekday.num_days_from_monday());
+                working_hours
+            }
+            None => default_working_hours(&workdays),
+        };
+        let min_free_slot_minutes = match settings_tbl.get::<Option<String>>("minFreeSlot")? {
+            Some(value) => parse_duration(&value)
+                .map_err(|_| {
+                    ConfigError::InvalidFieldValue(
+                        "settings.minFreeSlot".to_owned(),
+                        value.clone(),
+                        utils::path::contract_tilde(config_file_path),
+                    )
+                })?
+                .num_minutes(),
+            None => DEFAULT_MIN_FREE_SLOT_MINUTES,
+        };
+
+        let meeting_budget_minutes = match settings_tbl.get::<Option<String>>("meetingBudget")? {
+            Some(value) => Some(
+                parse_duration(&value)
+                    .map_err(|_| {
+                        ConfigError::InvalidFieldValue(
+                            "settings.meetingBudget".to_owned(),
+                            value.clone(),
+                            utils::path::contract_tilde(config_file_path),
+                        )
+                    })?
+                    .num_minutes(),
+            ),
+            None => None,
+        };
+
+        let settings = Settings {
+            oauth_file_path,
+            tz,
+            token_key_command,
+            token_refresh_leeway_seconds,
+            week_start,
+            workdays,
+            display_timezones,
+            working_hours,
+            min_free_slot_minutes,
+            meeting_budget_minutes,
         };
 
         let experimental_tbl = match config_tbl.get::<Option<Table>>("experimental") {
@@ -241,9 +598,139 @@ fn load_config(config_file_path: &Path) -> anyhow::Result<Config> {
 
         let calendar_id = insert_tbl.get::<Option<String>>("calendarID")?;
 
+        let write_tbl = mcp_tbl
+            .get::<Option<Table>>("write")?
+            .unwrap_or_else(|| lua.create_table().expect("failed to create table"));
+        let allowed_tbl = write_tbl.get::<Option<Table>>("allowedCalendars")?;
+        let allowed_calendars = WriteTool::ALL
+            .iter()
+            .map(|&tool| {
+                let configured = match &allowed_tbl {
+                    Some(allowed_tbl) => allowed_tbl
+                        .get::<Option<Table>>(tool.config_key())?
+                        .map(|ids_tbl| ids_tbl.sequence_values().collect::<Result<Vec<_>, _>>())
+                        .transpose()?,
+                    None => None,
+                };
+                // Without an explicit list, tools may only write to the
+                // calendar new events are inserted into.
+                let calendar_ids =
+                    configured.unwrap_or_else(|| calendar_id.iter().cloned().collect());
+                Ok(AllowedCalendars { tool, calendar_ids })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let max_events_per_session = write_tbl.get::<Option<usize>>("maxEventsPerSession")?;
+        let forbidden_ranges = match write_tbl.get::<Option<Table>>("forbiddenRanges")? {
+            Some(ranges_tbl) => {
+                let range_tz: Tz = settings.tz.parse().map_err(|_| {
+                    ConfigError::InvalidFieldValue(
+                        "settings.TZ".to_owned(),
+                        settings.tz.clone(),
+                        utils::path::contract_tilde(config_file_path),
+                    )
+                })?;
+                ranges_tbl
+                    .sequence_values::<Table>()
+                    .map(|range_tbl| {
+                        let range_tbl = range_tbl?;
+                        let since: String = range_tbl.get(1)?;
+                        let until: String = range_tbl.get(2)?;
+                        let invalid = || {
+                            ConfigError::InvalidFieldValue(
+                                "experimental.mcp.write.forbiddenRanges".to_owned(),
+                                format!("{} - {}", since, until),
+                                utils::path::contract_tilde(config_file_path),
+                            )
+                        };
+                        let start =
+                            parse_bound(&since, &range_tz, Bound::Start).map_err(|_| invalid())?;
+                        let end =
+                            parse_bound(&until, &range_tz, Bound::End).map_err(|_| invalid())?;
+                        if start >= end {
+                            return Err(invalid().into());
+                        }
+                        Ok(ForbiddenRange { since, until })
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?
+            }
+            None => vec![],
+        };
+        let audit_log_path = match write_tbl.get::<Value>("auditLog")? {
+            Value::Nil => Some(get_audit_log_path()?.to_string_lossy().to_string()),
+            Value::Boolean(false) => None,
+            value => {
+                let path = String::from_lua(value, &lua)?;
+                Some(
+                    utils::path::expand_tilde(&path)
+                        .to_string_lossy()
+                        .to_string(),
+                )
+            }
+        };
+
+        let poll_interval_seconds = match mcp_tbl.get::<Option<Table>>("resources")? {
+            Some(resources_tbl) => resources_tbl
+                .get::<Option<u64>>("pollIntervalSeconds")?
+                .unwrap_or(DEFAULT_RESOURCE_POLL_INTERVAL_SECONDS),
+            None => DEFAULT_RESOURCE_POLL_INTERVAL_SECONDS,
+        };
+        if poll_interval_seconds == 0 {
+            return Err(ConfigError::InvalidFieldValue(
+                "experimental.mcp.resources.pollIntervalSeconds".to_owned(),
+                poll_interval_seconds.to_string(),
+                utils::path::contract_tilde(config_file_path),
+            )
+            .into());
+        }
+
+        let http_tbl = mcp_tbl
+            .get::<Option<Table>>("http")?
+            .unwrap_or_else(|| lua.create_table().expect("failed to create table"));
+        let allowed_origins = http_tbl
+            .get::<Option<Vec<String>>>("allowedOrigins")?
+            .unwrap_or_default();
+        let max_sessions = http_tbl
+            .get::<Option<usize>>("maxSessions")?
+            .unwrap_or(DEFAULT_MCP_HTTP_MAX_SESSIONS);
+        if max_sessions == 0 {
+            return Err(ConfigError::InvalidFieldValue(
+                "experimental.mcp.http.maxSessions".to_owned(),
+                max_sessions.to_string(),
+                utils::path::contract_tilde(config_file_path),
+            )
+            .into());
+        }
+        let session_idle_timeout_seconds = http_tbl
+            .get::<Option<u64>>("sessionIdleTimeoutSeconds")?
+            .unwrap_or(DEFAULT_MCP_HTTP_SESSION_IDLE_TIMEOUT_SECONDS);
+        if session_idle_timeout_seconds == 0 {
+            return Err(ConfigError::InvalidFieldValue(
+                "experimental.mcp.http.sessionIdleTimeoutSeconds".to_owned(),
+                session_idle_timeout_seconds.to_string(),
+                utils::path::contract_tilde(config_file_path),
+            )
+            .into());
+        }
+
         let experimental = Experimental {
             mcp: Mcp {
                 insert_calendar_event: InsertCalendarEvent { calendar_id },
+                write: McpWrite {
+                    dry_run: write_tbl.get::<Option<bool>>("dryRun")?.unwrap_or(false),
+                    allowed_calendars,
+                    max_events_per_session,
+                    forbidden_ranges,
+                    audit_log_path,
+                },
+                resources: McpResources {
+                    poll_interval_seconds,
+                },
+                http: McpHttp {
+                    bearer_token: http_tbl.get::<Option<String>>("bearerToken")?,
+                    allowed_origins,
+                    max_sessions,
+                    session_idle_timeout_seconds,
+                },
             },
         };
 
@@ -256,12 +743,17 @@ fn load_config(config_file_path: &Path) -> anyhow::Result<Config> {
                         redirect_url,
                         scopes: google_oauth2_scopes,
                     },
+                    service_account,
                     calendar: GoogleCalendar {
                         get_events: GoogleCalendarGetEvents { calendar_ids },
                     },
                 },
             },
-            output: Output { template },
+            output: Output {
+                template,
+                include_empty_days,
+                templates,
+            },
             settings,
             experimental,
         };
@@ -275,6 +767,18 @@ fn load_config(config_file_path: &Path) -> anyhow::Result<Config> {
     }
 }
 
+fn default_working_hours(workdays: &[Weekday]) -> Vec<WorkingHours> {
+    let (start, end) = DEFAULT_WORKING_HOURS;
+    workdays
+        .iter()
+        .map(|weekday| WorkingHours {
+            weekday: *weekday,
+            start: NaiveTime::parse_from_str(start, "%H:%M").unwrap(),
+            end: NaiveTime::parse_from_str(end, "%H:%M").unwrap(),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -349,6 +853,7 @@ return M
                         redirect_url: "http://127.0.0.1:9004".to_string(),
                         scopes: vec!["https://www.googleapis.com/auth/calendar.events".to_string()],
                     },
+                    service_account: None,
                     calendar: GoogleCalendar {
                         get_events: GoogleCalendarGetEvents { calendar_ids },
                     },
@@ -356,16 +861,51 @@ return M
             },
             output: Output {
                 template: crate::config::templates::google::STANDARD.to_string(),
+                include_empty_days: false,
+                templates: vec![],
             },
             settings: Settings {
                 oauth_file_path,
                 tz,
+                token_key_command: None,
+                token_refresh_leeway_seconds: DEFAULT_TOKEN_REFRESH_LEEWAY_SECONDS,
+                week_start: Weekday::Mon,
+                workdays: DEFAULT_WORKDAYS.to_vec(),
+                display_timezones: vec![],
+                working_hours: default_working_hours(&DEFAULT_WORKDAYS),
+                min_free_slot_minutes: DEFAULT_MIN_FREE_SLOT_MINUTES,
+                meeting_budget_minutes: None,
             },
             experimental: Experimental {
                 mcp: Mcp {
                     insert_calendar_event: InsertCalendarEvent {
                         calendar_id: Some("test@example.com".to_string()),
                     },
+                    write: McpWrite {
+                        dry_run: false,
+                        allowed_calendars: WriteTool::ALL
+                            .iter()
+                            .map(|&tool| AllowedCalendars {
+                                tool,
+                                calendar_ids: vec!["test@example.com".to_string()],
+                            })
+                            .collect(),
+                        max_events_per_session: None,
+                        forbidden_ranges: vec![],
+                        audit_log_path: Some(format!(
+                            "{}/.local/share/cal2prompt/audit.jsonl",
+                            home_dir
+                        )),
+                    },
+                    resources: McpResources {
+                        poll_interval_seconds: DEFAULT_RESOURCE_POLL_INTERVAL_SECONDS,
+                    },
+                    http: McpHttp {
+                        bearer_token: None,
+                        allowed_origins: vec![],
+                        max_sessions: DEFAULT_MCP_HTTP_MAX_SESSIONS,
+                        session_idle_timeout_seconds: DEFAULT_MCP_HTTP_SESSION_IDLE_TIMEOUT_SECONDS,
+                    },
                 },
             },
         };
@@ -436,6 +976,7 @@ return M
                         redirect_url: "http://127.0.0.1:9004".to_string(),
                         scopes: vec!["https://www.googleapis.com/auth/calendar.events".to_string()],
                     },
+                    service_account: None,
                     calendar: GoogleCalendar {
                         get_events: GoogleCalendarGetEvents { calendar_ids },
                     },
@@ -443,14 +984,49 @@ return M
             },
             output: Output {
                 template: crate::config::templates::google::STANDARD.to_string(),
+                include_empty_days: false,
+                templates: vec![],
             },
             settings: Settings {
                 oauth_file_path,
                 tz,
+                token_key_command: None,
+                token_refresh_leeway_seconds: DEFAULT_TOKEN_REFRESH_LEEWAY_SECONDS,
+                week_start: Weekday::Mon,
+                workdays: DEFAULT_WORKDAYS.to_vec(),
+                display_timezones: vec![],
+                working_hours: default_working_hours(&DEFAULT_WORKDAYS),
+                min_free_slot_minutes: DEFAULT_MIN_FREE_SLOT_MINUTES,
+                meeting_budget_minutes: None,
             },
             experimental: Experimental {
                 mcp: Mcp {
                     insert_calendar_event: InsertCalendarEvent { calendar_id: None },
+                    write: McpWrite {
+                        dry_run: false,
+                        allowed_calendars: WriteTool::ALL
+                            .iter()
+                            .map(|&tool| AllowedCalendars {
+                                tool,
+                                calendar_ids: vec![],
+                            })
+                            .collect(),
+                        max_events_per_session: None,
+                        forbidden_ranges: vec![],
+                        audit_log_path: Some(format!(
+                            "{}/.local/share/cal2prompt/audit.jsonl",
+                            home_dir
+                        )),
+                    },
+                    resources: McpResources {
+                        poll_interval_seconds: DEFAULT_RESOURCE_POLL_INTERVAL_SECONDS,
+                    },
+                    http: McpHttp {
+                        bearer_token: None,
+                        allowed_origins: vec![],
+                        max_sessions: DEFAULT_MCP_HTTP_MAX_SESSIONS,
+                        session_idle_timeout_seconds: DEFAULT_MCP_HTTP_SESSION_IDLE_TIMEOUT_SECONDS,
+                    },
                 },
             },
         };
@@ -459,4 +1035,360 @@ return M
 
         Ok(())
     }
+
+    #[test]
+    fn test_load_config_redirect_url_auto() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config_file_path = temp_dir.path().join("config.lua");
+
+        let config_code = r#"
+return {
+  source = {
+    google = {
+      oauth2 = {
+        clientID = "test_client_id",
+        clientSecret = "test_client_secret",
+        redirectURL = "auto",
+      },
+      calendar = {
+        getEvents = {
+          calendarIDs = { "test@example.com" }
+        }
+      }
+    },
+  },
+  output = {
+    template = ""
+  }
+}
+"#;
+        fs::write(&config_file_path, config_code)?;
+
+        let config = load_config(&config_file_path)?;
+
+        assert_eq!(
+            config.source.google.oauth2.redirect_url,
+            "http://127.0.0.1:0"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_config_templates() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config_file_path = temp_dir.path().join("config.lua");
+
+        let config_code = r#"
+local cal2prompt = require("cal2prompt")
+
+return {
+  source = {
+    google = {
+      oauth2 = {
+        clientID = "test_client_id",
+        clientSecret = "test_client_secret",
+      },
+      calendar = {
+        getEvents = {
+          calendarIDs = { "test@example.com" }
+        }
+      }
+    },
+  },
+  output = {
+    template = "",
+    includeEmptyDays = true,
+    templates = {
+      standup = "{{ days|length }}",
+      review = {
+        description = "Last week in review",
+        template = cal2prompt.template.google.weeklyReview,
+        range = "last week",
+      },
+    },
+  }
+}
+"#;
+        fs::write(&config_file_path, config_code)?;
+
+        let config = load_config(&config_file_path)?;
+
+        assert!(config.output.include_empty_days);
+        assert_eq!(
+            config.output.templates,
+            vec![
+                NamedTemplate {
+                    name: "review".to_string(),
+                    description: Some("Last week in review".to_string()),
+                    template: crate::config::templates::google::WEEKLY_REVIEW.to_string(),
+                    range: Some("last week".to_string()),
+                },
+                NamedTemplate {
+                    name: "standup".to_string(),
+                    description: None,
+                    template: "{{ days|length }}".to_string(),
+                    range: None,
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_config_mcp_write() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config_file_path = temp_dir.path().join("config.lua");
+
+        let config_code = r#"
+return {
+  source = {
+    google = {
+      oauth2 = {
+        clientID = "test_client_id",
+        clientSecret = "test_client_secret",
+      },
+      calendar = {
+        getEvents = {
+          calendarIDs = { "test@example.com" }
+        }
+      }
+    },
+  },
+  output = {
+    template = ""
+  },
+  experimental = {
+    mcp = {
+      insertCalendarEvent = {
+        calendarID = "test@example.com"
+      },
+      write = {
+        dryRun = true,
+        allowedCalendars = {
+          deleteCalendarEvent = {},
+          moveCalendarEvent = { "test@example.com", "archive@example.com" },
+        },
+        maxEventsPerSession = 3,
+        forbiddenRanges = { { "2025-12-24", "2025-12-26" } },
+        auditLog = false,
+      },
+      http = {
+        maxSessions = 4,
+        sessionIdleTimeoutSeconds = 600,
+      }
+    }
+  }
+}
+"#;
+        fs::write(&config_file_path, config_code)?;
+
+        let mcp = load_config(&config_file_path)?.experimental.mcp;
+        assert_eq!(mcp.http.max_sessions, 4);
+        assert_eq!(mcp.http.session_idle_timeout_seconds, 600);
+        let write = mcp.write;
+
+        assert!(write.dry_run);
+        assert_eq!(
+            write.allowed_calendars(WriteTool::Insert),
+            ["test@example.com"]
+        );
+        assert!(write.allowed_calendars(WriteTool::Delete).is_empty());
+        assert_eq!(
+            write.allowed_calendars(WriteTool::Move),
+            ["test@example.com", "archive@example.com"]
+        );
+        assert_eq!(write.max_events_per_session, Some(3));
+        assert_eq!(
+            write.forbidden_ranges,
+            vec![ForbiddenRange {
+                since: "2025-12-24".to_string(),
+                until: "2025-12-26".to_string(),
+            }]
+        );
+        assert_eq!(write.audit_log_path, None);
+
+        let invalid = config_code.replace(
+            r#""2025-12-24", "2025-12-26""#,
+            r#""2025-12-26", "2025-12-24""#,
+        );
+        fs::write(&config_file_path, invalid)?;
+        let err = load_config(&config_file_path).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("experimental.mcp.write.forbiddenRanges"),
+            "{err}"
+        );
+
+        fs::write(
+            &config_file_path,
+            config_code.replace("maxSessions = 4", "maxSessions = 0"),
+        )?;
+        let err = load_config(&config_file_path).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("experimental.mcp.http.maxSessions"),
+            "{err}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_config_service_account() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config_file_path = temp_dir.path().join("config.lua");
+
+        let config_code = r#"
+return {
+  source = {
+    google = {
+      serviceAccount = {
+        keyFile = "/etc/cal2prompt/service-account.json",
+        subject = "rooms@example.com",
+      },
+      oauth2 = {
+        scopes = { "https://www.googleapis.com/auth/calendar.readonly" },
+      },
+      calendar = {
+        getEvents = {
+          calendarIDs = { "room-a@resource.calendar.google.com" }
+        }
+      }
+    },
+  },
+  output = {
+    template = ""
+  }
+}
+"#;
+        fs::write(&config_file_path, config_code)?;
+
+        let config = load_config(&config_file_path)?;
+
+        assert_eq!(
+            config.source.google.service_account,
+            Some(GoogleServiceAccount {
+                key_file: "/etc/cal2prompt/service-account.json".to_string(),
+                subject: Some("rooms@example.com".to_string()),
+            })
+        );
+        assert_eq!(
+            config.source.google.oauth2.scopes,
+            vec!["https://www.googleapis.com/auth/calendar.readonly".to_string()]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_config_week_settings() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config_file_path = temp_dir.path().join("config.lua");
+
+        let config_code = r#"
+return {
+  settings = {
+    weekStart = "Sunday",
+    workdays = { "sun", "mon", "tue", "wed", "thu" },
+    displayTimezones = { "Asia/Tokyo", "America/Los_Angeles" },
+    workingHours = {
+      sun = { "10:00", "16:00" },
+      mon = { "09:30", "18:00" },
+    },
+    minFreeSlot = "1h",
+    meetingBudget = "5h30m",
+  },
+  source = {
+    google = {
+      oauth2 = {
+        clientID = "test_client_id",
+        clientSecret = "test_client_secret",
+      },
+      calendar = {
+        getEvents = {
+          calendarIDs = { "test@example.com" }
+        }
+      }
+    },
+  },
+  output = {
+    template = ""
+  }
+}
+"#;
+        fs::write(&config_file_path, config_code)?;
+
+        let config = load_config(&config_file_path)?;
+
+        assert_eq!(config.settings.week_start, Weekday::Sun);
+        assert_eq!(
+            config.settings.workdays,
+            vec![
+                Weekday::Sun,
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu
+            ]
+        );
+        assert_eq!(
+            config.settings.display_timezones,
+            vec!["Asia/Tokyo".to_string(), "America/Los_Angeles".to_string()]
+        );
+        let time = |s| NaiveTime::parse_from_str(s, "%H:%M").unwrap();
+        assert_eq!(
+            config.settings.working_hours,
+            vec![
+                WorkingHours {
+                    weekday: Weekday::Mon,
+                    start: time("09:30"),
+                    end: time("18:00"),
+                },
+                WorkingHours {
+                    weekday: Weekday::Sun,
+                    start: time("10:00"),
+                    end: time("16:00"),
+                },
+            ]
+        );
+        assert_eq!(config.settings.min_free_slot_minutes, 60);
+        assert_eq!(config.settings.meeting_budget_minutes, Some(330));
+
+        fs::write(
+            &config_file_path,
+            config_code.replace("\"Sunday\"", "\"Someday\""),
+        )?;
+        let err = load_config(&config_file_path).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ConfigError>(),
+            Some(ConfigError::InvalidFieldValue(field, value, _))
+                if field == "settings.weekStart" && value == "Someday"
+        ));
+
+        fs::write(
+            &config_file_path,
+            config_code.replace("\"America/Los_Angeles\"", "\"Mars/Olympus\""),
+        )?;
+        let err = load_config(&config_file_path).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ConfigError>(),
+            Some(ConfigError::InvalidFieldValue(field, value, _))
+                if field == "settings.displayTimezones" && value == "Mars/Olympus"
+        ));
+
+        fs::write(
+            &config_file_path,
+            config_code.replace("\"16:00\"", "\"09:00\""),
+        )?;
+        let err = load_config(&config_file_path).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ConfigError>(),
+            Some(ConfigError::InvalidFieldValue(field, _, _))
+                if field == "settings.workingHours.sun"
+        ));
+
+        Ok(())
+    }
 }
@@ -20,14 +20,24 @@ pub const STANDARD: &str = r#"Here is your schedule summary. Please find the det
 {% endfor %}
 {% endif %}
 
+{% if day.conflicts|length > 0 %}
+### Conflicts:
+{% for c in day.conflicts %}
+- {{ c.events|join(" / ") }} ({{ c.start }} - {{ c.end }})
+{% endfor %}
+
+{% endif %}
 ### Events:
 {% if day.timed_events|length == 0 %}
 (No timed events)
 {% else %}
 {% for ev in day.timed_events %}
 - {{ ev.summary }}
-  - Start: {{ ev.start }}
-  - End:   {{ ev.end }}
+  - Start: {{ ev.start }}{{ " (" ~ ev.start_date ~ ")" if ev.continues_from_previous_day else "" }}
+  - End:   {{ ev.end }}{{ " (" ~ ev.end_date ~ ")" if ev.continues_to_next_day else "" }}
+  {% for z in ev.zones %}
+  - {{ z.tz }}: {{ z.start }} - {{ z.end }}
+  {% endfor %}
   - Location: {{ ev.location or "N/A" }}
   - Description: {{ ev.description or "No description." }}
   - Attendees:
@@ -42,3 +52,35 @@ pub const STANDARD: &str = r#"Here is your schedule summary. Please find the det
 {% endif %}
 {% endfor %}
 "#;
+
+pub const WEEKLY_REVIEW: &str = r#"Please help me review this stretch of my calendar. Tell me where my time went, what looks overbooked, and what I should protect or drop next time.
+
+## Summary
+- Events: {{ stats.total_events }} ({{ stats.total_minutes // 60 }}h {{ stats.total_minutes % 60 }}m)
+- Meetings: {{ stats.meeting_minutes // 60 }}h {{ stats.meeting_minutes % 60 }}m / Focus: {{ stats.focus_minutes // 60 }}h {{ stats.focus_minutes % 60 }}m / Other: {{ stats.other_minutes // 60 }}h {{ stats.other_minutes % 60 }}m
+- Recurring: {{ stats.recurring.events }} events / One-off: {{ stats.one_off.events }} events
+{% if stats.longest_free_block %}
+- Longest free block: {{ stats.longest_free_block.date }} {{ stats.longest_free_block.start }} - {{ stats.longest_free_block.end }}
+{% endif %}
+
+## Top collaborators
+{% for c in stats.top_collaborators %}
+- {{ c.key }}: {{ c.events }} events, {{ c.minutes // 60 }}h {{ c.minutes % 60 }}m
+{% else %}
+(None)
+{% endfor %}
+
+## Days
+{% for day in days %}
+### {{ day.date }} ({{ day.weekday }}){{ " - over the meeting budget" if day.over_budget else "" }}
+{% for ev in day.all_day_events %}
+- (All day) {{ ev.summary }}
+{% endfor %}
+{% for ev in day.timed_events %}
+- {{ ev.start }} - {{ ev.end }} {{ ev.summary }}
+{% endfor %}
+{% if day.all_day_events|length == 0 and day.timed_events|length == 0 %}
+(No events)
+{% endif %}
+{% endfor %}
+"#;
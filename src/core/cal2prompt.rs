@@ -1,22 +1,37 @@
 use crate::config::{self, Config};
+use crate::core::date_expr::DateRangeParser;
 use crate::core::event::{EventDurationCalculator, RealClock};
+use crate::core::schedule::{
+    availability, back_to_back_chains, busy_blocks, covered_minutes, format_free_slots,
+    free_busy_blocks, free_slots, overlapping_pairs, Availability, FreeSlot, TimeBlock,
+};
+use crate::core::stats::{self, Stats};
 use crate::core::template::generate;
-use crate::google::calendar::model::{CreatedEventResponse, EventItem};
-use crate::google::calendar::service::GoogleCalendarService;
+use crate::core::token_store::{TokenKeySource, TokenStore};
+use crate::core::write_guard::{AuditEntry, WriteGuard, WriteOutcome};
+use crate::google::calendar::model::EventItem;
+use crate::google::calendar::service::{
+    EventChanges, FetchProgress, GoogleCalendarService, Mutation, NewEvent, PlannedRequest,
+};
 use crate::google::oauth::{OAuth2Client, OAuth2Error, Token};
+use crate::google::service_account::ServiceAccountClient;
 use crate::mcp::handler::McpHandler;
+use crate::mcp::http;
 use crate::mcp::stdio::StdioTransport;
-use crate::shared::utils::date::intersection_days;
-use chrono::{DateTime, NaiveDate, TimeZone};
+use crate::shared::utils::date::{intersection_days, parse_bound, start_of_day, Bound};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
 use chrono_tz::Tz;
 use serde::Serialize;
 use std::collections::BTreeMap;
-use std::fs;
+use std::net::SocketAddr;
 use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Cal2PromptError {
-    #[error("OAuth2 port in use: {0}")]
+    #[error("{0}")]
     OAuth2PortInUse(#[from] OAuth2Error),
 
     #[error(transparent)]
@@ -33,15 +48,22 @@ pub enum JsonRpcErrorCode {
     InternalError = -32603,
     // Custom error codes should be in the range -32000 to -32099
     PortInUse = -32000,
+    ResourceNotFound = -32002,
 }
 
 pub struct Cal2Prompt {
-    config: Config,
-    pub token: Option<Token>,
+    config: Arc<Config>,
+    /// Locked for the whole of a refresh or OAuth flow, so concurrent MCP
+    /// requests wait for one instead of each starting their own.
+    token: Mutex<Option<Token>>,
+    write_guard: Arc<WriteGuard>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 struct Event {
+    id: Option<String>,
+    /// The configured calendar the event belongs to.
+    calendar_id: Option<String>,
     summary: String,
     start: String,
     end: String,
@@ -50,197 +72,556 @@ struct Event {
     attendees: Vec<String>,
     html_link: Option<String>,
     all_day: bool,
+    /// Full local dates of `start`/`end`. For all-day events `end_date` is
+    /// exclusive, as returned by Google.
+    start_date: String,
+    end_date: String,
+    /// Set on every day but the first/last of an event spanning several days.
+    continues_from_previous_day: bool,
+    continues_to_next_day: bool,
+    /// The zone the event was created in, as reported by Google.
+    time_zone: Option<String>,
+    /// The event's start/end in each of `settings.displayTimezones`, plus its
+    /// own zone when that differs from `settings.TZ`.
+    zones: Vec<ZonedTime>,
+    /// Marked as "free" in Google Calendar; does not count as busy time.
+    transparent: bool,
+    /// Positions in the day's `timed_events` of the other events that
+    /// overlap this one.
+    overlaps_with: Vec<usize>,
+    #[serde(skip)]
+    start_at: Option<DateTime<Tz>>,
+    #[serde(skip)]
+    end_at: Option<DateTime<Tz>>,
+}
+
+/// Two timed events that overlap (a double booking).
+#[derive(Debug, Clone, Serialize)]
+struct Conflict {
+    events: Vec<String>,
+    start: String,
+    end: String,
+}
+
+/// Timed events that follow each other without a break.
+#[derive(Debug, Clone, Serialize)]
+struct BackToBack {
+    events: Vec<String>,
+    start: String,
+    end: String,
+    minutes: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ZonedTime {
+    tz: String,
+    start: String,
+    end: String,
+}
+
+/// Slots where everyone asked about is free.
+#[derive(Serialize, Debug)]
+pub struct CommonFreeTime {
+    pub free_slots: Vec<FreeSlot>,
+    /// Calendars whose availability could not be read and were ignored.
+    pub unavailable: Vec<String>,
+}
+
+/// Everything a template can refer to.
+#[derive(Serialize, Debug)]
+pub struct Agenda {
+    pub days: Vec<Day>,
+    pub free_slots: Vec<FreeSlot>,
+    pub stats: Stats,
 }
 
 #[derive(Serialize, Debug)]
 pub struct Day {
     date: String,
+    /// English weekday name, e.g. `Monday`.
+    weekday: String,
+    iso_week: u32,
+    /// True when the day is not one of `settings.workdays`.
+    is_weekend: bool,
     all_day_events: Vec<Event>,
     timed_events: Vec<Event>,
+    conflicts: Vec<Conflict>,
+    back_to_back: Vec<BackToBack>,
+    /// Minutes covered by busy timed events, counting overlaps once.
+    meeting_minutes: i64,
+    /// True when `meeting_minutes` exceeds `settings.meetingBudget`.
+    over_budget: bool,
+}
+
+/// How fetched events are laid out into days.
+struct DayLayout {
+    tz: Tz,
+    display_timezones: Vec<Tz>,
+    workdays: Vec<Weekday>,
+    include_empty_days: bool,
+    meeting_budget_minutes: Option<i64>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum GetEventDuration {
     Today,
+    Tomorrow,
     ThisWeek,
-    ThisMonth,
     NextWeek,
+    LastWeek,
+    ThisWorkweek,
+    ThisMonth,
+    NextMonth,
+    LastMonth,
+    NextNDays(u32),
+    ThisQuarter,
+    RestOfToday,
+    NextHours(u32),
 }
 
 impl Cal2Prompt {
     pub fn new() -> anyhow::Result<Self> {
         match config::init() {
-            Ok(config) => Ok(Self {
-                config,
-                token: None,
-            }),
+            Ok(config) => Ok(Self::from_config(Arc::new(config))),
             Err(e) => Err(e),
         }
     }
 
-    pub async fn oauth(&mut self) -> anyhow::Result<()> {
+    pub(crate) fn from_config(config: Arc<Config>) -> Self {
+        Self {
+            write_guard: Arc::new(WriteGuard::new(config.experimental.mcp.write.clone())),
+            config,
+            token: Mutex::new(None),
+        }
+    }
+
+    /// A `Cal2Prompt` for another MCP session. It loads its own token but
+    /// shares this one's write limits, so a client cannot get a fresh
+    /// `maxEventsPerSession` by reconnecting.
+    fn for_session(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            token: Mutex::new(None),
+            write_guard: self.write_guard.clone(),
+        }
+    }
+
+    pub async fn oauth(&self) -> anyhow::Result<()> {
+        let mut token = self.token.lock().await;
+        *token = Some(self.load_or_refresh_token().await?);
+
+        Ok(())
+    }
+
+    /// Runs the OAuth flow when there is no token yet, and refreshes one
+    /// that is about to expire.
+    pub async fn ensure_valid_token(&self) -> anyhow::Result<()> {
+        let mut token = self.token.lock().await;
+        let valid = token.as_ref().is_some_and(|token| {
+            !token.expires_within(self.config.settings.token_refresh_leeway_seconds)
+        });
+        if !valid {
+            *token = Some(self.load_or_refresh_token().await?);
+        }
+        Ok(())
+    }
+
+    /// Reads the stored token and refreshes it (or runs the OAuth flow) when it
+    /// is about to expire.
+    ///
+    /// The read-refresh-write cycle runs under an exclusive lock on the token
+    /// file, so a CLI invocation and a running MCP server never refresh at the
+    /// same time. The file is read only after the lock is acquired, so a token
+    /// refreshed by the other process while we were waiting is reused. The lock
+    /// is released during the interactive OAuth flow, which would otherwise
+    /// block the other process for as long as the browser is left open.
+    async fn load_or_refresh_token(&self) -> anyhow::Result<Token> {
+        // Service account tokens are cheap to mint and never stored on disk.
+        if let Some(service_account) = &self.config.source.google.service_account {
+            let client = ServiceAccountClient::from_key_file(
+                Path::new(&service_account.key_file),
+                self.config.source.google.oauth2.scopes.clone(),
+                service_account.subject.clone(),
+            )?;
+            return client.fetch_token().await;
+        }
+
+        let store = self.token_store();
         let oauth2_client = OAuth2Client::new(
             &self.config.source.google.oauth2.client_id,
             &self.config.source.google.oauth2.client_secret,
             &self.config.source.google.oauth2.redirect_url,
         );
 
-        let token = match fs::read_to_string(&self.config.settings.oauth_file_path) {
-            Ok(content) => {
-                let stored = serde_json::from_str::<Token>(&content)?;
-
-                if stored.is_expired() {
-                    if let Some(ref refresh) = stored.refresh_token {
-                        let refreshed = oauth2_client.refresh_token(refresh.clone()).await?;
-                        self.save_token(&refreshed).await?;
-                        refreshed
-                    } else {
-                        match oauth2_client.oauth_flow().await {
-                            Ok(token) => {
-                                self.save_token(&token).await?;
-                                token
-                            }
-                            Err(e) => {
-                                if let Some(OAuth2Error::PortInUse) =
-                                    e.downcast_ref::<OAuth2Error>()
-                                {
-                                    return Err(Cal2PromptError::OAuth2PortInUse(
-                                        OAuth2Error::PortInUse,
-                                    )
-                                    .into());
-                                }
-                                return Err(e);
-                            }
-                        }
-                    }
-                } else {
-                    stored
-                }
+        let lock = store.lock().await?;
+        match store.load()? {
+            Some(stored)
+                if !stored.expires_within(self.config.settings.token_refresh_leeway_seconds) =>
+            {
+                return Ok(stored);
             }
-            Err(_) => match oauth2_client.oauth_flow().await {
-                Ok(new_token) => {
-                    self.save_token(&new_token).await?;
-                    new_token
-                }
-                Err(e) => {
-                    if let Some(OAuth2Error::PortInUse) = e.downcast_ref::<OAuth2Error>() {
-                        return Err(Cal2PromptError::OAuth2PortInUse(OAuth2Error::PortInUse).into());
-                    }
-                    return Err(e);
+            Some(Token {
+                refresh_token: Some(refresh_token),
+                ..
+            }) => {
+                let refreshed = oauth2_client.refresh_token(refresh_token.clone()).await?;
+                // Google only sometimes rotates the refresh token; keep the
+                // current one when the response does not carry a new one.
+                let token = Token {
+                    refresh_token: refreshed.refresh_token.or(Some(refresh_token)),
+                    ..refreshed
+                };
+                store.save(&token)?;
+                return Ok(token);
+            }
+            _ => {}
+        }
+        drop(lock);
+
+        let token = match oauth2_client.oauth_flow().await {
+            Ok(new_token) => new_token,
+            Err(e) => {
+                if let Some(OAuth2Error::PortInUse(port)) = e.downcast_ref::<OAuth2Error>() {
+                    return Err(
+                        Cal2PromptError::OAuth2PortInUse(OAuth2Error::PortInUse(*port)).into(),
+                    );
                 }
-            },
+                return Err(e);
+            }
         };
 
-        self.token = Some(token);
+        let _lock = store.lock().await?;
+        store.save(&token)?;
+        Ok(token)
+    }
 
-        Ok(())
+    pub async fn launch_mcp(&self) -> anyhow::Result<()> {
+        let (transport, _sender) = StdioTransport::new();
+        let handler = McpHandler::new(self);
+        eprintln!("MCP stdio transport server started. Waiting for JSON messages on stdin...");
+        handler.launch_mcp(&transport).await
     }
 
-    pub async fn ensure_valid_token(&mut self) -> anyhow::Result<()> {
-        if let Some(token) = &self.token {
-            if token.is_expired() {
-                let oauth2_client = OAuth2Client::new(
-                    &self.config.source.google.oauth2.client_id,
-                    &self.config.source.google.oauth2.client_secret,
-                    &self.config.source.google.oauth2.redirect_url,
+    /// Serves MCP over Streamable HTTP on `addr`. Write limits are counted
+    /// across all sessions, and the token file is shared between them.
+    ///
+    /// Refuses to listen beyond loopback without
+    /// `experimental.mcp.http.bearerToken` unless `allow_unauthenticated`.
+    pub async fn launch_mcp_http(
+        &self,
+        addr: SocketAddr,
+        allow_unauthenticated: bool,
+    ) -> anyhow::Result<()> {
+        let policy = self.config.experimental.mcp.http.clone();
+        if policy.bearer_token.is_none() && !addr.ip().is_loopback() {
+            if !allow_unauthenticated {
+                anyhow::bail!(
+                    "Refusing to listen on {} without experimental.mcp.http.bearerToken, as anyone who can reach it could read and change your calendars. Set a token, or pass --allow-unauthenticated.",
+                    addr
                 );
-
-                if let Some(ref refresh_token) = token.refresh_token {
-                    let refreshed = oauth2_client.refresh_token(refresh_token.clone()).await?;
-                    self.save_token(&refreshed).await?;
-                    self.token = Some(refreshed);
-                } else {
-                    match oauth2_client.oauth_flow().await {
-                        Ok(new_token) => {
-                            self.save_token(&new_token).await?;
-                            self.token = Some(new_token);
-                        }
-                        Err(e) => {
-                            if let Some(OAuth2Error::PortInUse) = e.downcast_ref::<OAuth2Error>() {
-                                return Err(Cal2PromptError::OAuth2PortInUse(
-                                    OAuth2Error::PortInUse,
-                                )
-                                .into());
-                            }
-                            return Err(e);
-                        }
-                    }
-                }
             }
+            eprintln!(
+                "Warning: listening on {} without experimental.mcp.http.bearerToken; anyone who can reach it can read your calendars.",
+                addr
+            );
         }
-        Ok(())
+
+        let listener = TcpListener::bind(addr).await?;
+        eprintln!(
+            "MCP HTTP server started on http://{}/mcp",
+            listener.local_addr()?
+        );
+
+        let shared = self.for_session();
+        http::serve(listener, policy, move |transport| {
+            let cal2prompt = shared.for_session();
+            tokio::spawn(async move {
+                let handler = McpHandler::new(&cal2prompt);
+                if let Err(err) = handler.launch_mcp(&transport).await {
+                    eprintln!("[SERVER] Session ended with an error: {:?}", err);
+                }
+            })
+        })
+        .await
     }
 
-    pub async fn launch_mcp(&mut self) -> anyhow::Result<()> {
-        let (transport, _sender) = StdioTransport::new();
-        let mut handler = McpHandler::new(self);
-        handler.launch_mcp(&transport).await
+    pub async fn insert_event(&self, event: NewEvent) -> anyhow::Result<WriteOutcome> {
+        let service = self.calendar_service().await;
+        let mutation = service.plan_insert(event)?;
+        self.apply(service, mutation).await
     }
 
-    pub async fn insert_event(
+    pub async fn update_event(
         &self,
-        summary: &str,
-        description: Option<String>,
-        start: &str,
-        end: &str,
-    ) -> anyhow::Result<CreatedEventResponse> {
-        let calendar_service = GoogleCalendarService::new(
-            self.config.clone(),
-            self.token
-                .as_ref()
-                .expect("token not set")
-                .access_token
-                .clone(),
-        );
+        calendar_id: Option<&str>,
+        event_id: &str,
+        changes: EventChanges,
+    ) -> anyhow::Result<WriteOutcome> {
+        let service = self.calendar_service().await;
+        let mutation = service.plan_update(calendar_id, event_id, changes).await?;
+        self.apply(service, mutation).await
+    }
 
-        calendar_service
-            .create_calendar_event(summary, description, start, end)
-            .await
+    pub async fn move_event(
+        &self,
+        calendar_id: Option<&str>,
+        event_id: &str,
+        start: Option<&str>,
+        destination: Option<&str>,
+    ) -> anyhow::Result<WriteOutcome> {
+        let service = self.calendar_service().await;
+        let mutation = service
+            .plan_move(calendar_id, event_id, start, destination)
+            .await?;
+        self.apply(service, mutation).await
     }
 
-    pub async fn fetch_days(&self, since: &str, until: &str) -> anyhow::Result<Vec<Day>> {
-        let tz: Tz =
-            self.config.settings.tz.parse().unwrap_or_else(|_| {
-                panic!("Invalid time zone string '{}'", self.config.settings.tz)
-            });
+    pub async fn delete_event(
+        &self,
+        calendar_id: Option<&str>,
+        event_id: &str,
+    ) -> anyhow::Result<WriteOutcome> {
+        let service = self.calendar_service().await;
+        let mutation = service.plan_delete(calendar_id, event_id).await?;
+        self.apply(service, mutation).await
+    }
 
-        let since_naive_date = NaiveDate::parse_from_str(since, "%Y-%m-%d")?
-            .and_hms_opt(0, 0, 0)
-            .unwrap();
-        let until_naive_date = NaiveDate::parse_from_str(until, "%Y-%m-%d")?
-            .and_hms_opt(0, 0, 0)
-            .unwrap();
-        let since_with_tz = tz.from_local_datetime(&since_naive_date).unwrap();
-        let until_with_tz = tz.from_local_datetime(&until_naive_date).unwrap();
+    /// Checks `mutation` against `experimental.mcp.write`, then sends it (or
+    /// only describes it in dry-run mode) and appends it to the audit log.
+    ///
+    /// Once the checks pass the write runs on its own task, so cancelling the
+    /// MCP request cannot stop it between sending the change and recording it.
+    async fn apply(
+        &self,
+        service: GoogleCalendarService,
+        mutation: Mutation,
+    ) -> anyhow::Result<WriteOutcome> {
+        let requests = service.preview(&mutation)?;
+        self.write_guard.check(&mutation, &self.tz())?;
+        if self.write_guard.dry_run() {
+            self.write_guard.release(&mutation);
+            return Ok(WriteOutcome::DryRun {
+                dry_run: true,
+                requests,
+            });
+        }
 
-        let calendar_service = GoogleCalendarService::new(
-            self.config.clone(),
-            self.token
-                .as_ref()
-                .expect("token not set")
-                .access_token
-                .clone(),
-        );
+        let write_guard = self.write_guard.clone();
+        tokio::spawn(async move { commit(&service, &write_guard, mutation, requests).await })
+            .await?
+    }
 
-        let all_events = calendar_service.get_calendar_events(since, until).await?;
+    /// Fetches the events between `since` and `until` and groups them by day.
+    ///
+    /// Both accept `YYYY-MM-DD`, `YYYY-MM-DD HH:MM` or RFC3339. A bare `until`
+    /// date is inclusive; with a time the window ends exactly there.
+    ///
+    /// `progress` is told as each calendar has been read.
+    pub async fn fetch_days(
+        &self,
+        since: &str,
+        until: &str,
+        progress: Option<&FetchProgress>,
+    ) -> anyhow::Result<Vec<Day>> {
+        let (since_with_tz, until_with_tz, all_events) = self
+            .fetch_events(self.calendar_ids(), since, until, progress)
+            .await?;
+        let tz = since_with_tz.timezone();
 
         Ok(Self::group_events_into_days(
             all_events,
             since_with_tz,
             until_with_tz,
-            tz,
+            &self.day_layout(tz),
         ))
     }
 
+    /// Like [`Cal2Prompt::fetch_days`], plus the free slots of at least
+    /// `settings.minFreeSlot` within working hours.
+    pub async fn fetch_agenda(&self, since: &str, until: &str) -> anyhow::Result<Agenda> {
+        self.fetch_agenda_from(self.calendar_ids(), since, until, None)
+            .await
+    }
+
+    /// Like [`Cal2Prompt::fetch_agenda`], limited to `calendar_ids` and
+    /// reporting to `progress` as each of them has been read.
+    pub async fn fetch_agenda_from(
+        &self,
+        calendar_ids: &[String],
+        since: &str,
+        until: &str,
+        progress: Option<&FetchProgress>,
+    ) -> anyhow::Result<Agenda> {
+        let (since_with_tz, until_with_tz, all_events) = self
+            .fetch_events(calendar_ids, since, until, progress)
+            .await?;
+        let tz = since_with_tz.timezone();
+
+        let free_slots = free_slots(
+            &busy_blocks(&all_events, &tz),
+            since_with_tz,
+            until_with_tz,
+            &self.config.settings.working_hours,
+            Duration::minutes(self.config.settings.min_free_slot_minutes),
+        );
+        let stats = stats::compute(&all_events, since_with_tz, until_with_tz, &free_slots);
+        let days = Self::group_events_into_days(
+            all_events,
+            since_with_tz,
+            until_with_tz,
+            &self.day_layout(tz),
+        );
+
+        Ok(Agenda {
+            days,
+            free_slots,
+            stats,
+        })
+    }
+
+    /// Aggregate statistics over the events between `since` and `until`.
+    pub async fn fetch_stats(&self, since: &str, until: &str) -> anyhow::Result<Stats> {
+        Ok(self.fetch_agenda(since, until).await?.stats)
+    }
+
+    /// Finds free slots of at least `min` (default `settings.minFreeSlot`)
+    /// within working hours between `since` and `until`.
+    pub async fn find_free_slots(
+        &self,
+        since: &str,
+        until: &str,
+        min: Option<Duration>,
+    ) -> anyhow::Result<Vec<FreeSlot>> {
+        let (since_with_tz, until_with_tz, all_events) = self
+            .fetch_events(self.calendar_ids(), since, until, None)
+            .await?;
+        let tz = since_with_tz.timezone();
+
+        Ok(free_slots(
+            &busy_blocks(&all_events, &tz),
+            since_with_tz,
+            until_with_tz,
+            &self.config.settings.working_hours,
+            min.unwrap_or_else(|| Duration::minutes(self.config.settings.min_free_slot_minutes)),
+        ))
+    }
+
+    /// Queries the busy periods of other people's calendars (by email or
+    /// calendar ID) between `since` and `until`.
+    pub async fn get_free_busy(
+        &self,
+        ids: &[String],
+        since: &str,
+        until: &str,
+    ) -> anyhow::Result<Vec<Availability>> {
+        let tz = self.tz();
+        let since_with_tz = parse_bound(since, &tz, Bound::Start)?;
+        let until_with_tz = parse_bound(until, &tz, Bound::End)?;
+
+        let response = self
+            .calendar_service()
+            .await
+            .get_free_busy(ids, since_with_tz, until_with_tz)
+            .await?;
+
+        Ok(availability(ids, &response, &tz))
+    }
+
+    /// Finds slots within working hours where everyone in `ids` is free.
+    /// With `include_self`, the configured calendars are taken into account
+    /// as well.
+    pub async fn find_common_free_time(
+        &self,
+        ids: &[String],
+        since: &str,
+        until: &str,
+        min: Option<Duration>,
+        include_self: bool,
+    ) -> anyhow::Result<CommonFreeTime> {
+        let tz = self.tz();
+        let since_with_tz = parse_bound(since, &tz, Bound::Start)?;
+        let until_with_tz = parse_bound(until, &tz, Bound::End)?;
+
+        let mut query_ids = ids.to_vec();
+        if include_self {
+            for calendar_id in &self.config.source.google.calendar.get_events.calendar_ids {
+                if !query_ids.contains(calendar_id) {
+                    query_ids.push(calendar_id.clone());
+                }
+            }
+        }
+
+        let response = self
+            .calendar_service()
+            .await
+            .get_free_busy(&query_ids, since_with_tz, until_with_tz)
+            .await?;
+
+        let unavailable = availability(&query_ids, &response, &tz)
+            .into_iter()
+            .filter(|availability| !availability.errors.is_empty())
+            .map(|availability| availability.id)
+            .collect();
+        let free_slots = free_slots(
+            &free_busy_blocks(&response, &tz),
+            since_with_tz,
+            until_with_tz,
+            &self.config.settings.working_hours,
+            min.unwrap_or_else(|| Duration::minutes(self.config.settings.min_free_slot_minutes)),
+        );
+
+        Ok(CommonFreeTime {
+            free_slots,
+            unavailable,
+        })
+    }
+
+    async fn fetch_events(
+        &self,
+        calendar_ids: &[String],
+        since: &str,
+        until: &str,
+        progress: Option<&FetchProgress>,
+    ) -> anyhow::Result<(DateTime<Tz>, DateTime<Tz>, Vec<EventItem>)> {
+        let tz = self.tz();
+
+        let since_with_tz = parse_bound(since, &tz, Bound::Start)?;
+        let until_with_tz = parse_bound(until, &tz, Bound::End)?;
+
+        let calendar_service = self.calendar_service().await;
+
+        let all_events = calendar_service
+            .get_calendar_events(calendar_ids, since_with_tz, until_with_tz, progress)
+            .await?;
+
+        Ok((since_with_tz, until_with_tz, all_events))
+    }
+
+    /// Groups events into days within `[since_with_tz, until_with_tz)`.
+    /// Timed events that do not overlap the window are dropped.
     fn group_events_into_days(
         mut all_events: Vec<EventItem>,
         since_with_tz: DateTime<Tz>,
         until_with_tz: DateTime<Tz>,
-        tz: Tz,
+        layout: &DayLayout,
     ) -> Vec<Day> {
         all_events.sort_by_key(|e| e.start_time_utc());
+        let tz = layout.tz;
+        let display_timezones = &layout.display_timezones;
+
+        let first_day = since_with_tz.date_naive();
+        // The window end is exclusive, so a window ending at midnight does not
+        // include that day.
+        let last_day = (until_with_tz - Duration::nanoseconds(1))
+            .date_naive()
+            .max(first_day);
 
-        let mut grouped: BTreeMap<String, (Vec<Event>, Vec<Event>)> = BTreeMap::new();
+        let mut grouped: BTreeMap<NaiveDate, (Vec<Event>, Vec<Event>)> = BTreeMap::new();
+        if layout.include_empty_days {
+            for day in intersection_days(first_day, last_day, first_day, last_day) {
+                grouped.entry(day).or_default();
+            }
+        }
 
         for ev_item in &all_events {
             let is_all_day = ev_item.is_all_day();
@@ -261,19 +642,17 @@ impl Cal2Prompt {
                 let all_day_end_day =
                     NaiveDate::parse_from_str(&all_day_end_day, "%Y-%m-%d").unwrap();
 
-                let duration = intersection_days(
-                    all_day_start_day,
-                    all_day_end_day,
-                    since_with_tz.date_naive(),
-                    until_with_tz.date_naive(),
-                );
+                // Google's end date is exclusive.
+                let all_day_last_day = all_day_end_day.pred_opt().unwrap().max(all_day_start_day);
+                let duration =
+                    intersection_days(all_day_start_day, all_day_last_day, first_day, last_day);
 
                 for day in duration {
-                    let entry = grouped
-                        .entry(day.to_string())
-                        .or_insert_with(|| (vec![], vec![]));
+                    let entry = grouped.entry(day).or_default();
 
                     let event = Event {
+                        id: ev_item.id.clone(),
+                        calendar_id: ev_item.calendar_id.clone(),
                         summary: ev_item
                             .summary
                             .clone()
@@ -285,6 +664,16 @@ impl Cal2Prompt {
                         attendees: attendees_emails.clone(),
                         html_link: ev_item.html_link.clone(),
                         all_day: true,
+                        start_date: all_day_start_day.to_string(),
+                        end_date: all_day_end_day.to_string(),
+                        continues_from_previous_day: day > all_day_start_day,
+                        continues_to_next_day: day < all_day_last_day,
+                        time_zone: None,
+                        zones: vec![],
+                        transparent: ev_item.is_transparent(),
+                        overlaps_with: vec![],
+                        start_at: None,
+                        end_at: None,
                     };
 
                     entry.0.push(event);
@@ -293,93 +682,394 @@ impl Cal2Prompt {
                 let start_utc_opt = ev_item.start_time_utc().unwrap();
                 let end_utc_opt = ev_item.end_time_utc().unwrap();
 
-                let date_key = start_utc_opt
-                    .with_timezone(&tz)
+                if end_utc_opt <= since_with_tz.to_utc() || start_utc_opt >= until_with_tz.to_utc()
+                {
+                    continue;
+                }
+
+                let start_local = start_utc_opt.with_timezone(&tz);
+                let end_local = end_utc_opt.with_timezone(&tz);
+                let start_day = start_local.date_naive();
+                // An event ending exactly at midnight does not touch the next day.
+                let end_day = (end_local - Duration::nanoseconds(1))
                     .date_naive()
-                    .format("%Y-%m-%d")
-                    .to_string();
-                let start_local_str = start_utc_opt
-                    .with_timezone(&tz)
-                    .naive_local()
-                    .format("%H:%M")
-                    .to_string();
-                let end_local_str = end_utc_opt
-                    .with_timezone(&tz)
-                    .naive_local()
-                    .format("%H:%M")
-                    .to_string();
+                    .max(start_day);
+
+                let time_zone = ev_item
+                    .start
+                    .as_ref()
+                    .and_then(|start| start.time_zone.clone());
+                let event_tz = time_zone
+                    .as_deref()
+                    .and_then(|name| name.parse::<Tz>().ok())
+                    .filter(|event_tz| *event_tz != tz && !display_timezones.contains(event_tz));
+                let zones = display_timezones
+                    .iter()
+                    .chain(event_tz.iter())
+                    .map(|zone| ZonedTime {
+                        tz: zone.name().to_string(),
+                        start: start_utc_opt
+                            .with_timezone(zone)
+                            .format("%Y-%m-%d %H:%M")
+                            .to_string(),
+                        end: end_utc_opt
+                            .with_timezone(zone)
+                            .format("%Y-%m-%d %H:%M")
+                            .to_string(),
+                    })
+                    .collect();
 
                 let event = Event {
+                    id: ev_item.id.clone(),
+                    calendar_id: ev_item.calendar_id.clone(),
                     summary: ev_item
                         .summary
                         .clone()
                         .unwrap_or_else(|| "(no summary)".to_string()),
-                    start: start_local_str,
-                    end: end_local_str,
+                    start: start_local.format("%H:%M").to_string(),
+                    end: end_local.format("%H:%M").to_string(),
                     location: ev_item.location.clone(),
                     description: ev_item.description.clone(),
                     attendees: attendees_emails,
                     html_link: ev_item.html_link.clone(),
                     all_day: false,
+                    start_date: start_day.to_string(),
+                    end_date: end_local.date_naive().to_string(),
+                    continues_from_previous_day: false,
+                    continues_to_next_day: false,
+                    time_zone,
+                    zones,
+                    transparent: ev_item.is_transparent(),
+                    overlaps_with: vec![],
+                    start_at: Some(start_local),
+                    end_at: Some(end_local),
                 };
 
-                let entry = grouped.entry(date_key).or_insert_with(|| (vec![], vec![]));
-                entry.1.push(event);
+                for day in intersection_days(start_day, end_day, first_day, last_day) {
+                    let entry = grouped.entry(day).or_default();
+                    entry.1.push(Event {
+                        continues_from_previous_day: day > start_day,
+                        continues_to_next_day: day < end_day,
+                        ..event.clone()
+                    });
+                }
             }
         }
 
         let mut days = Vec::new();
         for (date, (all_day_events, timed_events)) in grouped {
-            days.push(Day {
-                date,
+            let mut day = Day {
+                date: date.to_string(),
+                weekday: date.format("%A").to_string(),
+                iso_week: date.iso_week().week(),
+                is_weekend: !layout.workdays.contains(&date.weekday()),
                 all_day_events,
                 timed_events,
-            });
+                conflicts: vec![],
+                back_to_back: vec![],
+                meeting_minutes: 0,
+                over_budget: false,
+            };
+            // Only the last representable date has no following midnight to
+            // measure the day against; it is left unannotated.
+            let _ = Self::annotate_day(&mut day, date, layout);
+            days.push(day);
         }
         days
     }
 
+    /// Marks double bookings, back-to-back runs and the meeting load of a day.
+    /// Events marked as free are ignored. Returns `None` when `date` has no
+    /// following day.
+    fn annotate_day(day: &mut Day, date: NaiveDate, layout: &DayLayout) -> Option<()> {
+        let day_block = TimeBlock {
+            start: start_of_day(date, &layout.tz),
+            end: start_of_day(date.succ_opt()?, &layout.tz),
+        };
+
+        let (indices, blocks): (Vec<usize>, Vec<TimeBlock>) = day
+            .timed_events
+            .iter()
+            .enumerate()
+            .filter(|(_, ev)| !ev.transparent)
+            .filter_map(|(i, ev)| {
+                Some((
+                    i,
+                    TimeBlock {
+                        start: ev.start_at?,
+                        end: ev.end_at?,
+                    },
+                ))
+            })
+            .unzip();
+
+        let clipped_time = |at: DateTime<Tz>| {
+            at.clamp(day_block.start, day_block.end)
+                .format("%H:%M")
+                .to_string()
+        };
+
+        for (a, b) in overlapping_pairs(&blocks) {
+            let (a, b, a_block, b_block) = (indices[a], indices[b], &blocks[a], &blocks[b]);
+            day.timed_events[a].overlaps_with.push(b);
+            day.timed_events[b].overlaps_with.push(a);
+            day.conflicts.push(Conflict {
+                events: vec![
+                    day.timed_events[a].summary.clone(),
+                    day.timed_events[b].summary.clone(),
+                ],
+                start: clipped_time(a_block.start.max(b_block.start)),
+                end: clipped_time(a_block.end.min(b_block.end)),
+            });
+        }
+
+        for chain in back_to_back_chains(&blocks) {
+            let first = &blocks[chain[0]];
+            let last = &blocks[chain[chain.len() - 1]];
+            day.back_to_back.push(BackToBack {
+                events: chain
+                    .iter()
+                    .map(|&i| day.timed_events[indices[i]].summary.clone())
+                    .collect(),
+                start: clipped_time(first.start),
+                end: clipped_time(last.end),
+                minutes: (last.end - first.start).num_minutes(),
+            });
+        }
+
+        day.meeting_minutes = covered_minutes(&blocks, &day_block);
+        day.over_budget = layout
+            .meeting_budget_minutes
+            .is_some_and(|budget| day.meeting_minutes > budget);
+        Some(())
+    }
+
     pub async fn get_events_duration(self, since: String, until: String) -> anyhow::Result<String> {
-        let days = self.fetch_days(&since, &until).await?;
-        generate(&self.config.output.template, days)
+        let agenda = self.fetch_agenda(&since, &until).await?;
+        self.render(&agenda)
+    }
+
+    /// Renders `agenda` with `output.template`.
+    pub fn render(&self, agenda: &Agenda) -> anyhow::Result<String> {
+        generate(&self.config.output.template, agenda)
     }
 
     pub async fn get_events_short_cut(
         self,
         get_event_duration: GetEventDuration,
     ) -> anyhow::Result<String> {
-        let tz: Tz =
-            self.config.settings.tz.parse().unwrap_or_else(|_| {
-                panic!("Invalid time zone string '{}'", self.config.settings.tz)
-            });
+        let (since, until) = self.shortcut_range(&get_event_duration)?;
+        self.get_events_duration(since, until).await
+    }
+
+    pub async fn get_events_expression(self, expr: &str) -> anyhow::Result<String> {
+        let (since, until) = self.expression_range(expr)?;
+        self.get_events_duration(since, until).await
+    }
+
+    /// Lists the free slots between `since` and `until`, one per line.
+    pub async fn get_free_slots(
+        self,
+        since: String,
+        until: String,
+        min: Option<Duration>,
+    ) -> anyhow::Result<String> {
+        let slots = self.find_free_slots(&since, &until, min).await?;
+        Ok(format_free_slots(&slots))
+    }
 
-        let calculator = EventDurationCalculator::new(RealClock);
-        let (since_with_tz, until_with_tz) = calculator.get_duration(&tz, get_event_duration);
+    /// The `since`/`until` pair covered by a shortcut such as `--next-week`.
+    pub fn shortcut_range(
+        &self,
+        get_event_duration: &GetEventDuration,
+    ) -> anyhow::Result<(String, String)> {
+        let tz = self.tz();
 
-        let since = since_with_tz.format("%Y-%m-%d").to_string();
-        let until = until_with_tz.format("%Y-%m-%d").to_string();
+        let calculator = EventDurationCalculator::new(RealClock)
+            .with_week_start(self.config.settings.week_start)
+            .with_workdays(self.config.settings.workdays.clone());
+        let (since_with_tz, until_with_tz) = calculator.get_duration(&tz, get_event_duration)?;
 
-        let days = self.fetch_days(&since, &until).await?;
-        generate(&self.config.output.template, days)
+        // Sub-day shortcuts start (and possibly end) at the current time
+        // instead of covering whole days.
+        let since = match get_event_duration {
+            GetEventDuration::RestOfToday | GetEventDuration::NextHours(_) => {
+                since_with_tz.to_rfc3339()
+            }
+            _ => since_with_tz.format("%Y-%m-%d").to_string(),
+        };
+        let until = match get_event_duration {
+            GetEventDuration::NextHours(_) => until_with_tz.to_rfc3339(),
+            _ => until_with_tz.format("%Y-%m-%d").to_string(),
+        };
+        Ok((since, until))
     }
 
-    async fn save_token(&self, token: &Token) -> anyhow::Result<()> {
-        let text = serde_json::to_string_pretty(&token)?;
-        fs::create_dir_all(
-            Path::new(&self.config.settings.oauth_file_path)
-                .parent()
-                .expect("Failed to get token dir"),
-        )?;
-        fs::write(&self.config.settings.oauth_file_path, text)?;
-        Ok(())
+    /// The `since`/`until` pair described by a `--range` expression.
+    pub fn expression_range(&self, expr: &str) -> anyhow::Result<(String, String)> {
+        let tz = self.tz();
+
+        let parser =
+            DateRangeParser::new(RealClock).with_week_start(self.config.settings.week_start);
+        let (since, until) = parser.parse(&tz, expr)?;
+        Ok((since.to_string(), until.to_string()))
+    }
+
+    /// The calendars configured in `source.google.calendar.getEvents.calendarIDs`.
+    pub fn calendar_ids(&self) -> &[String] {
+        &self.config.source.google.calendar.get_events.calendar_ids
+    }
+
+    pub fn output(&self) -> &config::Output {
+        &self.config.output
+    }
+
+    pub fn resource_poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.config.experimental.mcp.resources.poll_interval_seconds)
+    }
+
+    /// Overrides `settings.TZ` for this run, e.g. from `--tz`.
+    pub fn set_tz(&mut self, tz: &str) {
+        Arc::make_mut(&mut self.config).settings.tz = tz.to_string();
+    }
+
+    fn tz(&self) -> Tz {
+        self.config
+            .settings
+            .tz
+            .parse()
+            .unwrap_or_else(|_| panic!("Invalid time zone string '{}'", self.config.settings.tz))
+    }
+
+    async fn calendar_service(&self) -> GoogleCalendarService {
+        GoogleCalendarService::new(
+            self.config.clone(),
+            self.token
+                .lock()
+                .await
+                .as_ref()
+                .expect("token not set")
+                .access_token
+                .clone(),
+        )
+    }
+
+    fn day_layout(&self, tz: Tz) -> DayLayout {
+        DayLayout {
+            tz,
+            // Validated when the config is loaded.
+            display_timezones: self
+                .config
+                .settings
+                .display_timezones
+                .iter()
+                .filter_map(|name| name.parse().ok())
+                .collect(),
+            workdays: self.config.settings.workdays.clone(),
+            include_empty_days: self.config.output.include_empty_days,
+            meeting_budget_minutes: self.config.settings.meeting_budget_minutes,
+        }
+    }
+
+    fn token_store(&self) -> TokenStore {
+        TokenStore::new(
+            &self.config.settings.oauth_file_path,
+            TokenKeySource::resolve(self.config.settings.token_key_command.as_deref()),
+        )
     }
 }
 
+/// Sends a checked `mutation` and writes the audit entry, whether or not
+/// the write succeeded.
+async fn commit(
+    service: &GoogleCalendarService,
+    write_guard: &WriteGuard,
+    mutation: Mutation,
+    requests: Vec<PlannedRequest>,
+) -> anyhow::Result<WriteOutcome> {
+    let executed = service.execute(&mutation).await;
+    if executed.is_err() {
+        write_guard.release(&mutation);
+    }
+
+    let event_id = match &executed {
+        Ok(Some(res)) => Some(res.id.as_str()),
+        _ => mutation.event_id(),
+    };
+    let after = match (&executed, &mutation) {
+        (Ok(None), _) => None,
+        (
+            Ok(Some(res)),
+            Mutation::Move {
+                destination: Some(destination),
+                ..
+            },
+        ) => service.get_event(destination, &res.id).await.ok(),
+        (Ok(Some(res)), _) => service
+            .get_event(mutation.calendar_id(), &res.id)
+            .await
+            .ok(),
+        // A failed insert left nothing behind; anything else may have been
+        // changed in part.
+        (Err(_), Mutation::Insert { .. }) => None,
+        (Err(_), _) => match mutation.event_id() {
+            Some(event_id) => service
+                .get_event(mutation.calendar_id(), event_id)
+                .await
+                .ok(),
+            None => None,
+        },
+    };
+
+    // Whatever happened to the calendar has happened, so a failure to record
+    // it is reported but does not change the tool's result.
+    if let Err(err) = write_guard.audit(&AuditEntry {
+        timestamp: Utc::now().to_rfc3339(),
+        tool: mutation.tool().tool_name(),
+        calendar_id: mutation.calendar_id(),
+        event_id,
+        requests: &requests,
+        before: mutation.current(),
+        after: after.as_ref(),
+        error: executed.as_ref().err().map(|err| format!("{:#}", err)),
+    }) {
+        eprintln!("Failed to write the audit log: {}", err);
+    }
+
+    let res = executed?;
+    Ok(match res {
+        Some(res) => WriteOutcome::Written(res),
+        None => WriteOutcome::Deleted {
+            id: mutation.event_id().unwrap_or_default().to_string(),
+            deleted: true,
+        },
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::templates::google::STANDARD;
     use crate::google::calendar::model::CalendarEventsResponse;
+    use crate::mcp::schema::assert_matches_output_schema;
+
+    impl DayLayout {
+        fn new(tz: Tz) -> Self {
+            Self {
+                tz,
+                display_timezones: vec![],
+                workdays: vec![
+                    Weekday::Mon,
+                    Weekday::Tue,
+                    Weekday::Wed,
+                    Weekday::Thu,
+                    Weekday::Fri,
+                ],
+                include_empty_days: false,
+                meeting_budget_minutes: None,
+            }
+        }
+    }
 
     #[test]
     fn test_event_to_llm_prompt() {
@@ -777,20 +1467,26 @@ mod tests {
 
         let parsed: CalendarEventsResponse = serde_json::from_str(json_str).unwrap();
         let tz: Tz = "America/Los_Angeles".parse().unwrap();
-        let since_naive_date = NaiveDate::parse_from_str("2025-01-05", "%Y-%m-%d")
-            .unwrap()
-            .and_hms_opt(0, 0, 0)
-            .unwrap();
-        let until_naive_date = NaiveDate::parse_from_str("2025-01-06", "%Y-%m-%d")
-            .unwrap()
-            .and_hms_opt(0, 0, 0)
-            .unwrap();
-        let since_with_tz = tz.from_local_datetime(&since_naive_date).unwrap();
-        let until_with_tz = tz.from_local_datetime(&until_naive_date).unwrap();
+        let since_with_tz = parse_bound("2025-01-05", &tz, Bound::Start).unwrap();
+        let until_with_tz = parse_bound("2025-01-06", &tz, Bound::End).unwrap();
 
-        let days =
-            Cal2Prompt::group_events_into_days(parsed.items, since_with_tz, until_with_tz, tz);
-        let prompt = generate(STANDARD, days).unwrap();
+        let days = Cal2Prompt::group_events_into_days(
+            parsed.items,
+            since_with_tz,
+            until_with_tz,
+            &DayLayout::new(tz),
+        );
+        // list_calendar_events returns these as they are.
+        assert_matches_output_schema("list_calendar_events", &serde_json::json!({ "days": days }));
+        let prompt = generate(
+            STANDARD,
+            &Agenda {
+                days,
+                free_slots: vec![],
+                stats: Stats::default(),
+            },
+        )
+        .unwrap();
 
         assert_eq!(
             prompt,
@@ -891,4 +1587,417 @@ mod tests {
 "#
         )
     }
+
+    #[test]
+    fn test_group_events_clips_to_sub_day_window() {
+        let json_str = r#"
+{
+ "items": [
+  {
+   "summary": "Lunch",
+   "start": { "dateTime": "2025-01-06T12:00:00+09:00" },
+   "end": { "dateTime": "2025-01-06T13:00:00+09:00" }
+  },
+  {
+   "summary": "Review",
+   "start": { "dateTime": "2025-01-06T13:30:00+09:00" },
+   "end": { "dateTime": "2025-01-06T15:00:00+09:00" }
+  },
+  {
+   "summary": "Planning",
+   "start": { "dateTime": "2025-01-06T17:00:00+09:00" },
+   "end": { "dateTime": "2025-01-06T18:00:00+09:00" }
+  },
+  {
+   "summary": "Dinner",
+   "start": { "dateTime": "2025-01-06T18:00:00+09:00" },
+   "end": { "dateTime": "2025-01-06T19:00:00+09:00" }
+  }
+ ]
+}
+    "#;
+
+        let parsed: CalendarEventsResponse = serde_json::from_str(json_str).unwrap();
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let since_with_tz = parse_bound("2025-01-06 14:00", &tz, Bound::Start).unwrap();
+        let until_with_tz = parse_bound("2025-01-06T18:00:00+09:00", &tz, Bound::End).unwrap();
+
+        let days = Cal2Prompt::group_events_into_days(
+            parsed.items,
+            since_with_tz,
+            until_with_tz,
+            &DayLayout::new(tz),
+        );
+
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].date, "2025-01-06");
+        let summaries: Vec<&str> = days[0]
+            .timed_events
+            .iter()
+            .map(|e| e.summary.as_str())
+            .collect();
+        assert_eq!(summaries, vec!["Review", "Planning"]);
+    }
+
+    #[test]
+    fn test_group_events_display_timezones() {
+        let json_str = r#"
+{
+ "items": [
+  {
+   "summary": "Sync with SF",
+   "start": { "dateTime": "2025-01-07T09:00:00+09:00", "timeZone": "America/New_York" },
+   "end": { "dateTime": "2025-01-07T10:00:00+09:00", "timeZone": "America/New_York" }
+  }
+ ]
+}
+    "#;
+
+        let parsed: CalendarEventsResponse = serde_json::from_str(json_str).unwrap();
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let layout = DayLayout {
+            display_timezones: vec![
+                "Asia/Tokyo".parse().unwrap(),
+                "America/Los_Angeles".parse().unwrap(),
+            ],
+            ..DayLayout::new(tz)
+        };
+        let since_with_tz = parse_bound("2025-01-07", &tz, Bound::Start).unwrap();
+        let until_with_tz = parse_bound("2025-01-07", &tz, Bound::End).unwrap();
+
+        let days =
+            Cal2Prompt::group_events_into_days(parsed.items, since_with_tz, until_with_tz, &layout);
+
+        let event = &days[0].timed_events[0];
+        assert_eq!(event.time_zone.as_deref(), Some("America/New_York"));
+        let zones: Vec<(&str, &str, &str)> = event
+            .zones
+            .iter()
+            .map(|z| (z.tz.as_str(), z.start.as_str(), z.end.as_str()))
+            .collect();
+        assert_eq!(
+            zones,
+            vec![
+                ("Asia/Tokyo", "2025-01-07 09:00", "2025-01-07 10:00"),
+                (
+                    "America/Los_Angeles",
+                    "2025-01-06 16:00",
+                    "2025-01-06 17:00"
+                ),
+                ("America/New_York", "2025-01-06 19:00", "2025-01-06 20:00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_events_spanning_multiple_days() {
+        let json_str = r#"
+{
+ "items": [
+  {
+   "summary": "On-call",
+   "start": { "dateTime": "2025-01-06T22:00:00+09:00" },
+   "end": { "dateTime": "2025-01-07T06:00:00+09:00" }
+  },
+  {
+   "summary": "Conference",
+   "start": { "dateTime": "2025-01-07T09:00:00+09:00" },
+   "end": { "dateTime": "2025-01-09T18:00:00+09:00" }
+  },
+  {
+   "summary": "Late meeting",
+   "start": { "dateTime": "2025-01-07T23:00:00+09:00" },
+   "end": { "dateTime": "2025-01-08T00:00:00+09:00" }
+  }
+ ]
+}
+    "#;
+
+        let parsed: CalendarEventsResponse = serde_json::from_str(json_str).unwrap();
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let since_with_tz = parse_bound("2025-01-07", &tz, Bound::Start).unwrap();
+        let until_with_tz = parse_bound("2025-01-08", &tz, Bound::End).unwrap();
+
+        let days = Cal2Prompt::group_events_into_days(
+            parsed.items,
+            since_with_tz,
+            until_with_tz,
+            &DayLayout::new(tz),
+        );
+
+        let summary = |day: &Day| -> Vec<(String, bool, bool)> {
+            day.timed_events
+                .iter()
+                .map(|e| {
+                    (
+                        e.summary.clone(),
+                        e.continues_from_previous_day,
+                        e.continues_to_next_day,
+                    )
+                })
+                .collect()
+        };
+
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[0].date, "2025-01-07");
+        assert_eq!(
+            summary(&days[0]),
+            vec![
+                ("On-call".to_string(), true, false),
+                ("Conference".to_string(), false, true),
+                ("Late meeting".to_string(), false, false),
+            ]
+        );
+        assert_eq!(days[1].date, "2025-01-08");
+        assert_eq!(
+            summary(&days[1]),
+            vec![("Conference".to_string(), true, true)]
+        );
+
+        let conference = &days[1].timed_events[0];
+        assert_eq!(conference.start_date, "2025-01-07");
+        assert_eq!(conference.end_date, "2025-01-09");
+        assert_eq!(conference.end, "18:00");
+
+        let prompt = generate(
+            STANDARD,
+            &Agenda {
+                days,
+                free_slots: vec![],
+                stats: Stats::default(),
+            },
+        )
+        .unwrap();
+        assert!(prompt.contains(
+            "- Conference\n  - Start: 09:00 (2025-01-07)\n  - End:   18:00 (2025-01-09)\n"
+        ));
+    }
+
+    #[test]
+    fn test_group_events_all_day_end_is_exclusive_across_months() {
+        let json_str = r#"
+{
+ "items": [
+  {
+   "summary": "Offsite",
+   "start": { "date": "2025-01-30" },
+   "end": { "date": "2025-02-01" }
+  },
+  {
+   "summary": "Kickoff",
+   "start": { "dateTime": "2025-02-01T10:00:00+09:00" },
+   "end": { "dateTime": "2025-02-01T11:00:00+09:00" }
+  }
+ ]
+}
+    "#;
+
+        let parsed: CalendarEventsResponse = serde_json::from_str(json_str).unwrap();
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let since_with_tz = parse_bound("2025-01-30", &tz, Bound::Start).unwrap();
+        let until_with_tz = parse_bound("2025-02-01", &tz, Bound::End).unwrap();
+
+        let days = Cal2Prompt::group_events_into_days(
+            parsed.items,
+            since_with_tz,
+            until_with_tz,
+            &DayLayout::new(tz),
+        );
+
+        let all_day = |day: &Day| -> Vec<(String, bool, bool)> {
+            day.all_day_events
+                .iter()
+                .map(|e| {
+                    (
+                        e.summary.clone(),
+                        e.continues_from_previous_day,
+                        e.continues_to_next_day,
+                    )
+                })
+                .collect()
+        };
+
+        // Google's end date is the day after the event, so February 1st is free.
+        assert_eq!(days.len(), 3);
+        assert_eq!(days[0].date, "2025-01-30");
+        assert_eq!(
+            all_day(&days[0]),
+            vec![("Offsite".to_string(), false, true)]
+        );
+        assert_eq!(days[1].date, "2025-01-31");
+        assert_eq!(
+            all_day(&days[1]),
+            vec![("Offsite".to_string(), true, false)]
+        );
+        assert_eq!(days[2].date, "2025-02-01");
+        assert!(all_day(&days[2]).is_empty());
+        assert_eq!(days[1].all_day_events[0].end_date, "2025-02-01");
+    }
+
+    #[test]
+    fn test_group_events_includes_empty_days() {
+        let json_str = r#"
+{
+ "items": [
+  {
+   "summary": "Standup",
+   "start": { "dateTime": "2025-01-03T10:00:00+09:00" },
+   "end": { "dateTime": "2025-01-03T10:15:00+09:00" }
+  }
+ ]
+}
+    "#;
+
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let since_with_tz = parse_bound("2025-01-03", &tz, Bound::Start).unwrap();
+        let until_with_tz = parse_bound("2025-01-06", &tz, Bound::End).unwrap();
+
+        let parsed: CalendarEventsResponse = serde_json::from_str(json_str).unwrap();
+        let days = Cal2Prompt::group_events_into_days(
+            parsed.items,
+            since_with_tz,
+            until_with_tz,
+            &DayLayout {
+                include_empty_days: true,
+                ..DayLayout::new(tz)
+            },
+        );
+
+        let summary: Vec<(&str, &str, u32, bool, usize)> = days
+            .iter()
+            .map(|d| {
+                (
+                    d.date.as_str(),
+                    d.weekday.as_str(),
+                    d.iso_week,
+                    d.is_weekend,
+                    d.timed_events.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("2025-01-03", "Friday", 1, false, 1),
+                ("2025-01-04", "Saturday", 1, true, 0),
+                ("2025-01-05", "Sunday", 1, true, 0),
+                ("2025-01-06", "Monday", 2, false, 0),
+            ]
+        );
+
+        // Off unless output.includeEmptyDays is set.
+        let parsed: CalendarEventsResponse = serde_json::from_str(json_str).unwrap();
+        let days = Cal2Prompt::group_events_into_days(
+            parsed.items,
+            since_with_tz,
+            until_with_tz,
+            &DayLayout::new(tz),
+        );
+        assert_eq!(days.len(), 1);
+    }
+
+    #[test]
+    fn test_group_events_annotates_conflicts_and_load() {
+        let json_str = r#"
+{
+ "items": [
+  {
+   "id": "a",
+   "summary": "Planning",
+   "start": { "dateTime": "2025-01-27T09:00:00+09:00" },
+   "end": { "dateTime": "2025-01-27T10:00:00+09:00" }
+  },
+  {
+   "id": "b",
+   "summary": "1:1",
+   "start": { "dateTime": "2025-01-27T10:00:00+09:00" },
+   "end": { "dateTime": "2025-01-27T11:00:00+09:00" }
+  },
+  {
+   "id": "c",
+   "summary": "Vendor call",
+   "start": { "dateTime": "2025-01-27T10:30:00+09:00" },
+   "end": { "dateTime": "2025-01-27T11:30:00+09:00" }
+  },
+  {
+   "id": "d",
+   "summary": "Focus (free)",
+   "transparency": "transparent",
+   "start": { "dateTime": "2025-01-27T09:30:00+09:00" },
+   "end": { "dateTime": "2025-01-27T12:00:00+09:00" }
+  },
+  {
+   "id": "e",
+   "summary": "1:1",
+   "start": { "dateTime": "2025-01-27T14:00:00+09:00" },
+   "end": { "dateTime": "2025-01-27T15:00:00+09:00" }
+  }
+ ]
+}
+    "#;
+
+        let parsed: CalendarEventsResponse = serde_json::from_str(json_str).unwrap();
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let since_with_tz = parse_bound("2025-01-27", &tz, Bound::Start).unwrap();
+        let until_with_tz = parse_bound("2025-01-27", &tz, Bound::End).unwrap();
+
+        let days = Cal2Prompt::group_events_into_days(
+            parsed.items,
+            since_with_tz,
+            until_with_tz,
+            &DayLayout {
+                meeting_budget_minutes: Some(120),
+                ..DayLayout::new(tz)
+            },
+        );
+        let day = &days[0];
+
+        assert_eq!(day.conflicts.len(), 1);
+        assert_eq!(day.conflicts[0].events, vec!["1:1", "Vendor call"]);
+        assert_eq!(
+            (
+                day.conflicts[0].start.as_str(),
+                day.conflicts[0].end.as_str()
+            ),
+            ("10:30", "11:00")
+        );
+
+        // The second "1:1" shares a title with "b" but overlaps nothing.
+        let overlaps: Vec<(Option<&str>, Vec<usize>)> = day
+            .timed_events
+            .iter()
+            .map(|e| (e.id.as_deref(), e.overlaps_with.clone()))
+            .collect();
+        assert_eq!(
+            overlaps,
+            vec![
+                (Some("a"), vec![]),
+                (Some("d"), vec![]),
+                (Some("b"), vec![3]),
+                (Some("c"), vec![2]),
+                (Some("e"), vec![]),
+            ]
+        );
+
+        assert_eq!(day.back_to_back.len(), 1);
+        assert_eq!(day.back_to_back[0].events, vec!["Planning", "1:1"]);
+        assert_eq!(day.back_to_back[0].minutes, 120);
+
+        assert_eq!(day.meeting_minutes, 210);
+        assert!(day.over_budget);
+
+        let prompt = generate(
+            STANDARD,
+            &Agenda {
+                days,
+                free_slots: vec![],
+                stats: Stats::default(),
+            },
+        )
+        .unwrap();
+        assert!(
+            prompt.contains("### Conflicts:\n- 1:1 / Vendor call (10:30 - 11:00)\n\n### Events:"),
+            "{prompt}"
+        );
+    }
 }
@@ -0,0 +1,347 @@
+use chrono::{Datelike, Days, Months, NaiveDate, TimeZone, Weekday};
+
+use crate::core::event::Clock;
+use crate::shared::utils::date::{days_since_week_start, parse_weekday};
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum DateExprError {
+    #[error("Invalid date range '{0}'. Examples: today, tomorrow, last week, next 3 days, +7d, 2025-W05, 2025-03, 2025-03-01, monday..friday")]
+    Invalid(String),
+
+    #[error("Date range '{0}' ends before it starts")]
+    Reversed(String),
+}
+
+/// A term of a date expression. Offsets like `+7d` are a span when used on
+/// their own ("the next 7 days") but a single day when used as an endpoint of
+/// `a..b`.
+enum Term {
+    Span(NaiveDate, NaiveDate),
+    Offset(NaiveDate, NaiveDate),
+}
+
+impl Term {
+    fn span(&self) -> (NaiveDate, NaiveDate) {
+        match *self {
+            Term::Span(start, end) => (start, end),
+            Term::Offset(today, target) => (today.min(target), today.max(target)),
+        }
+    }
+
+    fn start(&self) -> NaiveDate {
+        match *self {
+            Term::Span(start, _) => start,
+            Term::Offset(_, target) => target,
+        }
+    }
+
+    fn end(&self) -> NaiveDate {
+        match *self {
+            Term::Span(_, end) => end,
+            Term::Offset(_, target) => target,
+        }
+    }
+}
+
+/// Evaluates human-friendly date range expressions relative to the clock.
+pub struct DateRangeParser<C: Clock> {
+    clock: C,
+    week_start: Weekday,
+}
+
+impl<C: Clock> DateRangeParser<C> {
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            week_start: Weekday::Mon,
+        }
+    }
+
+    pub fn with_week_start(mut self, week_start: Weekday) -> Self {
+        self.week_start = week_start;
+        self
+    }
+
+    /// Returns the inclusive `(since, until)` dates for `expr` in `tz`.
+    pub fn parse<TZ: TimeZone>(
+        &self,
+        tz: &TZ,
+        expr: &str,
+    ) -> Result<(NaiveDate, NaiveDate), DateExprError> {
+        let today = self.clock.now().with_timezone(tz).date_naive();
+        let normalized = expr.trim().to_lowercase();
+        let invalid = || DateExprError::Invalid(expr.to_string());
+
+        let (since, until) = match normalized.split_once("..") {
+            Some((left, right)) => {
+                let left = self.parse_term(today, left.trim()).ok_or_else(invalid)?;
+                let right = self.parse_term(today, right.trim()).ok_or_else(invalid)?;
+                (left.start(), right.end())
+            }
+            None => self
+                .parse_term(today, &normalized)
+                .ok_or_else(invalid)?
+                .span(),
+        };
+
+        if since > until {
+            return Err(DateExprError::Reversed(expr.to_string()));
+        }
+        Ok((since, until))
+    }
+
+    fn parse_term(&self, today: NaiveDate, term: &str) -> Option<Term> {
+        let words: Vec<&str> = term.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["today"] => Some(Term::Span(today, today)),
+            ["tomorrow"] => {
+                let day = today.succ_opt()?;
+                Some(Term::Span(day, day))
+            }
+            ["yesterday"] => {
+                let day = today.pred_opt()?;
+                Some(Term::Span(day, day))
+            }
+            [modifier, "week"] => {
+                let weeks = relative(modifier)?;
+                let start = shift_days(self.week_start(today), weeks.checked_mul(7)?)?;
+                Some(Term::Span(start, start.checked_add_days(Days::new(6))?))
+            }
+            [modifier, "month"] => {
+                let months = relative(modifier)?;
+                let first = shift_months(today.with_day(1)?, months)?;
+                Some(Term::Span(first, month_end(first)?))
+            }
+            [direction @ ("next" | "last"), count, unit] => {
+                let count: i64 = count.parse().ok().filter(|n| *n > 0)?;
+                let days = match *unit {
+                    "day" | "days" => count,
+                    "week" | "weeks" => count.checked_mul(7)?,
+                    _ => return None,
+                };
+                if *direction == "next" {
+                    Some(Term::Span(today, shift_days(today, days - 1)?))
+                } else {
+                    Some(Term::Span(shift_days(today, -(days - 1))?, today))
+                }
+            }
+            [modifier, weekday] => {
+                let weeks = relative(modifier)?;
+                let day = self.weekday_in_week(today, parse_weekday(weekday)?);
+                let day = shift_days(day, weeks.checked_mul(7)?)?;
+                Some(Term::Span(day, day))
+            }
+            [single] => self.parse_single(today, single),
+            _ => None,
+        }
+    }
+
+    fn parse_single(&self, today: NaiveDate, term: &str) -> Option<Term> {
+        if let Some(weekday) = parse_weekday(term) {
+            let day = self.weekday_in_week(today, weekday);
+            return Some(Term::Span(day, day));
+        }
+
+        if let Some(offset) = parse_offset(term) {
+            return Some(Term::Offset(today, shift_days(today, offset)?));
+        }
+
+        if let Ok(day) = NaiveDate::parse_from_str(term, "%Y-%m-%d") {
+            return Some(Term::Span(day, day));
+        }
+
+        if let Some((year, week)) = term.split_once("-w") {
+            let monday =
+                NaiveDate::from_isoywd_opt(year.parse().ok()?, week.parse().ok()?, Weekday::Mon)?;
+            return Some(Term::Span(monday, monday.checked_add_days(Days::new(6))?));
+        }
+
+        if let Some((year, month)) = term.split_once('-') {
+            if month.len() == 2 {
+                let first = NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, 1)?;
+                return Some(Term::Span(first, month_end(first)?));
+            }
+        }
+
+        None
+    }
+
+    fn week_start(&self, day: NaiveDate) -> NaiveDate {
+        day - Days::new(days_since_week_start(day.weekday(), self.week_start).into())
+    }
+
+    /// The given weekday within the week containing `today`.
+    fn weekday_in_week(&self, today: NaiveDate, weekday: Weekday) -> NaiveDate {
+        self.week_start(today) + Days::new(days_since_week_start(weekday, self.week_start).into())
+    }
+}
+
+/// Parses `+7d`, `-3d`, `+2w` into a number of days.
+fn parse_offset(term: &str) -> Option<i64> {
+    let sign = match term.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let body = &term[1..];
+    let (count, days_per_unit) = match body.strip_suffix('d') {
+        Some(count) => (count, 1),
+        None => (body.strip_suffix('w')?, 7),
+    };
+    let count: i64 = count.parse().ok()?;
+    count.checked_mul(days_per_unit)?.checked_mul(sign)
+}
+
+fn relative(modifier: &str) -> Option<i64> {
+    match modifier {
+        "this" => Some(0),
+        "next" => Some(1),
+        "last" => Some(-1),
+        _ => None,
+    }
+}
+
+fn shift_days(day: NaiveDate, days: i64) -> Option<NaiveDate> {
+    if days >= 0 {
+        day.checked_add_days(Days::new(days as u64))
+    } else {
+        day.checked_sub_days(Days::new(days.unsigned_abs()))
+    }
+}
+
+fn shift_months(day: NaiveDate, months: i64) -> Option<NaiveDate> {
+    if months >= 0 {
+        day.checked_add_months(Months::new(months as u32))
+    } else {
+        day.checked_sub_months(Months::new(months.unsigned_abs() as u32))
+    }
+}
+
+fn month_end(first: NaiveDate) -> Option<NaiveDate> {
+    first.checked_add_months(Months::new(1))?.pred_opt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::event::MockClock;
+    use chrono::{FixedOffset, Utc};
+
+    // 2025-01-29 (Wed) 10:00 JST
+    fn parse(expr: &str) -> Result<(String, String), DateExprError> {
+        let mut mock_clock = MockClock::new();
+        mock_clock
+            .expect_now()
+            .returning(|| Utc.with_ymd_and_hms(2025, 1, 29, 1, 0, 0).unwrap());
+
+        let parser = DateRangeParser::new(mock_clock);
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        parser
+            .parse(&jst, expr)
+            .map(|(since, until)| (since.to_string(), until.to_string()))
+    }
+
+    fn range(since: &str, until: &str) -> Result<(String, String), DateExprError> {
+        Ok((since.to_string(), until.to_string()))
+    }
+
+    #[test]
+    fn test_relative_days() {
+        assert_eq!(parse("today"), range("2025-01-29", "2025-01-29"));
+        assert_eq!(parse("Tomorrow"), range("2025-01-30", "2025-01-30"));
+        assert_eq!(parse(" yesterday "), range("2025-01-28", "2025-01-28"));
+    }
+
+    #[test]
+    fn test_relative_weeks_and_months() {
+        assert_eq!(parse("this week"), range("2025-01-27", "2025-02-02"));
+        assert_eq!(parse("last week"), range("2025-01-20", "2025-01-26"));
+        assert_eq!(parse("next week"), range("2025-02-03", "2025-02-09"));
+        assert_eq!(parse("next month"), range("2025-02-01", "2025-02-28"));
+        assert_eq!(parse("last month"), range("2024-12-01", "2024-12-31"));
+    }
+
+    #[test]
+    fn test_next_and_last_n() {
+        assert_eq!(parse("next 3 days"), range("2025-01-29", "2025-01-31"));
+        assert_eq!(parse("last 7 days"), range("2025-01-23", "2025-01-29"));
+        assert_eq!(parse("next 2 weeks"), range("2025-01-29", "2025-02-11"));
+        assert!(parse("next 0 days").is_err());
+    }
+
+    #[test]
+    fn test_offsets() {
+        assert_eq!(parse("+7d"), range("2025-01-29", "2025-02-05"));
+        assert_eq!(parse("-3d"), range("2025-01-26", "2025-01-29"));
+        assert_eq!(parse("+1w"), range("2025-01-29", "2025-02-05"));
+        assert_eq!(parse("+7d..+14d"), range("2025-02-05", "2025-02-12"));
+        assert_eq!(parse("today..+2d"), range("2025-01-29", "2025-01-31"));
+    }
+
+    #[test]
+    fn test_calendar_units() {
+        assert_eq!(parse("2025-W05"), range("2025-01-27", "2025-02-02"));
+        assert_eq!(parse("2025-w01"), range("2024-12-30", "2025-01-05"));
+        assert_eq!(parse("2025-03"), range("2025-03-01", "2025-03-31"));
+        assert_eq!(parse("2024-02"), range("2024-02-01", "2024-02-29"));
+        assert_eq!(parse("2025-03-01"), range("2025-03-01", "2025-03-01"));
+        assert_eq!(
+            parse("2025-03-01..2025-03-05"),
+            range("2025-03-01", "2025-03-05")
+        );
+        assert_eq!(parse("2025-03..2025-04"), range("2025-03-01", "2025-04-30"));
+    }
+
+    #[test]
+    fn test_weekdays() {
+        assert_eq!(parse("monday..friday"), range("2025-01-27", "2025-01-31"));
+        assert_eq!(parse("fri"), range("2025-01-31", "2025-01-31"));
+        assert_eq!(parse("next monday"), range("2025-02-03", "2025-02-03"));
+        assert_eq!(parse("last friday"), range("2025-01-24", "2025-01-24"));
+        assert_eq!(parse("today..sunday"), range("2025-01-29", "2025-02-02"));
+    }
+
+    #[test]
+    fn test_week_start_sunday() {
+        let mut mock_clock = MockClock::new();
+        mock_clock
+            .expect_now()
+            .returning(|| Utc.with_ymd_and_hms(2025, 1, 29, 1, 0, 0).unwrap());
+        let parser = DateRangeParser::new(mock_clock).with_week_start(Weekday::Sun);
+        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+
+        let this_week = parser.parse(&jst, "this week").unwrap();
+        assert_eq!(
+            (this_week.0.to_string(), this_week.1.to_string()),
+            ("2025-01-26".to_string(), "2025-02-01".to_string())
+        );
+
+        let sunday = parser.parse(&jst, "sunday").unwrap();
+        assert_eq!(sunday.0.to_string(), "2025-01-26");
+
+        // ISO weeks always start on Monday.
+        let iso_week = parser.parse(&jst, "2025-W05").unwrap();
+        assert_eq!(iso_week.0.to_string(), "2025-01-27");
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(
+            parse("someday"),
+            Err(DateExprError::Invalid("someday".to_string()))
+        );
+        assert!(parse("2025-13").is_err());
+        assert!(parse("2025-W60").is_err());
+        assert!(parse("+7x").is_err());
+        assert!(parse("+1é").is_err());
+        assert!(parse("-3週").is_err());
+        assert!(parse("next 2000000000000000000 weeks").is_err());
+        assert!(parse("+2000000000000000000w").is_err());
+        assert!(parse("--9223372036854775808d").is_err());
+        assert_eq!(
+            parse("friday..monday"),
+            Err(DateExprError::Reversed("friday..monday".to_string()))
+        );
+    }
+}
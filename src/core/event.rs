@@ -1,6 +1,8 @@
-use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc, Weekday};
+use chrono_tz::Tz;
 
 use crate::core::cal2prompt::GetEventDuration;
+use crate::shared::utils::date::{days_since_week_start, start_of_day, DateError};
 
 #[cfg_attr(test, mockall::automock)]
 pub trait Clock {
@@ -17,73 +19,141 @@ impl Clock for RealClock {
 
 pub struct EventDurationCalculator<C: Clock> {
     clock: C,
+    week_start: Weekday,
+    workdays: Vec<Weekday>,
 }
 
 impl<C: Clock> EventDurationCalculator<C> {
     pub fn new(clock: C) -> Self {
-        Self { clock }
+        Self {
+            clock,
+            week_start: Weekday::Mon,
+            workdays: vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+        }
+    }
+
+    pub fn with_week_start(mut self, week_start: Weekday) -> Self {
+        self.week_start = week_start;
+        self
+    }
+
+    pub fn with_workdays(mut self, workdays: Vec<Weekday>) -> Self {
+        self.workdays = workdays;
+        self
     }
 
-    pub fn get_duration<TZ: TimeZone>(
+    /// Months and quarters start and end at the beginning of their first and
+    /// last day. Fails only when that day lies outside the supported range.
+    pub fn get_duration(
         &self,
-        tz: &TZ,
-        duration: GetEventDuration,
-    ) -> (DateTime<TZ>, DateTime<TZ>) {
+        tz: &Tz,
+        duration: &GetEventDuration,
+    ) -> Result<(DateTime<Tz>, DateTime<Tz>), DateError> {
         let now_utc = self.clock.now();
         let now_local = now_utc.with_timezone(tz);
 
-        match duration {
+        let range = match duration {
             GetEventDuration::Today => {
-                let since = now_local.clone();
-                let until = now_local.clone();
+                let since = now_local;
+                let until = now_local;
                 (since, until)
             }
-            GetEventDuration::ThisWeek => {
-                let weekday = now_local.weekday();
-                let days_from_monday = weekday.num_days_from_monday();
-                let monday = now_local - Duration::days(days_from_monday.into());
-                let sunday = monday.clone() + Duration::days(6);
-
-                let since = monday;
-                let until = sunday;
-                (since, until)
+            GetEventDuration::Tomorrow => {
+                let tomorrow = now_local + Duration::days(1);
+                (tomorrow, tomorrow)
             }
-            GetEventDuration::ThisMonth => {
-                let first_day = now_local.with_day(1).unwrap();
-                let next_month = if now_local.month() == 12 {
-                    first_day
-                        .with_month(1)
-                        .unwrap()
-                        .with_year(now_local.year() + 1)
-                        .unwrap()
-                } else {
-                    first_day.with_month(now_local.month() + 1).unwrap()
-                };
-                let last_day = next_month - Duration::days(1);
-
-                let since = first_day;
-                let until = last_day;
-                (since, until)
+            GetEventDuration::ThisWeek => self.week_of(now_local, 0),
+            GetEventDuration::NextWeek => self.week_of(now_local, 1),
+            GetEventDuration::LastWeek => self.week_of(now_local, -1),
+            GetEventDuration::ThisWorkweek => {
+                let (week_start, week_end) = self.week_of(now_local, 0);
+                let workday_offsets: Vec<i64> = (0..7)
+                    .filter(|offset| {
+                        let day = week_start + Duration::days(*offset);
+                        self.workdays.contains(&day.weekday())
+                    })
+                    .collect();
+
+                match (workday_offsets.first(), workday_offsets.last()) {
+                    (Some(first), Some(last)) => (
+                        week_start + Duration::days(*first),
+                        week_start + Duration::days(*last),
+                    ),
+                    _ => (week_start, week_end),
+                }
+            }
+            GetEventDuration::ThisMonth => return month_of(now_local, 0),
+            GetEventDuration::NextMonth => return month_of(now_local, 1),
+            GetEventDuration::LastMonth => return month_of(now_local, -1),
+            GetEventDuration::NextNDays(days) => {
+                let until = now_local + Duration::days(i64::from((*days).max(1)) - 1);
+                (now_local, until)
+            }
+            GetEventDuration::RestOfToday => (now_local, now_local),
+            GetEventDuration::NextHours(hours) => {
+                let until = now_local + Duration::hours(i64::from(*hours));
+                (now_local, until)
             }
-            GetEventDuration::NextWeek => {
-                let weekday = now_local.weekday();
-                let days_until_next_monday = 7 - weekday.num_days_from_monday();
-                let next_monday = now_local + Duration::days(days_until_next_monday.into());
-                let next_sunday = next_monday.clone() + Duration::days(6);
+            GetEventDuration::ThisQuarter => {
+                let first_month = (now_local.month0() / 3) * 3 + 1;
+                let first_day = NaiveDate::from_ymd_opt(now_local.year(), first_month, 1)
+                    .expect("the first of a month is always a valid date");
+                let last_day = add_months(first_day, 3)?
+                    .pred_opt()
+                    .ok_or_else(|| DateError::Invalid(first_day.to_string()))?;
+                (start_of_day(first_day, tz), start_of_day(last_day, tz))
+            }
+        };
+        Ok(range)
+    }
 
-                let since = next_monday;
-                let until = next_sunday;
+    /// The week containing `now_local` shifted by `weeks`, honoring the
+    /// configured first day of the week.
+    fn week_of(&self, now_local: DateTime<Tz>, weeks: i64) -> (DateTime<Tz>, DateTime<Tz>) {
+        let days_from_start = days_since_week_start(now_local.weekday(), self.week_start);
+        let start = now_local - Duration::days(days_from_start.into()) + Duration::weeks(weeks);
+        let end = start + Duration::days(6);
+        (start, end)
+    }
+}
 
-                (since, until)
-            }
-        }
+/// The calendar month containing `now_local` shifted by `months`.
+fn month_of(
+    now_local: DateTime<Tz>,
+    months: i32,
+) -> Result<(DateTime<Tz>, DateTime<Tz>), DateError> {
+    let this_month = now_local
+        .date_naive()
+        .with_day(1)
+        .expect("every month has a first day");
+    let first_day = if months >= 0 {
+        this_month.checked_add_months(Months::new(months as u32))
+    } else {
+        this_month.checked_sub_months(Months::new(months.unsigned_abs()))
     }
+    .ok_or_else(|| DateError::Invalid(this_month.to_string()))?;
+    let last_day = add_months(first_day, 1)?
+        .pred_opt()
+        .ok_or_else(|| DateError::Invalid(first_day.to_string()))?;
+    let tz = now_local.timezone();
+    Ok((start_of_day(first_day, &tz), start_of_day(last_day, &tz)))
+}
+
+fn add_months(date: NaiveDate, months: u32) -> Result<NaiveDate, DateError> {
+    date.checked_add_months(Months::new(months))
+        .ok_or_else(|| DateError::Invalid(date.to_string()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::{FixedOffset, TimeZone, Utc};
+    use chrono::{TimeZone, Utc};
 
     #[test]
     fn test_today() {
@@ -93,9 +163,11 @@ mod tests {
             .returning(|| Utc.with_ymd_and_hms(2025, 1, 26, 15, 0, 0).unwrap());
 
         let calculator = EventDurationCalculator::new(mock_clock);
-        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+        let jst = chrono_tz::Asia::Tokyo;
 
-        let (since, until) = calculator.get_duration(&jst, GetEventDuration::Today);
+        let (since, until) = calculator
+            .get_duration(&jst, &GetEventDuration::Today)
+            .unwrap();
 
         assert_eq!(since.format("%Y-%m-%d").to_string(), "2025-01-27");
         assert_eq!(until.format("%Y-%m-%d").to_string(), "2025-01-27");
@@ -109,8 +181,10 @@ mod tests {
             .returning(|| Utc.with_ymd_and_hms(2025, 1, 27, 15, 0, 0).unwrap());
 
         let calculator = EventDurationCalculator::new(mock_clock);
-        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
-        let (since, until) = calculator.get_duration(&jst, GetEventDuration::ThisWeek);
+        let jst = chrono_tz::Asia::Tokyo;
+        let (since, until) = calculator
+            .get_duration(&jst, &GetEventDuration::ThisWeek)
+            .unwrap();
 
         assert_eq!(since.format("%Y-%m-%d").to_string(), "2025-01-27");
         assert_eq!(until.format("%Y-%m-%d").to_string(), "2025-02-02");
@@ -124,8 +198,10 @@ mod tests {
             .returning(|| Utc.with_ymd_and_hms(2025, 1, 26, 15, 0, 0).unwrap());
 
         let calculator = EventDurationCalculator::new(mock_clock);
-        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
-        let (since, until) = calculator.get_duration(&jst, GetEventDuration::ThisMonth);
+        let jst = chrono_tz::Asia::Tokyo;
+        let (since, until) = calculator
+            .get_duration(&jst, &GetEventDuration::ThisMonth)
+            .unwrap();
 
         assert_eq!(since.format("%Y-%m-%d").to_string(), "2025-01-01");
         assert_eq!(until.format("%Y-%m-%d").to_string(), "2025-01-31");
@@ -139,10 +215,268 @@ mod tests {
             .returning(|| Utc.with_ymd_and_hms(2025, 1, 26, 15, 0, 0).unwrap());
 
         let calculator = EventDurationCalculator::new(mock_clock);
-        let jst = FixedOffset::east_opt(9 * 3600).unwrap();
-        let (since, until) = calculator.get_duration(&jst, GetEventDuration::NextWeek);
+        let jst = chrono_tz::Asia::Tokyo;
+        let (since, until) = calculator
+            .get_duration(&jst, &GetEventDuration::NextWeek)
+            .unwrap();
 
         assert_eq!(since.format("%Y-%m-%d").to_string(), "2025-02-03");
         assert_eq!(until.format("%Y-%m-%d").to_string(), "2025-02-09");
     }
+
+    fn calculator_at(
+        year: i32,
+        month: u32,
+        day: u32,
+        hour: u32,
+    ) -> EventDurationCalculator<MockClock> {
+        let mut mock_clock = MockClock::new();
+        mock_clock
+            .expect_now()
+            .returning(move || Utc.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap());
+        EventDurationCalculator::new(mock_clock)
+    }
+
+    fn format_range<TZ: TimeZone>(range: (DateTime<TZ>, DateTime<TZ>)) -> (String, String)
+    where
+        TZ::Offset: std::fmt::Display,
+    {
+        (
+            range.0.format("%Y-%m-%d").to_string(),
+            range.1.format("%Y-%m-%d").to_string(),
+        )
+    }
+
+    fn range(since: &str, until: &str) -> (String, String) {
+        (since.to_string(), until.to_string())
+    }
+
+    #[test]
+    fn test_tomorrow() {
+        // 2025-01-31 23:00 JST
+        let calculator = calculator_at(2025, 1, 31, 14);
+        let jst = chrono_tz::Asia::Tokyo;
+
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::Tomorrow)
+                    .unwrap()
+            ),
+            range("2025-02-01", "2025-02-01")
+        );
+    }
+
+    #[test]
+    fn test_last_week() {
+        // Wednesday 2025-01-29 JST
+        let calculator = calculator_at(2025, 1, 29, 1);
+        let jst = chrono_tz::Asia::Tokyo;
+
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::LastWeek)
+                    .unwrap()
+            ),
+            range("2025-01-20", "2025-01-26")
+        );
+    }
+
+    #[test]
+    fn test_week_start_sunday() {
+        // Wednesday 2025-01-29 JST
+        let calculator = calculator_at(2025, 1, 29, 1).with_week_start(Weekday::Sun);
+        let jst = chrono_tz::Asia::Tokyo;
+
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::ThisWeek)
+                    .unwrap()
+            ),
+            range("2025-01-26", "2025-02-01")
+        );
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::NextWeek)
+                    .unwrap()
+            ),
+            range("2025-02-02", "2025-02-08")
+        );
+    }
+
+    #[test]
+    fn test_week_start_saturday_on_saturday() {
+        // Saturday 2025-02-01 JST
+        let calculator = calculator_at(2025, 2, 1, 1).with_week_start(Weekday::Sat);
+        let jst = chrono_tz::Asia::Tokyo;
+
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::ThisWeek)
+                    .unwrap()
+            ),
+            range("2025-02-01", "2025-02-07")
+        );
+    }
+
+    #[test]
+    fn test_this_workweek() {
+        // Wednesday 2025-01-29 JST
+        let jst = chrono_tz::Asia::Tokyo;
+
+        let calculator = calculator_at(2025, 1, 29, 1);
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::ThisWorkweek)
+                    .unwrap()
+            ),
+            range("2025-01-27", "2025-01-31")
+        );
+
+        let calculator = calculator_at(2025, 1, 29, 1)
+            .with_week_start(Weekday::Sun)
+            .with_workdays(vec![
+                Weekday::Sun,
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+            ]);
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::ThisWorkweek)
+                    .unwrap()
+            ),
+            range("2025-01-26", "2025-01-30")
+        );
+    }
+
+    #[test]
+    fn test_next_and_last_month() {
+        // 2025-12-15 JST
+        let calculator = calculator_at(2025, 12, 15, 1);
+        let jst = chrono_tz::Asia::Tokyo;
+
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::NextMonth)
+                    .unwrap()
+            ),
+            range("2026-01-01", "2026-01-31")
+        );
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::LastMonth)
+                    .unwrap()
+            ),
+            range("2025-11-01", "2025-11-30")
+        );
+    }
+
+    #[test]
+    fn test_next_n_days() {
+        // 2025-02-27 JST
+        let calculator = calculator_at(2025, 2, 27, 1);
+        let jst = chrono_tz::Asia::Tokyo;
+
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::NextNDays(3))
+                    .unwrap()
+            ),
+            range("2025-02-27", "2025-03-01")
+        );
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::NextNDays(1))
+                    .unwrap()
+            ),
+            range("2025-02-27", "2025-02-27")
+        );
+    }
+
+    #[test]
+    fn test_rest_of_today_and_next_hours() {
+        // 2025-01-29 14:20 JST
+        let mut mock_clock = MockClock::new();
+        mock_clock
+            .expect_now()
+            .returning(|| Utc.with_ymd_and_hms(2025, 1, 29, 5, 20, 0).unwrap());
+        let calculator = EventDurationCalculator::new(mock_clock);
+        let jst = chrono_tz::Asia::Tokyo;
+
+        let (since, until) = calculator
+            .get_duration(&jst, &GetEventDuration::RestOfToday)
+            .unwrap();
+        assert_eq!(since.to_rfc3339(), "2025-01-29T14:20:00+09:00");
+        assert_eq!(until.format("%Y-%m-%d").to_string(), "2025-01-29");
+
+        let (since, until) = calculator
+            .get_duration(&jst, &GetEventDuration::NextHours(12))
+            .unwrap();
+        assert_eq!(since.to_rfc3339(), "2025-01-29T14:20:00+09:00");
+        assert_eq!(until.to_rfc3339(), "2025-01-30T02:20:00+09:00");
+    }
+
+    #[test]
+    fn test_this_quarter() {
+        let jst = chrono_tz::Asia::Tokyo;
+
+        let calculator = calculator_at(2025, 5, 31, 1);
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::ThisQuarter)
+                    .unwrap()
+            ),
+            range("2025-04-01", "2025-06-30")
+        );
+
+        let calculator = calculator_at(2025, 12, 31, 1);
+        assert_eq!(
+            format_range(
+                calculator
+                    .get_duration(&jst, &GetEventDuration::ThisQuarter)
+                    .unwrap()
+            ),
+            range("2025-10-01", "2025-12-31")
+        );
+    }
+
+    #[test]
+    fn test_month_start_in_dst_gap() {
+        // Paraguay moved its clocks from 00:00 to 01:00 on 2017-10-01, so that
+        // day begins at 01:00.
+        let tz = chrono_tz::America::Asuncion;
+        let mut mock_clock = MockClock::new();
+        mock_clock.expect_now().returning(|| {
+            chrono_tz::America::Asuncion
+                .with_ymd_and_hms(2017, 10, 15, 0, 30, 0)
+                .unwrap()
+                .with_timezone(&Utc)
+        });
+        let calculator = EventDurationCalculator::new(mock_clock);
+
+        let (since, until) = calculator
+            .get_duration(&tz, &GetEventDuration::ThisMonth)
+            .unwrap();
+        assert_eq!(since.to_rfc3339(), "2017-10-01T01:00:00-03:00");
+        assert_eq!(until.format("%Y-%m-%d").to_string(), "2017-10-31");
+
+        let (since, until) = calculator
+            .get_duration(&tz, &GetEventDuration::ThisQuarter)
+            .unwrap();
+        assert_eq!(since.to_rfc3339(), "2017-10-01T01:00:00-03:00");
+        assert_eq!(until.format("%Y-%m-%d").to_string(), "2017-12-31");
+    }
 }
@@ -1,3 +1,8 @@
 pub mod cal2prompt;
+pub mod date_expr;
 pub mod event;
+pub mod schedule;
+pub mod stats;
 pub mod template;
+pub mod token_store;
+pub mod write_guard;
@@ -0,0 +1,569 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::config::WorkingHours;
+use crate::google::calendar::model::{BusyPeriod, EventItem, FreeBusyResponse};
+use crate::shared::utils::date::{intersection_days, resolve_local, start_of_day, LocalResolution};
+
+/// A span of time `[start, end)` during which the calendar owner is busy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeBlock {
+    pub start: DateTime<Tz>,
+    pub end: DateTime<Tz>,
+}
+
+/// A gap within working hours that is long enough to schedule something.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FreeSlot {
+    pub date: String,
+    pub weekday: String,
+    pub start: String,
+    pub end: String,
+    pub minutes: i64,
+}
+
+/// The busy periods of one person or calendar, without event details.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Availability {
+    pub id: String,
+    pub busy: Vec<BusySpan>,
+    /// Reasons reported by Google when the calendar could not be read
+    /// (e.g. `notFound`).
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BusySpan {
+    pub start: String,
+    pub end: String,
+}
+
+/// Collects the blocks of time occupied by `events`, merging overlapping and
+/// adjacent ones.
+///
+/// Events marked as free (transparent), cancelled events and invitations the
+/// owner declined do not block time. All-day events block their whole days
+/// unless they are transparent.
+pub fn busy_blocks(events: &[EventItem], tz: &Tz) -> Vec<TimeBlock> {
+    let blocks: Vec<TimeBlock> = events
+        .iter()
+        .filter(|ev| !ev.is_transparent() && !ev.is_cancelled() && !ev.is_declined())
+        .filter_map(|ev| {
+            if ev.is_all_day() {
+                let start =
+                    NaiveDate::parse_from_str(ev.start.as_ref()?.date.as_ref()?, "%Y-%m-%d");
+                let end = NaiveDate::parse_from_str(ev.end.as_ref()?.date.as_ref()?, "%Y-%m-%d");
+                Some(TimeBlock {
+                    start: start_of_day(start.ok()?, tz),
+                    end: start_of_day(end.ok()?, tz),
+                })
+            } else {
+                Some(TimeBlock {
+                    start: ev.start_time_utc()?.with_timezone(tz),
+                    end: ev.end_time_utc()?.with_timezone(tz),
+                })
+            }
+        })
+        .collect();
+
+    merge_blocks(blocks)
+}
+
+/// Collects the busy periods of every calendar in a freeBusy response,
+/// merging overlapping and adjacent ones.
+pub fn free_busy_blocks(response: &FreeBusyResponse, tz: &Tz) -> Vec<TimeBlock> {
+    let blocks = response
+        .calendars
+        .values()
+        .flat_map(|calendar| calendar.busy.iter())
+        .filter_map(|period| period_to_block(period, tz))
+        .collect();
+
+    merge_blocks(blocks)
+}
+
+/// The busy periods of each of `ids`, in the order they were requested.
+pub fn availability(ids: &[String], response: &FreeBusyResponse, tz: &Tz) -> Vec<Availability> {
+    ids.iter()
+        .map(|id| {
+            let calendar = response.calendars.get(id);
+            let busy = calendar
+                .map(|calendar| {
+                    calendar
+                        .busy
+                        .iter()
+                        .filter_map(|period| period_to_block(period, tz))
+                        .map(|block| BusySpan {
+                            start: block.start.format("%Y-%m-%d %H:%M").to_string(),
+                            end: block.end.format("%Y-%m-%d %H:%M").to_string(),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let errors = match calendar {
+                Some(calendar) => calendar
+                    .errors
+                    .iter()
+                    .map(|error| error.reason.clone())
+                    .collect(),
+                None => vec!["notFound".to_string()],
+            };
+
+            Availability {
+                id: id.clone(),
+                busy,
+                errors,
+            }
+        })
+        .collect()
+}
+
+fn period_to_block(period: &BusyPeriod, tz: &Tz) -> Option<TimeBlock> {
+    Some(TimeBlock {
+        start: DateTime::parse_from_rfc3339(&period.start)
+            .ok()?
+            .with_timezone(tz),
+        end: DateTime::parse_from_rfc3339(&period.end)
+            .ok()?
+            .with_timezone(tz),
+    })
+}
+
+fn merge_blocks(mut blocks: Vec<TimeBlock>) -> Vec<TimeBlock> {
+    blocks.retain(|block| block.start < block.end);
+    blocks.sort_by_key(|block| block.start);
+
+    let mut merged: Vec<TimeBlock> = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        match merged.last_mut() {
+            Some(last) if block.start <= last.end => {
+                last.end = last.end.max(block.end);
+            }
+            _ => merged.push(block),
+        }
+    }
+    merged
+}
+
+/// Finds the free slots of at least `min` within `[since, until)` that fall
+/// inside `working_hours` and outside every block in `busy`.
+///
+/// `busy` must be sorted and merged, as returned by [`busy_blocks`].
+pub fn free_slots(
+    busy: &[TimeBlock],
+    since: DateTime<Tz>,
+    until: DateTime<Tz>,
+    working_hours: &[WorkingHours],
+    min: Duration,
+) -> Vec<FreeSlot> {
+    let tz = since.timezone();
+    let first_day = since.date_naive();
+    let last_day = (until - Duration::nanoseconds(1)).date_naive();
+
+    let mut slots = Vec::new();
+    for date in intersection_days(first_day, last_day, first_day, last_day) {
+        for hours in working_hours
+            .iter()
+            .filter(|hours| hours.weekday == date.weekday())
+        {
+            let window_start =
+                resolve_local(&tz, date.and_time(hours.start), LocalResolution::Earliest)
+                    .expect("only LocalResolution::Reject can fail")
+                    .max(since);
+            let window_end = resolve_local(&tz, date.and_time(hours.end), LocalResolution::Latest)
+                .expect("only LocalResolution::Reject can fail")
+                .min(until);
+
+            let mut cursor = window_start;
+            for block in busy
+                .iter()
+                .filter(|block| block.end > window_start && block.start < window_end)
+            {
+                if block.start > cursor {
+                    push_slot(&mut slots, date, cursor, block.start, min);
+                }
+                cursor = cursor.max(block.end);
+            }
+            if cursor < window_end {
+                push_slot(&mut slots, date, cursor, window_end, min);
+            }
+        }
+    }
+    slots
+}
+
+/// Pairs of indices into `blocks` whose time ranges overlap.
+pub fn overlapping_pairs(blocks: &[TimeBlock]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    for (i, a) in blocks.iter().enumerate() {
+        for (j, b) in blocks.iter().enumerate().skip(i + 1) {
+            if a.start < b.end && b.start < a.end {
+                pairs.push((i, j));
+            }
+        }
+    }
+    pairs
+}
+
+/// Runs of two or more blocks where each one starts exactly when the
+/// previous one ends, leaving no break. `blocks` must be sorted by start.
+pub fn back_to_back_chains(blocks: &[TimeBlock]) -> Vec<Vec<usize>> {
+    let mut chains = Vec::new();
+    let mut chain: Vec<usize> = Vec::new();
+    for (i, block) in blocks.iter().enumerate() {
+        match chain.last() {
+            Some(&last) if blocks[last].end == block.start => chain.push(i),
+            _ => {
+                if chain.len() > 1 {
+                    chains.push(std::mem::take(&mut chain));
+                }
+                chain = vec![i];
+            }
+        }
+    }
+    if chain.len() > 1 {
+        chains.push(chain);
+    }
+    chains
+}
+
+/// Minutes of `within` covered by at least one of `blocks`, counting
+/// overlapping time once.
+pub fn covered_minutes(blocks: &[TimeBlock], within: &TimeBlock) -> i64 {
+    let clipped = blocks
+        .iter()
+        .map(|block| TimeBlock {
+            start: block.start.max(within.start),
+            end: block.end.min(within.end),
+        })
+        .collect();
+
+    merge_blocks(clipped)
+        .iter()
+        .map(|block| (block.end - block.start).num_minutes())
+        .sum()
+}
+
+/// Renders free slots as plain text grouped by day.
+pub fn format_free_slots(slots: &[FreeSlot]) -> String {
+    if slots.is_empty() {
+        return "(No free slots)\n".to_string();
+    }
+
+    let mut out = String::new();
+    let mut current_date = None;
+    for slot in slots {
+        if current_date != Some(&slot.date) {
+            out.push_str(&format!("## {} ({})\n", slot.date, slot.weekday));
+            current_date = Some(&slot.date);
+        }
+        out.push_str(&format!(
+            "- {} - {} ({} min)\n",
+            slot.start, slot.end, slot.minutes
+        ));
+    }
+    out
+}
+
+fn push_slot(
+    slots: &mut Vec<FreeSlot>,
+    date: NaiveDate,
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    min: Duration,
+) {
+    let length = end - start;
+    if length < min {
+        return;
+    }
+    slots.push(FreeSlot {
+        date: date.to_string(),
+        weekday: date.format("%A").to_string(),
+        start: start.format("%H:%M").to_string(),
+        end: end.format("%H:%M").to_string(),
+        minutes: length.num_minutes(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::google::calendar::model::{CalendarEventsResponse, FreeBusyResponse};
+    use crate::shared::utils::date::{parse_bound, Bound};
+    use chrono::{NaiveTime, Weekday};
+
+    fn events(json_str: &str) -> Vec<EventItem> {
+        serde_json::from_str::<CalendarEventsResponse>(json_str)
+            .unwrap()
+            .items
+    }
+
+    fn hours(weekday: Weekday, start: &str, end: &str) -> WorkingHours {
+        WorkingHours {
+            weekday,
+            start: NaiveTime::parse_from_str(start, "%H:%M").unwrap(),
+            end: NaiveTime::parse_from_str(end, "%H:%M").unwrap(),
+        }
+    }
+
+    fn format_slots(slots: &[FreeSlot]) -> Vec<String> {
+        slots
+            .iter()
+            .map(|slot| format!("{} {}-{} {}", slot.date, slot.start, slot.end, slot.minutes))
+            .collect()
+    }
+
+    #[test]
+    fn test_busy_blocks_merge_transparency_and_declines() {
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let items = events(
+            r#"
+{
+ "items": [
+  {
+   "summary": "Design review",
+   "start": { "dateTime": "2025-01-27T10:00:00+09:00" },
+   "end": { "dateTime": "2025-01-27T11:00:00+09:00" }
+  },
+  {
+   "summary": "Overlapping 1:1",
+   "start": { "dateTime": "2025-01-27T10:30:00+09:00" },
+   "end": { "dateTime": "2025-01-27T11:30:00+09:00" }
+  },
+  {
+   "summary": "Back-to-back",
+   "start": { "dateTime": "2025-01-27T11:30:00+09:00" },
+   "end": { "dateTime": "2025-01-27T12:00:00+09:00" }
+  },
+  {
+   "summary": "Someone else declined",
+   "attendees": [
+    { "email": "me@example.com", "self": true, "responseStatus": "accepted" },
+    { "email": "other@example.com", "responseStatus": "declined" }
+   ],
+   "start": { "dateTime": "2025-01-27T13:00:00+09:00" },
+   "end": { "dateTime": "2025-01-27T13:30:00+09:00" }
+  },
+  {
+   "summary": "Declined",
+   "attendees": [
+    { "email": "me@example.com", "self": true, "responseStatus": "declined" }
+   ],
+   "start": { "dateTime": "2025-01-27T13:30:00+09:00" },
+   "end": { "dateTime": "2025-01-27T14:00:00+09:00" }
+  },
+  {
+   "summary": "Reminder (free)",
+   "transparency": "transparent",
+   "start": { "dateTime": "2025-01-27T14:00:00+09:00" },
+   "end": { "dateTime": "2025-01-27T15:00:00+09:00" }
+  },
+  {
+   "summary": "Cancelled",
+   "status": "cancelled",
+   "start": { "dateTime": "2025-01-27T16:00:00+09:00" },
+   "end": { "dateTime": "2025-01-27T17:00:00+09:00" }
+  },
+  {
+   "summary": "Out of office",
+   "start": { "date": "2025-01-28" },
+   "end": { "date": "2025-01-29" }
+  }
+ ]
+}
+"#,
+        );
+
+        let blocks: Vec<(String, String)> = busy_blocks(&items, &tz)
+            .iter()
+            .map(|block| (block.start.to_rfc3339(), block.end.to_rfc3339()))
+            .collect();
+
+        assert_eq!(
+            blocks,
+            vec![
+                (
+                    "2025-01-27T10:00:00+09:00".to_string(),
+                    "2025-01-27T12:00:00+09:00".to_string()
+                ),
+                (
+                    "2025-01-27T13:00:00+09:00".to_string(),
+                    "2025-01-27T13:30:00+09:00".to_string()
+                ),
+                (
+                    "2025-01-28T00:00:00+09:00".to_string(),
+                    "2025-01-29T00:00:00+09:00".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_free_slots_within_working_hours() {
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let items = events(
+            r#"
+{
+ "items": [
+  {
+   "summary": "Standup",
+   "start": { "dateTime": "2025-01-27T09:00:00+09:00" },
+   "end": { "dateTime": "2025-01-27T09:15:00+09:00" }
+  },
+  {
+   "summary": "Lunch",
+   "start": { "dateTime": "2025-01-27T12:00:00+09:00" },
+   "end": { "dateTime": "2025-01-27T13:00:00+09:00" }
+  },
+  {
+   "summary": "Focus",
+   "start": { "dateTime": "2025-01-27T13:20:00+09:00" },
+   "end": { "dateTime": "2025-01-27T17:00:00+09:00" }
+  }
+ ]
+}
+"#,
+        );
+        let working_hours = vec![
+            hours(Weekday::Mon, "09:00", "18:00"),
+            hours(Weekday::Tue, "10:00", "12:00"),
+        ];
+
+        let slots = free_slots(
+            &busy_blocks(&items, &tz),
+            parse_bound("2025-01-26", &tz, Bound::Start).unwrap(),
+            parse_bound("2025-01-28", &tz, Bound::End).unwrap(),
+            &working_hours,
+            Duration::minutes(30),
+        );
+
+        // The 20 minute gap after lunch is too short, and Sunday has no
+        // working hours.
+        assert_eq!(
+            format_slots(&slots),
+            vec![
+                "2025-01-27 09:15-12:00 165",
+                "2025-01-27 17:00-18:00 60",
+                "2025-01-28 10:00-12:00 120",
+            ]
+        );
+        assert_eq!(slots[0].weekday, "Monday");
+    }
+
+    #[test]
+    fn test_free_slots_clipped_to_window() {
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let working_hours = vec![hours(Weekday::Mon, "09:00", "18:00")];
+
+        let slots = free_slots(
+            &[],
+            parse_bound("2025-01-27 14:10", &tz, Bound::Start).unwrap(),
+            parse_bound("2025-01-27 16:00", &tz, Bound::End).unwrap(),
+            &working_hours,
+            Duration::minutes(30),
+        );
+
+        assert_eq!(format_slots(&slots), vec!["2025-01-27 14:10-16:00 110"]);
+    }
+
+    #[test]
+    fn test_common_free_time_from_free_busy() {
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let response: FreeBusyResponse = serde_json::from_str(
+            r#"
+{
+ "kind": "calendar#freeBusy",
+ "timeMin": "2025-01-27T00:00:00.000Z",
+ "timeMax": "2025-01-28T00:00:00.000Z",
+ "calendars": {
+  "alice@example.com": {
+   "busy": [
+    { "start": "2025-01-27T01:00:00Z", "end": "2025-01-27T03:00:00Z" }
+   ]
+  },
+  "bob@example.com": {
+   "busy": [
+    { "start": "2025-01-27T02:30:00Z", "end": "2025-01-27T04:00:00Z" },
+    { "start": "2025-01-27T06:00:00Z", "end": "2025-01-27T08:30:00Z" }
+   ]
+  },
+  "carol@other.example": {
+   "errors": [ { "domain": "global", "reason": "notFound" } ],
+   "busy": []
+  }
+ }
+}
+"#,
+        )
+        .unwrap();
+
+        let slots = free_slots(
+            &free_busy_blocks(&response, &tz),
+            parse_bound("2025-01-27", &tz, Bound::Start).unwrap(),
+            parse_bound("2025-01-27", &tz, Bound::End).unwrap(),
+            &[hours(Weekday::Mon, "09:00", "18:00")],
+            Duration::minutes(30),
+        );
+        assert_eq!(
+            format_slots(&slots),
+            vec![
+                "2025-01-27 09:00-10:00 60",
+                "2025-01-27 13:00-15:00 120",
+                "2025-01-27 17:30-18:00 30",
+            ]
+        );
+
+        let ids = vec![
+            "bob@example.com".to_string(),
+            "carol@other.example".to_string(),
+        ];
+        let availability = availability(&ids, &response, &tz);
+        assert_eq!(
+            availability[0].busy,
+            vec![
+                BusySpan {
+                    start: "2025-01-27 11:30".to_string(),
+                    end: "2025-01-27 13:00".to_string(),
+                },
+                BusySpan {
+                    start: "2025-01-27 15:00".to_string(),
+                    end: "2025-01-27 17:30".to_string(),
+                },
+            ]
+        );
+        assert!(availability[0].errors.is_empty());
+        assert_eq!(availability[1].errors, vec!["notFound".to_string()]);
+    }
+
+    #[test]
+    fn test_overlaps_chains_and_covered_minutes() {
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let block = |start: &str, end: &str| TimeBlock {
+            start: parse_bound(start, &tz, Bound::Start).unwrap(),
+            end: parse_bound(end, &tz, Bound::Start).unwrap(),
+        };
+        let blocks = vec![
+            block("2025-01-27 09:00", "2025-01-27 10:00"),
+            block("2025-01-27 10:00", "2025-01-27 11:00"),
+            block("2025-01-27 10:30", "2025-01-27 11:30"),
+            block("2025-01-27 11:30", "2025-01-27 12:00"),
+            block("2025-01-27 13:00", "2025-01-27 14:00"),
+            block("2025-01-27 14:00", "2025-01-27 14:30"),
+        ];
+
+        assert_eq!(overlapping_pairs(&blocks), vec![(1, 2)]);
+        assert_eq!(
+            back_to_back_chains(&blocks),
+            vec![vec![0, 1], vec![2, 3], vec![4, 5]]
+        );
+        assert_eq!(
+            covered_minutes(&blocks, &block("2025-01-27 00:00", "2025-01-28 00:00")),
+            180 + 90
+        );
+        assert_eq!(
+            covered_minutes(&blocks, &block("2025-01-27 11:00", "2025-01-27 13:30")),
+            60 + 30
+        );
+    }
+}
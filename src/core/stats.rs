@@ -0,0 +1,381 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::core::schedule::FreeSlot;
+use crate::google::calendar::model::EventItem;
+
+const TOP_COLLABORATORS: usize = 5;
+const FOCUS_TIME_EVENT_TYPE: &str = "focusTime";
+
+/// Aggregate figures over the timed events of a range, for retrospectives.
+///
+/// All-day, cancelled, free (transparent) and declined events are not
+/// counted, and events are clipped to the requested range.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Stats {
+    pub total_events: usize,
+    pub total_minutes: i64,
+    pub by_calendar: Vec<Bucket>,
+    pub by_color: Vec<Bucket>,
+    pub by_event_type: Vec<Bucket>,
+    /// Time spent with attendees from each domain, other than yourself.
+    pub by_attendee_domain: Vec<Bucket>,
+    pub recurring: Bucket,
+    pub one_off: Bucket,
+    /// Google "focus time" events.
+    pub focus_minutes: i64,
+    /// Events with at least one other attendee.
+    pub meeting_minutes: i64,
+    /// Everything else, e.g. blocks on your own calendar.
+    pub other_minutes: i64,
+    pub top_collaborators: Vec<Bucket>,
+    pub longest_free_block: Option<FreeSlot>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Bucket {
+    pub key: String,
+    pub events: usize,
+    pub minutes: i64,
+}
+
+#[derive(Default)]
+struct Tally(HashMap<String, (usize, i64)>);
+
+impl Tally {
+    fn add(&mut self, key: &str, minutes: i64) {
+        let entry = self.0.entry(key.to_string()).or_default();
+        entry.0 += 1;
+        entry.1 += minutes;
+    }
+
+    /// Buckets with the most time first.
+    fn into_buckets(self) -> Vec<Bucket> {
+        let mut buckets: Vec<Bucket> = self
+            .0
+            .into_iter()
+            .map(|(key, (events, minutes))| Bucket {
+                key,
+                events,
+                minutes,
+            })
+            .collect();
+        buckets.sort_by(|a, b| b.minutes.cmp(&a.minutes).then_with(|| a.key.cmp(&b.key)));
+        buckets
+    }
+}
+
+pub fn compute(
+    events: &[EventItem],
+    since: DateTime<Tz>,
+    until: DateTime<Tz>,
+    free_slots: &[FreeSlot],
+) -> Stats {
+    let mut stats = Stats {
+        recurring: Bucket {
+            key: "recurring".to_string(),
+            ..Bucket::default()
+        },
+        one_off: Bucket {
+            key: "one_off".to_string(),
+            ..Bucket::default()
+        },
+        ..Stats::default()
+    };
+    let mut by_calendar = Tally::default();
+    let mut by_color = Tally::default();
+    let mut by_event_type = Tally::default();
+    let mut by_attendee_domain = Tally::default();
+    let mut collaborators = Tally::default();
+
+    for ev in events {
+        if ev.is_all_day() || ev.is_cancelled() || ev.is_transparent() || ev.is_declined() {
+            continue;
+        }
+        let (Some(start), Some(end)) = (ev.start_time_utc(), ev.end_time_utc()) else {
+            continue;
+        };
+        let start = start.max(since.to_utc());
+        let end = end.min(until.to_utc());
+        if start >= end {
+            continue;
+        }
+        let minutes = (end - start).num_minutes();
+
+        stats.total_events += 1;
+        stats.total_minutes += minutes;
+
+        by_calendar.add(ev.calendar_id.as_deref().unwrap_or("unknown"), minutes);
+        by_color.add(ev.color_id.as_deref().unwrap_or("default"), minutes);
+        by_event_type.add(ev.event_type.as_deref().unwrap_or("default"), minutes);
+
+        let recurrence = if ev.recurring_event_id.is_some() {
+            &mut stats.recurring
+        } else {
+            &mut stats.one_off
+        };
+        recurrence.events += 1;
+        recurrence.minutes += minutes;
+
+        let others: Vec<&str> = ev
+            .attendees
+            .iter()
+            .flatten()
+            .filter(|at| at.self_field != Some(true) && at.resource != Some(true))
+            .filter_map(|at| at.email.as_deref())
+            .collect();
+
+        if ev.event_type.as_deref() == Some(FOCUS_TIME_EVENT_TYPE) {
+            stats.focus_minutes += minutes;
+        } else if !others.is_empty() {
+            stats.meeting_minutes += minutes;
+        } else {
+            stats.other_minutes += minutes;
+        }
+
+        let domains: BTreeSet<&str> = others
+            .iter()
+            .filter_map(|email| email.rsplit_once('@').map(|(_, domain)| domain))
+            .collect();
+        for domain in domains {
+            by_attendee_domain.add(domain, minutes);
+        }
+        for email in others {
+            collaborators.add(email, minutes);
+        }
+    }
+
+    stats.by_calendar = by_calendar.into_buckets();
+    stats.by_color = by_color.into_buckets();
+    stats.by_event_type = by_event_type.into_buckets();
+    stats.by_attendee_domain = by_attendee_domain.into_buckets();
+    stats.top_collaborators = collaborators.into_buckets();
+    stats.top_collaborators.truncate(TOP_COLLABORATORS);
+    stats.longest_free_block = free_slots
+        .iter()
+        .fold(None::<&FreeSlot>, |longest, slot| match longest {
+            Some(longest) if longest.minutes >= slot.minutes => Some(longest),
+            _ => Some(slot),
+        })
+        .cloned();
+
+    stats
+}
+
+/// Renders `stats` as plain-text tables.
+pub fn format_table(stats: &Stats) -> String {
+    let mut out = format!(
+        "Total: {} events, {}\n",
+        stats.total_events,
+        format_minutes(stats.total_minutes)
+    );
+    out.push_str(&format!(
+        "Meetings: {} / Focus: {} / Other: {}\n",
+        format_minutes(stats.meeting_minutes),
+        format_minutes(stats.focus_minutes),
+        format_minutes(stats.other_minutes)
+    ));
+    out.push_str(&format!(
+        "Recurring: {} events, {} / One-off: {} events, {}\n",
+        stats.recurring.events,
+        format_minutes(stats.recurring.minutes),
+        stats.one_off.events,
+        format_minutes(stats.one_off.minutes)
+    ));
+    match &stats.longest_free_block {
+        Some(slot) => out.push_str(&format!(
+            "Longest free block: {} ({}) {} - {} ({})\n",
+            slot.date,
+            slot.weekday,
+            slot.start,
+            slot.end,
+            format_minutes(slot.minutes)
+        )),
+        None => out.push_str("Longest free block: (none)\n"),
+    }
+
+    for (title, buckets) in [
+        ("By calendar", &stats.by_calendar),
+        ("By color", &stats.by_color),
+        ("By event type", &stats.by_event_type),
+        ("By attendee domain", &stats.by_attendee_domain),
+        ("Top collaborators", &stats.top_collaborators),
+    ] {
+        out.push_str(&format!("\n## {}\n", title));
+        if buckets.is_empty() {
+            out.push_str("(none)\n");
+            continue;
+        }
+        let width = buckets.iter().map(|b| b.key.len()).max().unwrap_or(0);
+        for bucket in buckets {
+            out.push_str(&format!(
+                "{:<width$}  {:>4} events  {:>8}\n",
+                bucket.key,
+                bucket.events,
+                format_minutes(bucket.minutes),
+                width = width
+            ));
+        }
+    }
+    out
+}
+
+fn format_minutes(minutes: i64) -> String {
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::google::calendar::model::CalendarEventsResponse;
+    use crate::shared::utils::date::{parse_bound, Bound};
+
+    #[test]
+    fn test_compute_stats() {
+        let json_str = r#"
+{
+ "items": [
+  {
+   "summary": "Weekly sync",
+   "recurringEventId": "weekly",
+   "colorId": "5",
+   "start": { "dateTime": "2025-01-27T10:00:00+09:00" },
+   "end": { "dateTime": "2025-01-27T11:00:00+09:00" },
+   "attendees": [
+    { "email": "me@example.com", "self": true },
+    { "email": "alice@example.com" },
+    { "email": "bob@partner.example" },
+    { "email": "room@resource.example", "resource": true }
+   ]
+  },
+  {
+   "summary": "Design review",
+   "start": { "dateTime": "2025-01-28T14:00:00+09:00" },
+   "end": { "dateTime": "2025-01-28T15:30:00+09:00" },
+   "attendees": [
+    { "email": "me@example.com", "self": true },
+    { "email": "alice@example.com" }
+   ]
+  },
+  {
+   "summary": "Deep work",
+   "eventType": "focusTime",
+   "start": { "dateTime": "2025-01-29T09:00:00+09:00" },
+   "end": { "dateTime": "2025-01-29T11:00:00+09:00" }
+  },
+  {
+   "summary": "Gym",
+   "start": { "dateTime": "2025-01-31T23:00:00+09:00" },
+   "end": { "dateTime": "2025-02-01T01:00:00+09:00" }
+  },
+  {
+   "summary": "Lunch (free)",
+   "transparency": "transparent",
+   "start": { "dateTime": "2025-01-27T12:00:00+09:00" },
+   "end": { "dateTime": "2025-01-27T13:00:00+09:00" }
+  },
+  {
+   "summary": "Holiday",
+   "start": { "date": "2025-01-30" },
+   "end": { "date": "2025-01-31" }
+  },
+  {
+   "summary": "Vendor pitch (declined)",
+   "start": { "dateTime": "2025-01-30T15:00:00+09:00" },
+   "end": { "dateTime": "2025-01-30T16:00:00+09:00" },
+   "attendees": [
+    { "email": "me@example.com", "self": true, "responseStatus": "declined" },
+    { "email": "carol@vendor.example" }
+   ]
+  }
+ ]
+}
+"#;
+        let mut events = serde_json::from_str::<CalendarEventsResponse>(json_str)
+            .unwrap()
+            .items;
+        for (i, ev) in events.iter_mut().enumerate() {
+            ev.calendar_id = Some(if i == 3 { "personal" } else { "work" }.to_string());
+        }
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let free_slots = vec![
+            FreeSlot {
+                date: "2025-01-27".to_string(),
+                weekday: "Monday".to_string(),
+                start: "13:00".to_string(),
+                end: "15:00".to_string(),
+                minutes: 120,
+            },
+            FreeSlot {
+                date: "2025-01-28".to_string(),
+                weekday: "Tuesday".to_string(),
+                start: "09:00".to_string(),
+                end: "14:00".to_string(),
+                minutes: 300,
+            },
+        ];
+
+        let stats = compute(
+            &events,
+            parse_bound("2025-01-27", &tz, Bound::Start).unwrap(),
+            parse_bound("2025-01-31", &tz, Bound::End).unwrap(),
+            &free_slots,
+        );
+
+        let bucket = |key: &str, events, minutes| Bucket {
+            key: key.to_string(),
+            events,
+            minutes,
+        };
+
+        // The gym session is clipped at midnight, the end of the range.
+        assert_eq!(stats.total_events, 4);
+        assert_eq!(stats.total_minutes, 60 + 90 + 120 + 60);
+        assert_eq!(
+            stats.by_calendar,
+            vec![bucket("work", 3, 270), bucket("personal", 1, 60)]
+        );
+        assert_eq!(
+            stats.by_color,
+            vec![bucket("default", 3, 270), bucket("5", 1, 60)]
+        );
+        assert_eq!(
+            stats.by_event_type,
+            vec![bucket("default", 3, 210), bucket("focusTime", 1, 120)]
+        );
+        assert_eq!(
+            stats.by_attendee_domain,
+            vec![
+                bucket("example.com", 2, 150),
+                bucket("partner.example", 1, 60)
+            ]
+        );
+        assert_eq!(stats.recurring, bucket("recurring", 1, 60));
+        assert_eq!(stats.one_off, bucket("one_off", 3, 270));
+        assert_eq!(
+            (
+                stats.meeting_minutes,
+                stats.focus_minutes,
+                stats.other_minutes
+            ),
+            (150, 120, 60)
+        );
+        assert_eq!(
+            stats.top_collaborators,
+            vec![
+                bucket("alice@example.com", 2, 150),
+                bucket("bob@partner.example", 1, 60)
+            ]
+        );
+        assert_eq!(stats.longest_free_block, Some(free_slots[1].clone()));
+
+        let table = format_table(&stats);
+        assert!(table.starts_with("Total: 4 events, 5h 30m\n"), "{table}");
+        assert!(
+            table.contains("\n## By calendar\nwork         3 events    4h 30m\n"),
+            "{table}"
+        );
+    }
+}
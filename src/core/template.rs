@@ -1,8 +1,8 @@
 use minijinja::{context, Environment};
 
-use crate::core::cal2prompt::Day;
+use crate::core::cal2prompt::Agenda;
 
-pub fn generate(template: &str, days: Vec<Day>) -> anyhow::Result<String> {
+pub fn generate(template: &str, agenda: &Agenda) -> anyhow::Result<String> {
     let mut env = Environment::new();
     env.set_trim_blocks(true);
     env.set_lstrip_blocks(true);
@@ -11,7 +11,9 @@ pub fn generate(template: &str, days: Vec<Day>) -> anyhow::Result<String> {
     let tmpl = env.get_template("schedule")?;
 
     let rendered = tmpl.render(context! {
-        days => days
+        days => agenda.days,
+        free_slots => agenda.free_slots,
+        stats => agenda.stats,
     })?;
 
     Ok(rendered)
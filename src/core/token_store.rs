@@ -0,0 +1,383 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::google::oauth::Token;
+use crate::shared::utils::fs::{write_private_file_atomic, FileLock};
+
+pub const TOKEN_KEY_ENV: &str = "CAL2_PROMPT_TOKEN_KEY";
+
+const ENVELOPE_VERSION: u32 = 1;
+const CIPHER: &str = "AES-256-GCM";
+const KDF: &str = "PBKDF2-HMAC-SHA256";
+const KDF_ITERATIONS: u32 = 210_000;
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenStoreError {
+    #[error("The token file '{0}' is encrypted, but no key is configured. Set settings.tokenKeyCommand or $CAL2_PROMPT_TOKEN_KEY.")]
+    MissingKey(String),
+
+    #[error("Failed to decrypt the token file '{0}'. The key may be wrong or the file may be corrupted.")]
+    Decrypt(String),
+
+    #[error("Failed to read the token file '{0}': {1}")]
+    Read(String, String),
+
+    #[error("The token file '{0}' is not a valid token ({1}). Remove it to sign in again.")]
+    Invalid(String, String),
+
+    #[error("Unsupported token file format: {0}")]
+    UnsupportedFormat(String),
+
+    #[error("Token key command '{0}' failed: {1}")]
+    KeyCommand(String, String),
+
+    #[error("Token key is empty")]
+    EmptyKey,
+}
+
+/// Where the passphrase protecting the token file comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKeySource {
+    Env(String),
+    Command(String),
+}
+
+impl TokenKeySource {
+    /// `$CAL2_PROMPT_TOKEN_KEY` wins over `settings.tokenKeyCommand`.
+    pub fn resolve(key_command: Option<&str>) -> Option<Self> {
+        match std::env::var(TOKEN_KEY_ENV) {
+            Ok(key) if !key.is_empty() => Some(TokenKeySource::Env(key)),
+            _ => key_command.map(|cmd| TokenKeySource::Command(cmd.to_string())),
+        }
+    }
+
+    fn passphrase(&self) -> anyhow::Result<String> {
+        let passphrase = match self {
+            TokenKeySource::Env(key) => key.clone(),
+            TokenKeySource::Command(cmd) => {
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(cmd)
+                    .output()
+                    .map_err(|e| TokenStoreError::KeyCommand(cmd.clone(), e.to_string()))?;
+                if !output.status.success() {
+                    return Err(TokenStoreError::KeyCommand(
+                        cmd.clone(),
+                        String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                    )
+                    .into());
+                }
+                // Tools like `pass` print the secret on the first line.
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string()
+            }
+        };
+
+        if passphrase.is_empty() {
+            return Err(TokenStoreError::EmptyKey.into());
+        }
+        Ok(passphrase)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedToken {
+    version: u32,
+    cipher: String,
+    kdf: String,
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StoredToken {
+    Encrypted(EncryptedToken),
+    Plain(Token),
+}
+
+pub struct TokenStore {
+    path: PathBuf,
+    key_source: Option<TokenKeySource>,
+}
+
+impl TokenStore {
+    pub fn new<P: Into<PathBuf>>(path: P, key_source: Option<TokenKeySource>) -> Self {
+        Self {
+            path: path.into(),
+            key_source,
+        }
+    }
+
+    /// Returns `Ok(None)` when no token has been stored yet. A file that
+    /// cannot be read or parsed is an error rather than a missing token.
+    ///
+    /// A plaintext file is still accepted when a key is configured; it is
+    /// encrypted the next time the token is saved.
+    pub fn load(&self) -> anyhow::Result<Option<Token>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(TokenStoreError::Read(self.display_path(), err.to_string()).into())
+            }
+        };
+
+        let stored = serde_json::from_str::<StoredToken>(&content)
+            .map_err(|err| TokenStoreError::Invalid(self.display_path(), err.to_string()))?;
+        match stored {
+            StoredToken::Plain(token) => Ok(Some(token)),
+            StoredToken::Encrypted(envelope) => {
+                let Some(key_source) = &self.key_source else {
+                    return Err(TokenStoreError::MissingKey(self.display_path()).into());
+                };
+                let token = self.decrypt(&envelope, &key_source.passphrase()?)?;
+                Ok(Some(token))
+            }
+        }
+    }
+
+    pub fn save(&self, token: &Token) -> anyhow::Result<()> {
+        let text = match &self.key_source {
+            Some(key_source) => {
+                let envelope = encrypt(token, &key_source.passphrase()?)?;
+                serde_json::to_string_pretty(&envelope)?
+            }
+            None => serde_json::to_string_pretty(token)?,
+        };
+
+        write_private_file_atomic(&self.path, text.as_bytes())?;
+        Ok(())
+    }
+
+    /// Takes the advisory lock guarding read-refresh-write of the token file.
+    pub async fn lock(&self) -> anyhow::Result<FileLock> {
+        let path = self.path.clone();
+        let lock = tokio::task::spawn_blocking(move || FileLock::acquire(&path)).await??;
+        Ok(lock)
+    }
+
+    fn decrypt(&self, envelope: &EncryptedToken, passphrase: &str) -> anyhow::Result<Token> {
+        if envelope.version != ENVELOPE_VERSION || envelope.cipher != CIPHER || envelope.kdf != KDF
+        {
+            return Err(TokenStoreError::UnsupportedFormat(format!(
+                "version={} cipher={} kdf={}",
+                envelope.version, envelope.cipher, envelope.kdf
+            ))
+            .into());
+        }
+
+        let decrypt_error = || TokenStoreError::Decrypt(self.display_path());
+
+        let salt = BASE64.decode(&envelope.salt).map_err(|_| decrypt_error())?;
+        let nonce = BASE64
+            .decode(&envelope.nonce)
+            .map_err(|_| decrypt_error())?;
+        let mut in_out = BASE64
+            .decode(&envelope.ciphertext)
+            .map_err(|_| decrypt_error())?;
+        let iterations = NonZeroU32::new(envelope.iterations).ok_or_else(decrypt_error)?;
+
+        let key = derive_key(passphrase, &salt, iterations);
+        let nonce = Nonce::try_assume_unique_for_key(&nonce).map_err(|_| decrypt_error())?;
+        let plaintext = key
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| decrypt_error())?;
+
+        Ok(serde_json::from_slice(plaintext)?)
+    }
+
+    fn display_path(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+}
+
+fn encrypt(token: &Token, passphrase: &str) -> anyhow::Result<EncryptedToken> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut salt)
+        .map_err(|_| anyhow::anyhow!("failed to generate salt"))?;
+    rng.fill(&mut nonce)
+        .map_err(|_| anyhow::anyhow!("failed to generate nonce"))?;
+
+    let iterations = NonZeroU32::new(KDF_ITERATIONS).expect("iterations must be non-zero");
+    let key = derive_key(passphrase, &salt, iterations);
+
+    let mut in_out = serde_json::to_vec(token)?;
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut in_out,
+    )
+    .map_err(|_| anyhow::anyhow!("failed to encrypt token"))?;
+
+    Ok(EncryptedToken {
+        version: ENVELOPE_VERSION,
+        cipher: CIPHER.to_string(),
+        kdf: KDF.to_string(),
+        iterations: KDF_ITERATIONS,
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(in_out),
+    })
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: NonZeroU32) -> LessSafeKey {
+    let mut key = [0u8; KEY_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).expect("key length matches AES-256"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_token() -> Token {
+        Token {
+            access_token: "access".to_string(),
+            refresh_token: Some("refresh".to_string()),
+            expires_at: Some(1_700_000_000),
+        }
+    }
+
+    #[test]
+    fn test_plain_round_trip() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let store = TokenStore::new(temp_dir.path().join("oauth"), None);
+
+        assert!(store.load()?.is_none());
+
+        store.save(&sample_token())?;
+        let loaded = store.load()?.unwrap();
+
+        assert_eq!(loaded.access_token, "access");
+        assert_eq!(loaded.refresh_token.as_deref(), Some("refresh"));
+        assert_eq!(loaded.expires_at, Some(1_700_000_000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_round_trip() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("oauth");
+        let store = TokenStore::new(&path, Some(TokenKeySource::Env("passphrase".to_string())));
+
+        store.save(&sample_token())?;
+
+        let raw = fs::read_to_string(&path)?;
+        assert!(
+            !raw.contains("refresh"),
+            "token must not be stored in clear"
+        );
+        assert!(raw.contains(CIPHER));
+
+        let loaded = store.load()?.unwrap();
+        assert_eq!(loaded.refresh_token.as_deref(), Some("refresh"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_with_key_command() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let store = TokenStore::new(
+            temp_dir.path().join("oauth"),
+            Some(TokenKeySource::Command(
+                "printf 'from-command\\nsecond line'".to_string(),
+            )),
+        );
+
+        store.save(&sample_token())?;
+        let with_env = TokenStore::new(
+            temp_dir.path().join("oauth"),
+            Some(TokenKeySource::Env("from-command".to_string())),
+        );
+
+        assert_eq!(with_env.load()?.unwrap().access_token, "access");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_wrong_key() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("oauth");
+        TokenStore::new(&path, Some(TokenKeySource::Env("right".to_string())))
+            .save(&sample_token())?;
+
+        let err = TokenStore::new(&path, Some(TokenKeySource::Env("wrong".to_string())))
+            .load()
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TokenStoreError>(),
+            Some(TokenStoreError::Decrypt(_))
+        ));
+
+        let err = TokenStore::new(&path, None).load().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TokenStoreError>(),
+            Some(TokenStoreError::MissingKey(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unreadable_or_corrupt_file_is_an_error() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+
+        let err = TokenStore::new(temp_dir.path(), None).load().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TokenStoreError>(),
+            Some(TokenStoreError::Read(..))
+        ));
+
+        let path = temp_dir.path().join("oauth");
+        fs::write(&path, "{ not json")?;
+        let err = TokenStore::new(&path, None).load().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TokenStoreError>(),
+            Some(TokenStoreError::Invalid(..))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plain_file_is_readable_with_key() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("oauth");
+        TokenStore::new(&path, None).save(&sample_token())?;
+
+        let store = TokenStore::new(&path, Some(TokenKeySource::Env("key".to_string())));
+        let token = store.load()?.unwrap();
+        store.save(&token)?;
+
+        assert!(fs::read_to_string(&path)?.contains("ciphertext"));
+        assert_eq!(store.load()?.unwrap().access_token, "access");
+
+        Ok(())
+    }
+}
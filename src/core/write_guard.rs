@@ -0,0 +1,492 @@
+use chrono::{DateTime, NaiveDate};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::config::{McpWrite, WriteTool};
+use crate::google::calendar::model::{CreatedEventResponse, EventDateTime, EventItem};
+use crate::google::calendar::service::{Mutation, PlannedRequest};
+use crate::shared::utils::date::{parse_bound, start_of_day, Bound};
+use crate::shared::utils::fs::append_private_line;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WriteGuardError {
+    #[error("{tool} may not write to calendar '{calendar_id}'. Add it to experimental.mcp.write.allowedCalendars.{key} to allow it.")]
+    CalendarNotAllowed {
+        tool: &'static str,
+        key: &'static str,
+        calendar_id: String,
+    },
+
+    #[error("This session already created {0} events, the limit set by experimental.mcp.write.maxEventsPerSession.")]
+    SessionLimitReached(usize),
+
+    #[error("Recurring events may not be written while experimental.mcp.write.forbiddenRanges is set, as only their first occurrence could be checked against it. Write a single event, or change one instance of the series.")]
+    RecurringWithForbiddenRanges,
+
+    #[error("{start} - {end} overlaps the forbidden range {since} - {until}.")]
+    ForbiddenRange {
+        start: String,
+        end: String,
+        since: String,
+        until: String,
+    },
+}
+
+/// What a write tool reports back.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum WriteOutcome {
+    /// Nothing was sent; `requests` is what would have been.
+    DryRun {
+        #[serde(rename = "dryRun")]
+        dry_run: bool,
+        requests: Vec<PlannedRequest>,
+    },
+    Written(CreatedEventResponse),
+    Deleted {
+        id: String,
+        deleted: bool,
+    },
+}
+
+/// One line of the audit log.
+#[derive(Debug, Serialize)]
+pub struct AuditEntry<'a> {
+    pub timestamp: String,
+    pub tool: &'static str,
+    pub calendar_id: &'a str,
+    pub event_id: Option<&'a str>,
+    pub requests: &'a [PlannedRequest],
+    pub before: Option<&'a EventItem>,
+    pub after: Option<&'a EventItem>,
+    /// Why the write failed. `after` then shows what it left behind, e.g. an
+    /// event rescheduled by a move that could not change calendars.
+    pub error: Option<String>,
+}
+
+/// Enforces `experimental.mcp.write` on the tools that change calendars.
+///
+/// Lives as long as the MCP server (one stdio session, or every HTTP
+/// session), which is what `maxEventsPerSession` counts against.
+pub struct WriteGuard {
+    policy: McpWrite,
+    created: AtomicUsize,
+}
+
+impl WriteGuard {
+    pub fn new(policy: McpWrite) -> Self {
+        Self {
+            policy,
+            created: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.policy.dry_run
+    }
+
+    /// Refuses `mutation` when it writes to a calendar the tool may not write
+    /// to, touches a forbidden range before or after the change, or exceeds
+    /// the session limit.
+    ///
+    /// An insert that passes takes its slot under the limit right away, so
+    /// concurrent requests cannot all get through; `release` gives it back
+    /// when the event is not created after all.
+    pub fn check(&self, mutation: &Mutation, tz: &Tz) -> Result<(), WriteGuardError> {
+        let tool = mutation.tool();
+        self.check_calendar(tool, mutation.calendar_id())?;
+        if let Mutation::Move {
+            destination: Some(destination),
+            ..
+        } = mutation
+        {
+            self.check_calendar(tool, destination)?;
+        }
+
+        let current = mutation.current();
+        if !self.policy.forbidden_ranges.is_empty() && is_recurring(mutation) {
+            return Err(WriteGuardError::RecurringWithForbiddenRanges);
+        }
+        if let Some(current) = current {
+            self.check_range(current.start.as_ref(), current.end.as_ref(), tz)?;
+        }
+        let (start, end) = mutation.new_times();
+        if start.is_some() || end.is_some() {
+            // A partial update keeps the other end of the event where it was.
+            let start = start.or_else(|| current.and_then(|ev| ev.start.as_ref()));
+            let end = end.or_else(|| current.and_then(|ev| ev.end.as_ref()));
+            self.check_range(start, end, tz)?;
+        }
+
+        if tool == WriteTool::Insert {
+            if let Some(max) = self.policy.max_events_per_session {
+                self.created
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |created| {
+                        (created < max).then_some(created + 1)
+                    })
+                    .map_err(|_| WriteGuardError::SessionLimitReached(max))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the slot `check` took for `mutation`, for an insert that was
+    /// only previewed or failed.
+    pub fn release(&self, mutation: &Mutation) {
+        if mutation.tool() == WriteTool::Insert && self.policy.max_events_per_session.is_some() {
+            self.created.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Appends `entry` to the audit log, if one is configured.
+    pub fn audit(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        if let Some(path) = &self.policy.audit_log_path {
+            append_private_line(Path::new(path), &serde_json::to_string(entry)?)?;
+        }
+        Ok(())
+    }
+
+    fn check_calendar(&self, tool: WriteTool, calendar_id: &str) -> Result<(), WriteGuardError> {
+        if self
+            .policy
+            .allowed_calendars(tool)
+            .iter()
+            .any(|allowed| allowed == calendar_id)
+        {
+            Ok(())
+        } else {
+            Err(WriteGuardError::CalendarNotAllowed {
+                tool: tool.tool_name(),
+                key: tool.config_key(),
+                calendar_id: calendar_id.to_string(),
+            })
+        }
+    }
+
+    fn check_range(
+        &self,
+        start: Option<&EventDateTime>,
+        end: Option<&EventDateTime>,
+        tz: &Tz,
+    ) -> Result<(), WriteGuardError> {
+        let (Some(start), Some(end)) = (
+            start.and_then(|start| instant(start, tz)),
+            end.and_then(|end| instant(end, tz)),
+        ) else {
+            return Ok(());
+        };
+
+        for range in &self.policy.forbidden_ranges {
+            // The ranges were validated when the config was loaded.
+            let (Ok(since), Ok(until)) = (
+                parse_bound(&range.since, tz, Bound::Start),
+                parse_bound(&range.until, tz, Bound::End),
+            ) else {
+                continue;
+            };
+            if start < until && since < end {
+                return Err(WriteGuardError::ForbiddenRange {
+                    start: start.format("%Y-%m-%d %H:%M").to_string(),
+                    end: end.format("%Y-%m-%d %H:%M").to_string(),
+                    since: range.since.clone(),
+                    until: range.until.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `mutation` creates a series or changes a whole one.
+fn is_recurring(mutation: &Mutation) -> bool {
+    let recurrence = match mutation {
+        Mutation::Insert { event, .. } => event.recurrence.as_ref(),
+        mutation => mutation
+            .current()
+            .and_then(|current| current.recurrence.as_ref()),
+    };
+    recurrence.is_some_and(|recurrence| !recurrence.is_empty())
+}
+
+/// The instant an event start/end stands for; all-day dates start at
+/// midnight in `tz`.
+fn instant(value: &EventDateTime, tz: &Tz) -> Option<DateTime<Tz>> {
+    if let Some(date_time) = &value.date_time {
+        return DateTime::parse_from_rfc3339(date_time)
+            .ok()
+            .map(|date_time| date_time.with_timezone(tz));
+    }
+    let date = NaiveDate::parse_from_str(value.date.as_deref()?, "%Y-%m-%d").ok()?;
+    Some(start_of_day(date, tz))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AllowedCalendars, ForbiddenRange};
+    use crate::google::calendar::model::{InsertEventRequest, PatchEventRequest};
+
+    fn policy() -> McpWrite {
+        McpWrite {
+            dry_run: false,
+            allowed_calendars: WriteTool::ALL
+                .iter()
+                .map(|&tool| AllowedCalendars {
+                    tool,
+                    calendar_ids: vec!["work@example.com".to_string()],
+                })
+                .collect(),
+            max_events_per_session: Some(1),
+            forbidden_ranges: vec![ForbiddenRange {
+                since: "2025-12-24".to_string(),
+                until: "2025-12-26".to_string(),
+            }],
+            audit_log_path: None,
+        }
+    }
+
+    fn timed(value: &str) -> EventDateTime {
+        EventDateTime {
+            date_time: Some(value.to_string()),
+            time_zone: None,
+            date: None,
+        }
+    }
+
+    fn insert(calendar_id: &str, start: &str, end: &str) -> Mutation {
+        Mutation::Insert {
+            calendar_id: calendar_id.to_string(),
+            event: InsertEventRequest {
+                summary: "Planning".to_string(),
+                description: None,
+                start: timed(start),
+                end: timed(end),
+                location: None,
+                attendees: None,
+                reminders: None,
+                color_id: None,
+                visibility: None,
+                recurrence: None,
+                conference_data: None,
+            },
+            send_updates: None,
+        }
+    }
+
+    fn event(start: &str, end: &str) -> EventItem {
+        serde_json::from_value(serde_json::json!({
+            "id": "evt1",
+            "start": { "dateTime": start },
+            "end": { "dateTime": end },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_check_allowlist_and_session_limit() {
+        let guard = WriteGuard::new(policy());
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+
+        let err = guard
+            .check(
+                &insert(
+                    "other@example.com",
+                    "2025-03-03T10:00:00+09:00",
+                    "2025-03-03T11:00:00+09:00",
+                ),
+                &tz,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "insert_calendar_event may not write to calendar 'other@example.com'. Add it to experimental.mcp.write.allowedCalendars.insertCalendarEvent to allow it."
+        );
+
+        let mutation = insert(
+            "work@example.com",
+            "2025-03-03T10:00:00+09:00",
+            "2025-03-03T11:00:00+09:00",
+        );
+        guard.check(&mutation, &tz).unwrap();
+        assert!(matches!(
+            guard.check(&mutation, &tz),
+            Err(WriteGuardError::SessionLimitReached(1))
+        ));
+        // A failed insert gives its slot back.
+        guard.release(&mutation);
+        guard.check(&mutation, &tz).unwrap();
+
+        let moved = Mutation::Move {
+            calendar_id: "work@example.com".to_string(),
+            event_id: "evt1".to_string(),
+            current: event("2025-03-03T10:00:00+09:00", "2025-03-03T11:00:00+09:00"),
+            patch: None,
+            destination: Some("personal@example.com".to_string()),
+        };
+        assert!(matches!(
+            guard.check(&moved, &tz),
+            Err(WriteGuardError::CalendarNotAllowed { calendar_id, .. }) if calendar_id == "personal@example.com"
+        ));
+    }
+
+    #[test]
+    fn test_session_limit_holds_under_concurrency() {
+        let guard = WriteGuard::new(McpWrite {
+            max_events_per_session: Some(3),
+            ..policy()
+        });
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+        let mutation = insert(
+            "work@example.com",
+            "2025-03-03T10:00:00+09:00",
+            "2025-03-03T11:00:00+09:00",
+        );
+
+        let passed: usize = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..16)
+                .map(|_| scope.spawn(|| guard.check(&mutation, &tz).is_ok()))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap() as usize)
+                .sum()
+        });
+        assert_eq!(passed, 3);
+    }
+
+    #[test]
+    fn test_check_forbidden_ranges() {
+        let guard = WriteGuard::new(policy());
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+
+        // The range ends with the whole of 2025-12-26.
+        assert!(matches!(
+            guard.check(
+                &insert(
+                    "work@example.com",
+                    "2025-12-26T23:00:00+09:00",
+                    "2025-12-27T01:00:00+09:00",
+                ),
+                &tz,
+            ),
+            Err(WriteGuardError::ForbiddenRange { .. })
+        ));
+        guard
+            .check(
+                &insert(
+                    "work@example.com",
+                    "2025-12-27T00:00:00+09:00",
+                    "2025-12-27T01:00:00+09:00",
+                ),
+                &tz,
+            )
+            .unwrap();
+
+        // Moving only the end of an event keeps its start, which is forbidden.
+        let update = Mutation::Update {
+            calendar_id: "work@example.com".to_string(),
+            event_id: "evt1".to_string(),
+            current: event("2025-12-25T10:00:00+09:00", "2025-12-25T11:00:00+09:00"),
+            patch: PatchEventRequest {
+                end: Some(timed("2025-12-25T12:00:00+09:00")),
+                ..PatchEventRequest::default()
+            },
+        };
+        assert!(guard.check(&update, &tz).is_err());
+
+        // Only the first occurrence of a series could be checked.
+        let Mutation::Insert {
+            calendar_id,
+            event: mut request,
+            send_updates,
+        } = insert(
+            "work@example.com",
+            "2025-03-03T10:00:00+09:00",
+            "2025-03-03T11:00:00+09:00",
+        )
+        else {
+            unreachable!()
+        };
+        request.recurrence = Some(vec!["RRULE:FREQ=WEEKLY;BYDAY=MO".to_string()]);
+        let weekly = Mutation::Insert {
+            calendar_id,
+            event: request,
+            send_updates,
+        };
+        assert!(matches!(
+            guard.check(&weekly, &tz),
+            Err(WriteGuardError::RecurringWithForbiddenRanges)
+        ));
+        let mut series = event("2025-03-03T10:00:00+09:00", "2025-03-03T11:00:00+09:00");
+        series.recurrence = Some(vec!["RRULE:FREQ=DAILY".to_string()]);
+        let delete_series = Mutation::Delete {
+            calendar_id: "work@example.com".to_string(),
+            event_id: "evt1".to_string(),
+            current: series,
+        };
+        assert!(matches!(
+            guard.check(&delete_series, &tz),
+            Err(WriteGuardError::RecurringWithForbiddenRanges)
+        ));
+        // Without forbidden ranges there is nothing to check them against.
+        let unrestricted = WriteGuard::new(McpWrite {
+            forbidden_ranges: vec![],
+            max_events_per_session: None,
+            ..policy()
+        });
+        unrestricted.check(&weekly, &tz).unwrap();
+        unrestricted.check(&delete_series, &tz).unwrap();
+
+        // Deleting an event inside the range is refused as well.
+        let delete = Mutation::Delete {
+            calendar_id: "work@example.com".to_string(),
+            event_id: "evt1".to_string(),
+            current: event("2025-12-24T10:00:00+09:00", "2025-12-24T11:00:00+09:00"),
+        };
+        assert!(guard.check(&delete, &tz).is_err());
+    }
+
+    #[test]
+    fn test_audit_appends_lines() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("audit.jsonl");
+        let guard = WriteGuard::new(McpWrite {
+            audit_log_path: Some(path.to_string_lossy().to_string()),
+            ..policy()
+        });
+
+        let before = event("2025-03-03T10:00:00+09:00", "2025-03-03T11:00:00+09:00");
+        for tool in ["update_calendar_event", "delete_calendar_event"] {
+            guard.audit(&AuditEntry {
+                timestamp: "2025-03-01T00:00:00+00:00".to_string(),
+                tool,
+                calendar_id: "work@example.com",
+                event_id: Some("evt1"),
+                requests: &[],
+                before: Some(&before),
+                after: None,
+                error: (tool == "delete_calendar_event").then(|| "http error".to_string()),
+            })?;
+        }
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["tool"], "update_calendar_event");
+        assert_eq!(lines[1]["tool"], "delete_calendar_event");
+        assert_eq!(
+            lines[1]["before"]["start"]["dateTime"],
+            "2025-03-03T10:00:00+09:00"
+        );
+        assert!(lines[1]["after"].is_null());
+        assert!(lines[0]["error"].is_null());
+        assert_eq!(lines[1]["error"], "http error");
+
+        Ok(())
+    }
+}
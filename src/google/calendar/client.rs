@@ -1,17 +1,27 @@
 use reqwest::Client;
 use thiserror::Error;
+use url::Url;
 
-use super::model::{CalendarEventsResponse, CreatedEventResponse, InsertEventRequest};
+use super::model::{
+    CalendarEventsResponse, CreatedEventResponse, EventItem, FreeBusyRequest, FreeBusyResponse,
+    InsertEventRequest, PatchEventRequest,
+};
 
 #[derive(Error, Debug)]
 pub enum GoogleCalendarError {
     #[error("http error: {0}")]
     HttpError(#[from] reqwest::Error),
+
+    #[error("'{0}' is not a valid calendar or event ID.")]
+    InvalidId(String),
 }
 
+const BASE_URL: &str = "https://www.googleapis.com/calendar/v3";
+
 pub struct GoogleCalendarClient {
     client: Client,
     access_token: String,
+    base_url: Url,
 }
 
 impl GoogleCalendarClient {
@@ -19,19 +29,85 @@ impl GoogleCalendarClient {
         GoogleCalendarClient {
             client: Client::new(),
             access_token: token.into(),
+            base_url: Url::parse(BASE_URL).unwrap(),
         }
     }
 
+    /// A client talking to `base_url` instead of the Calendar API, e.g. a
+    /// local stub.
+    #[cfg(test)]
+    pub fn with_base_url<T: Into<String>>(token: T, base_url: &str) -> Self {
+        GoogleCalendarClient {
+            base_url: Url::parse(base_url).unwrap(),
+            ..Self::new(token)
+        }
+    }
+
+    pub fn events_url(&self, calendar_id: &str) -> Result<String, GoogleCalendarError> {
+        Ok(self.api_url(&["calendars", checked_id(calendar_id)?, "events"]))
+    }
+
+    pub fn event_url(
+        &self,
+        calendar_id: &str,
+        event_id: &str,
+    ) -> Result<String, GoogleCalendarError> {
+        Ok(self.api_url(&[
+            "calendars",
+            checked_id(calendar_id)?,
+            "events",
+            checked_id(event_id)?,
+        ]))
+    }
+
+    pub fn move_url(
+        &self,
+        calendar_id: &str,
+        event_id: &str,
+    ) -> Result<String, GoogleCalendarError> {
+        Ok(self.api_url(&[
+            "calendars",
+            checked_id(calendar_id)?,
+            "events",
+            checked_id(event_id)?,
+            "move",
+        ]))
+    }
+
+    /// The API base URL followed by `segments`, each escaped so that it stays
+    /// one path segment, e.g. the `#` in
+    /// `ja.japanese#holiday@group.v.calendar.google.com` or a `/`.
+    fn api_url(&self, segments: &[&str]) -> String {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("the API base URL is an http(s) URL")
+            .pop_if_empty()
+            .extend(segments);
+        url.into()
+    }
+
+    /// The query parameters sent along with `new_event`.
+    pub fn insert_query<'a>(
+        new_event: &InsertEventRequest,
+        send_updates: Option<&'a str>,
+    ) -> Vec<(&'static str, &'a str)> {
+        let mut query = Vec::new();
+        if let Some(send_updates) = send_updates {
+            query.push(("sendUpdates", send_updates));
+        }
+        if new_event.conference_data.is_some() {
+            query.push(("conferenceDataVersion", "1"));
+        }
+        query
+    }
+
     pub async fn fetch_calendar_events(
         &self,
         calendar_id: &str,
         since: &str,
         until: &str,
     ) -> anyhow::Result<CalendarEventsResponse> {
-        let url = format!(
-            "https://www.googleapis.com/calendar/v3/calendars/{}/events",
-            calendar_id
-        );
+        let url = self.events_url(calendar_id)?;
 
         let response = self
             .client
@@ -52,20 +128,23 @@ impl GoogleCalendarClient {
         Ok(calendar_events_response)
     }
 
+    /// Inserts `new_event`. `send_updates` (`all`, `externalOnly` or `none`)
+    /// controls whether guests are emailed.
     pub async fn create_calendar_event(
         &self,
         calendar_id: &str,
         new_event: &InsertEventRequest,
+        send_updates: Option<&str>,
     ) -> anyhow::Result<CreatedEventResponse> {
-        let url = format!(
-            "https://www.googleapis.com/calendar/v3/calendars/{}/events",
-            calendar_id
-        );
+        let url = self.events_url(calendar_id)?;
+
+        let query = Self::insert_query(new_event, send_updates);
 
         let response = self
             .client
             .post(&url)
             .bearer_auth(&self.access_token)
+            .query(&query)
             .json(new_event)
             .send()
             .await?
@@ -74,4 +153,154 @@ impl GoogleCalendarClient {
         let created_event = response.json::<CreatedEventResponse>().await?;
         Ok(created_event)
     }
+
+    pub async fn get_calendar_event(
+        &self,
+        calendar_id: &str,
+        event_id: &str,
+    ) -> anyhow::Result<EventItem> {
+        let url = self.event_url(calendar_id, event_id)?;
+
+        let response = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.access_token)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let event = response.json::<EventItem>().await?;
+        Ok(event)
+    }
+
+    /// Updates only the fields set in `patch`.
+    pub async fn patch_calendar_event(
+        &self,
+        calendar_id: &str,
+        event_id: &str,
+        patch: &PatchEventRequest,
+    ) -> anyhow::Result<CreatedEventResponse> {
+        let url = self.event_url(calendar_id, event_id)?;
+
+        let response = self
+            .client
+            .patch(&url)
+            .bearer_auth(&self.access_token)
+            .json(patch)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let updated_event = response.json::<CreatedEventResponse>().await?;
+        Ok(updated_event)
+    }
+
+    /// Moves an event to another calendar (changing its organizer).
+    pub async fn move_calendar_event(
+        &self,
+        calendar_id: &str,
+        event_id: &str,
+        destination: &str,
+    ) -> anyhow::Result<CreatedEventResponse> {
+        let url = self.move_url(calendar_id, event_id)?;
+
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.access_token)
+            .query(&[("destination", destination)])
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let moved_event = response.json::<CreatedEventResponse>().await?;
+        Ok(moved_event)
+    }
+
+    pub async fn delete_calendar_event(
+        &self,
+        calendar_id: &str,
+        event_id: &str,
+    ) -> anyhow::Result<()> {
+        let url = self.event_url(calendar_id, event_id)?;
+
+        self.client
+            .delete(&url)
+            .bearer_auth(&self.access_token)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Returns the busy periods of each requested calendar or user, without
+    /// any event details.
+    pub async fn query_free_busy(
+        &self,
+        request: &FreeBusyRequest,
+    ) -> anyhow::Result<FreeBusyResponse> {
+        let response = self
+            .client
+            .post(self.api_url(&["freeBusy"]))
+            .bearer_auth(&self.access_token)
+            .json(request)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let free_busy = response.json::<FreeBusyResponse>().await?;
+        Ok(free_busy)
+    }
+}
+
+/// `id`, unless it is a path segment that could not address the calendar or
+/// event it names. `.` and `..` are refused as URL parsers resolve them even
+/// when escaped.
+fn checked_id(id: &str) -> Result<&str, GoogleCalendarError> {
+    if matches!(id, "" | "." | "..") {
+        return Err(GoogleCalendarError::InvalidId(id.to_string()));
+    }
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_stay_in_their_path_segment() {
+        let client = GoogleCalendarClient::with_base_url("token", "http://127.0.0.1:1");
+
+        assert_eq!(
+            client
+                .event_url("work@example.com", "../../other@example.com/events/E")
+                .unwrap(),
+            "http://127.0.0.1:1/calendars/work@example.com/events/..%2F..%2Fother@example.com%2Fevents%2FE"
+        );
+        assert_eq!(
+            client
+                .events_url("ja.japanese#holiday@group.v.calendar.google.com")
+                .unwrap(),
+            "http://127.0.0.1:1/calendars/ja.japanese%23holiday@group.v.calendar.google.com/events"
+        );
+        // The encoded ID is not resolved away.
+        let url = reqwest::Url::parse(
+            &client
+                .event_url("work@example.com", "../../other@example.com/events/E")
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(url
+            .path()
+            .starts_with("/calendars/work@example.com/events/"));
+
+        for id in ["", ".", ".."] {
+            assert!(matches!(
+                client.event_url("work@example.com", id),
+                Err(GoogleCalendarError::InvalidId(_))
+            ));
+            assert!(client.events_url(id).is_err());
+        }
+    }
 }
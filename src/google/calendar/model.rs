@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[allow(dead_code)]
 #[derive(Deserialize, Debug)]
@@ -36,7 +37,7 @@ pub struct DefaultReminder {
 }
 
 #[allow(dead_code)]
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct EventItem {
     #[serde(rename = "kind")]
     pub kind: Option<String>,
@@ -60,6 +61,9 @@ pub struct EventItem {
     pub location: Option<String>,
     #[serde(rename = "recurringEventId")]
     pub recurring_event_id: Option<String>,
+    /// Only set on the series itself, not on its instances.
+    #[serde(rename = "recurrence")]
+    pub recurrence: Option<Vec<String>>,
     #[serde(rename = "originalStartTime")]
     pub original_start_time: Option<EventDateTime>,
     #[serde(rename = "attendees")]
@@ -80,6 +84,8 @@ pub struct EventItem {
     pub start: Option<EventDateTime>,
     #[serde(rename = "end")]
     pub end: Option<EventDateTime>,
+    #[serde(rename = "transparency")]
+    pub transparency: Option<String>,
     #[serde(rename = "iCalUID")]
     pub i_cal_uid: Option<String>,
     #[serde(rename = "sequence")]
@@ -88,6 +94,11 @@ pub struct EventItem {
     pub reminders: Option<Reminders>,
     #[serde(rename = "eventType")]
     pub event_type: Option<String>,
+    #[serde(rename = "colorId")]
+    pub color_id: Option<String>,
+    /// The configured calendar the event was fetched from.
+    #[serde(skip)]
+    pub calendar_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -174,7 +185,7 @@ pub struct Attachment {
 }
 
 #[allow(dead_code)]
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct CalendarUser {
     #[serde(rename = "email")]
     pub email: Option<String>,
@@ -184,7 +195,7 @@ pub struct CalendarUser {
 }
 
 #[allow(dead_code)]
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Reminders {
     #[serde(rename = "useDefault")]
     pub use_default: Option<bool>,
@@ -193,15 +204,32 @@ pub struct Reminders {
 #[allow(dead_code)]
 #[derive(Deserialize, Debug, Clone, Serialize)]
 pub struct EventDateTime {
-    #[serde(rename = "dateTime")]
+    #[serde(rename = "dateTime", skip_serializing_if = "Option::is_none")]
     pub date_time: Option<String>,
-    #[serde(rename = "timeZone")]
+    #[serde(rename = "timeZone", skip_serializing_if = "Option::is_none")]
     pub time_zone: Option<String>,
-    #[serde(rename = "date")]
+    #[serde(rename = "date", skip_serializing_if = "Option::is_none")]
     pub date: Option<String>,
 }
 
 impl EventItem {
+    /// Whether the event is marked "free" and does not block time.
+    pub fn is_transparent(&self) -> bool {
+        self.transparency.as_deref() == Some("transparent")
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.status.as_deref() == Some("cancelled")
+    }
+
+    /// Whether the calendar's owner was invited and declined.
+    pub fn is_declined(&self) -> bool {
+        self.attendees.iter().flatten().any(|attendee| {
+            attendee.self_field == Some(true)
+                && attendee.response_status.as_deref() == Some("declined")
+        })
+    }
+
     pub fn is_all_day(&self) -> bool {
         if let Some(start) = &self.start {
             if start.date.is_some() {
@@ -245,6 +273,70 @@ pub struct InsertEventRequest {
     pub location: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub attendees: Option<Vec<AttendeeEmail>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reminders: Option<EventReminders>,
+    #[serde(rename = "colorId", skip_serializing_if = "Option::is_none")]
+    pub color_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+    /// RRULE, EXRULE, RDATE and EXDATE lines (RFC 5545).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Vec<String>>,
+    /// Only honored when the request is sent with `conferenceDataVersion=1`.
+    #[serde(rename = "conferenceData", skip_serializing_if = "Option::is_none")]
+    pub conference_data: Option<ConferenceDataRequest>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventReminders {
+    pub use_default: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<ReminderOverride>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ReminderOverride {
+    /// `popup` or `email`.
+    #[serde(default = "default_reminder_method")]
+    pub method: String,
+    pub minutes: u32,
+}
+
+fn default_reminder_method() -> String {
+    "popup".to_string()
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConferenceDataRequest {
+    pub create_request: CreateConferenceRequest,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateConferenceRequest {
+    /// Client-generated; retrying with the same ID does not create a second
+    /// conference.
+    pub request_id: String,
+    pub conference_solution_key: ConferenceSolutionKey,
+}
+
+/// A partial update; fields left as `None` are not sent and stay unchanged.
+#[derive(Debug, Default, Serialize)]
+pub struct PatchEventRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<EventDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<EventDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attendees: Option<Vec<AttendeeEmail>>,
 }
 
 #[derive(Debug, Serialize)]
@@ -254,17 +346,66 @@ pub struct AttendeeEmail {
 
 #[allow(dead_code)]
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CreatedEventResponse {
     pub id: String,
     pub summary: Option<String>,
     pub html_link: Option<String>,
+    /// The Google Meet link, when one was created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hangout_link: Option<String>,
     pub start: Option<EventDateTimeResponse>,
     pub end: Option<EventDateTimeResponse>,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct EventDateTimeResponse {
     pub date: Option<String>,
     pub date_time: Option<String>,
 }
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FreeBusyRequest {
+    pub time_min: String,
+    pub time_max: String,
+    pub items: Vec<FreeBusyRequestItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FreeBusyRequestItem {
+    pub id: String,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FreeBusyResponse {
+    pub time_min: Option<String>,
+    pub time_max: Option<String>,
+    #[serde(default)]
+    pub calendars: HashMap<String, FreeBusyCalendar>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct FreeBusyCalendar {
+    #[serde(default)]
+    pub busy: Vec<BusyPeriod>,
+    #[serde(default)]
+    pub errors: Vec<FreeBusyError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BusyPeriod {
+    pub start: String,
+    pub end: String,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct FreeBusyError {
+    pub domain: Option<String>,
+    pub reason: String,
+}
@@ -1,27 +1,211 @@
-use chrono::{Days, NaiveDate, NaiveDateTime, TimeZone};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime};
 use chrono_tz::Tz;
-use futures::future;
+use futures::{future, FutureExt};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
 
-use crate::config::Config;
+use crate::config::{Config, WriteTool};
 use crate::google::calendar::client::GoogleCalendarClient;
 use crate::google::calendar::model::{
-    CreatedEventResponse, EventDateTime, EventItem, InsertEventRequest,
+    AttendeeEmail, ConferenceDataRequest, ConferenceSolutionKey, CreateConferenceRequest,
+    CreatedEventResponse, EventDateTime, EventItem, EventReminders, FreeBusyRequest,
+    FreeBusyRequestItem, FreeBusyResponse, InsertEventRequest, PatchEventRequest, ReminderOverride,
 };
-use crate::shared::utils::date::to_utc_start_of_start_rfc3339;
+use crate::shared::utils::date::{resolve_local, LocalResolution};
 
 #[derive(Debug, thiserror::Error)]
 pub enum CalendarServiceError {
     #[error("No calendar_id configured. Please specify experimental.mcp.insertCalendarEvent.calendarID in your config.")]
     NoCalendarId,
+
+    #[error("At least one email address or calendar ID is required.")]
+    NoFreeBusyIds,
+
+    #[error("An event ID is required.")]
+    NoEventId,
+
+    #[error("Nothing to change. Specify a new start and/or a destination calendar.")]
+    NothingToMove,
+
+    #[error("Event {0} is an all-day event and cannot be moved to a time.")]
+    AllDayEvent(String),
+
+    #[error("Invalid {name} '{value}': {reason}")]
+    InvalidArgument {
+        name: &'static str,
+        value: String,
+        reason: String,
+    },
+
+    #[error("The event must end after it starts ({start} - {end}).")]
+    EndBeforeStart { start: String, end: String },
+
+    #[error("Event {event_id} was rescheduled but could not be moved to '{destination}', so it is still on '{calendar_id}' at its new time: {reason}")]
+    PartialMove {
+        event_id: String,
+        calendar_id: String,
+        destination: String,
+        reason: String,
+    },
+}
+
+const VISIBILITIES: [&str; 4] = ["default", "public", "private", "confidential"];
+const SEND_UPDATES: [&str; 3] = ["all", "externalOnly", "none"];
+const REMINDER_METHODS: [&str; 2] = ["popup", "email"];
+const RECURRENCE_PROPERTIES: [&str; 4] = ["RRULE", "EXRULE", "RDATE", "EXDATE"];
+/// Google rejects reminders more than four weeks before the event.
+const MAX_REMINDER_MINUTES: u32 = 40320;
+
+/// A validated change to a calendar that has not been sent yet.
+#[derive(Debug)]
+pub enum Mutation {
+    Insert {
+        calendar_id: String,
+        event: InsertEventRequest,
+        send_updates: Option<String>,
+    },
+    Update {
+        calendar_id: String,
+        event_id: String,
+        /// The event as it was when the change was planned.
+        current: EventItem,
+        patch: PatchEventRequest,
+    },
+    /// Reschedules the event with `patch` and/or moves it to `destination`.
+    Move {
+        calendar_id: String,
+        event_id: String,
+        current: EventItem,
+        patch: Option<PatchEventRequest>,
+        destination: Option<String>,
+    },
+    Delete {
+        calendar_id: String,
+        event_id: String,
+        current: EventItem,
+    },
+}
+
+impl Mutation {
+    pub fn tool(&self) -> WriteTool {
+        match self {
+            Mutation::Insert { .. } => WriteTool::Insert,
+            Mutation::Update { .. } => WriteTool::Update,
+            Mutation::Move { .. } => WriteTool::Move,
+            Mutation::Delete { .. } => WriteTool::Delete,
+        }
+    }
+
+    pub fn calendar_id(&self) -> &str {
+        match self {
+            Mutation::Insert { calendar_id, .. }
+            | Mutation::Update { calendar_id, .. }
+            | Mutation::Move { calendar_id, .. }
+            | Mutation::Delete { calendar_id, .. } => calendar_id,
+        }
+    }
+
+    pub fn event_id(&self) -> Option<&str> {
+        match self {
+            Mutation::Insert { .. } => None,
+            Mutation::Update { event_id, .. }
+            | Mutation::Move { event_id, .. }
+            | Mutation::Delete { event_id, .. } => Some(event_id),
+        }
+    }
+
+    /// The event before the change; `None` for insertions.
+    pub fn current(&self) -> Option<&EventItem> {
+        match self {
+            Mutation::Insert { .. } => None,
+            Mutation::Update { current, .. }
+            | Mutation::Move { current, .. }
+            | Mutation::Delete { current, .. } => Some(current),
+        }
+    }
+
+    /// The start and end the event will have afterwards, where they change.
+    pub fn new_times(&self) -> (Option<&EventDateTime>, Option<&EventDateTime>) {
+        match self {
+            Mutation::Insert { event, .. } => (Some(&event.start), Some(&event.end)),
+            Mutation::Update { patch, .. }
+            | Mutation::Move {
+                patch: Some(patch), ..
+            } => (patch.start.as_ref(), patch.end.as_ref()),
+            Mutation::Move { patch: None, .. } | Mutation::Delete { .. } => (None, None),
+        }
+    }
+}
+
+/// One HTTP request of a [`Mutation`], as shown by a dry run.
+#[derive(Debug, Serialize)]
+pub struct PlannedRequest {
+    pub method: &'static str,
+    pub url: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub query: BTreeMap<&'static str, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<Value>,
+}
+
+/// An event to insert.
+///
+/// `start`/`end` are `YYYY-MM-DD HH:MM`, or `YYYY-MM-DD` for all-day events,
+/// in which case `end` is the last day of the event.
+#[derive(Debug, Default)]
+pub struct NewEvent {
+    pub summary: String,
+    pub description: Option<String>,
+    pub start: String,
+    pub end: String,
+    /// The zone `start`/`end` are in. Defaults to `settings.tz`.
+    pub time_zone: Option<String>,
+    pub all_day: bool,
+    pub location: Option<String>,
+    pub attendees: Vec<String>,
+    /// `None` keeps the calendar's default reminders.
+    pub reminders: Option<Vec<ReminderOverride>>,
+    /// `1` to `11`, see the Calendar API `colors` endpoint.
+    pub color_id: Option<String>,
+    pub visibility: Option<String>,
+    /// e.g. `RRULE:FREQ=WEEKLY;BYDAY=MO`; a bare rule gets the `RRULE:` prefix.
+    pub recurrence: Vec<String>,
+    pub send_updates: Option<String>,
+    /// Attach a new Google Meet conference.
+    pub create_meet: bool,
 }
 
+/// Changes to an existing event. Fields left as `None` stay unchanged;
+/// `attendees` replaces the whole guest list.
+#[derive(Debug, Default)]
+pub struct EventChanges {
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub location: Option<String>,
+    pub attendees: Option<Vec<String>>,
+    /// The zone `start`/`end` are in. Defaults to `settings.tz`.
+    pub time_zone: Option<String>,
+}
+
+/// Told `(fetched, total)` each time one of the calendars of a fetch has
+/// been read.
+pub type FetchProgress = mpsc::UnboundedSender<(usize, usize)>;
+
 pub struct GoogleCalendarService {
-    config: Config,
+    config: Arc<Config>,
     calendar_client: GoogleCalendarClient,
 }
 
 impl GoogleCalendarService {
-    pub fn new(config: Config, access_token: String) -> Self {
+    pub fn new(config: Arc<Config>, access_token: String) -> Self {
         let calendar_client = GoogleCalendarClient::new(access_token);
         Self {
             config,
@@ -29,108 +213,432 @@ impl GoogleCalendarService {
         }
     }
 
-    pub async fn create_calendar_event(
+    /// Validates `event` and prepares its insertion into the insert calendar.
+    pub fn plan_insert(&self, event: NewEvent) -> anyhow::Result<Mutation> {
+        let (start, end) = if event.all_day {
+            all_day_range(&event.start, &event.end)?
+        } else {
+            let tz = self.event_tz(event.time_zone.as_deref())?;
+            let start = local_date_time(&event.start, &tz)?;
+            let end = local_date_time(&event.end, &tz)?;
+            ensure_end_after_start(start, end)?;
+            (to_event_date_time(start), to_event_date_time(end))
+        };
+
+        let reminders = event.reminders.map(validate_reminders).transpose()?;
+        let color_id = event.color_id.map(validate_color_id).transpose()?;
+        let visibility = event
+            .visibility
+            .map(|value| validate_choice("visibility", value, &VISIBILITIES))
+            .transpose()?;
+        let send_updates = event
+            .send_updates
+            .map(|value| validate_choice("sendUpdates", value, &SEND_UPDATES))
+            .transpose()?;
+        let recurrence = event
+            .recurrence
+            .into_iter()
+            .map(normalize_recurrence)
+            .collect::<Result<Vec<_>, _>>()?;
+        let conference_data = if event.create_meet {
+            Some(ConferenceDataRequest {
+                create_request: CreateConferenceRequest {
+                    request_id: conference_request_id()?,
+                    conference_solution_key: ConferenceSolutionKey {
+                        key_type: Some("hangoutsMeet".to_string()),
+                    },
+                },
+            })
+        } else {
+            None
+        };
+
+        let calendar_id = self.target_calendar_id(None)?;
+
+        Ok(Mutation::Insert {
+            calendar_id,
+            event: InsertEventRequest {
+                summary: event.summary,
+                start,
+                end,
+                location: event.location,
+                description: event.description,
+                attendees: (!event.attendees.is_empty()).then(|| {
+                    event
+                        .attendees
+                        .into_iter()
+                        .map(|email| AttendeeEmail { email })
+                        .collect()
+                }),
+                reminders: reminders.map(|overrides| EventReminders {
+                    use_default: false,
+                    overrides,
+                }),
+                color_id,
+                visibility,
+                recurrence: (!recurrence.is_empty()).then_some(recurrence),
+                conference_data,
+            },
+            send_updates,
+        })
+    }
+
+    /// Prepares applying `changes` to an event on `calendar_id` (default: the
+    /// insert calendar).
+    pub async fn plan_update(
         &self,
-        summary: &str,
-        description: Option<String>,
-        start: &str,
-        end: &str,
-    ) -> anyhow::Result<CreatedEventResponse> {
-        let tz: Tz =
-            self.config.settings.tz.parse().unwrap_or_else(|_| {
-                panic!("Invalid time zone string '{}'", self.config.settings.tz)
-            });
+        calendar_id: Option<&str>,
+        event_id: &str,
+        changes: EventChanges,
+    ) -> anyhow::Result<Mutation> {
+        if event_id.is_empty() {
+            return Err(CalendarServiceError::NoEventId.into());
+        }
+        let calendar_id = self.target_calendar_id(calendar_id)?;
 
-        let start_naive_date = NaiveDateTime::parse_from_str(start, "%Y-%m-%d %H:%M")?;
-        let end_naive_date = NaiveDateTime::parse_from_str(end, "%Y-%m-%d %H:%M")?;
+        let tz = self.event_tz(changes.time_zone.as_deref())?;
+        let start = changes
+            .start
+            .map(|start| local_date_time(&start, &tz))
+            .transpose()?;
+        let end = changes
+            .end
+            .map(|end| local_date_time(&end, &tz))
+            .transpose()?;
 
-        let start_with_tz = tz.from_local_datetime(&start_naive_date).unwrap();
-        let end_with_tz = tz.from_local_datetime(&end_naive_date).unwrap();
+        let current = self
+            .calendar_client
+            .get_calendar_event(&calendar_id, event_id)
+            .await?;
 
-        let start_rfc3339 = start_with_tz.to_rfc3339();
-        let end_rfc3339 = end_with_tz.to_rfc3339();
+        // A side that is not patched keeps its current time.
+        let new_start = start.or_else(|| Some(current.start_time_utc()?.with_timezone(&tz)));
+        let new_end = end.or_else(|| Some(current.end_time_utc()?.with_timezone(&tz)));
+        if let (Some(new_start), Some(new_end)) = (new_start, new_end) {
+            ensure_end_after_start(new_start, new_end)?;
+        }
 
-        let Some(calendar_id) = &self
-            .config
-            .experimental
-            .mcp
-            .insert_calendar_event
-            .calendar_id
-        else {
-            return Err(CalendarServiceError::NoCalendarId.into());
+        let patch = PatchEventRequest {
+            summary: changes.summary,
+            description: changes.description,
+            start: start.map(to_event_date_time),
+            end: end.map(to_event_date_time),
+            location: changes.location,
+            attendees: changes.attendees.map(|emails| {
+                emails
+                    .into_iter()
+                    .map(|email| AttendeeEmail { email })
+                    .collect()
+            }),
         };
 
-        let res = self
+        Ok(Mutation::Update {
+            calendar_id,
+            event_id: event_id.to_string(),
+            current,
+            patch,
+        })
+    }
+
+    /// Prepares rescheduling an event to `start`, keeping its length, and/or
+    /// moving it to the `destination` calendar.
+    pub async fn plan_move(
+        &self,
+        calendar_id: Option<&str>,
+        event_id: &str,
+        start: Option<&str>,
+        destination: Option<&str>,
+    ) -> anyhow::Result<Mutation> {
+        if event_id.is_empty() {
+            return Err(CalendarServiceError::NoEventId.into());
+        }
+        if start.is_none() && destination.is_none() {
+            return Err(CalendarServiceError::NothingToMove.into());
+        }
+        let calendar_id = self.target_calendar_id(calendar_id)?;
+
+        let current = self
             .calendar_client
-            .create_calendar_event(
-                calendar_id,
-                &InsertEventRequest {
-                    summary: summary.to_string(),
-                    start: EventDateTime {
-                        date_time: Some(start_rfc3339),
-                        time_zone: Some("Asia/Tokyo".to_string()),
-                        date: None,
-                    },
-                    end: EventDateTime {
-                        date_time: Some(end_rfc3339),
-                        time_zone: Some("Asia/Tokyo".to_string()),
-                        date: None,
-                    },
-                    location: None,
-                    description,
-                    attendees: None, // TODO: add attendees
-                },
-            )
+            .get_calendar_event(&calendar_id, event_id)
             .await?;
 
-        Ok(res)
+        let patch = match start {
+            Some(start) => {
+                let (Some(old_start), Some(old_end)) =
+                    (current.start_time_utc(), current.end_time_utc())
+                else {
+                    return Err(CalendarServiceError::AllDayEvent(event_id.to_string()).into());
+                };
+
+                let new_start = local_date_time(start, &self.event_tz(None)?)?;
+                let new_end = new_start + (old_end - old_start);
+                ensure_end_after_start(new_start, new_end)?;
+                Some(PatchEventRequest {
+                    start: Some(to_event_date_time(new_start)),
+                    end: Some(to_event_date_time(new_end)),
+                    ..PatchEventRequest::default()
+                })
+            }
+            None => None,
+        };
+
+        Ok(Mutation::Move {
+            calendar_id,
+            event_id: event_id.to_string(),
+            current,
+            patch,
+            destination: destination.map(String::from),
+        })
     }
 
-    pub async fn get_calendar_events(
+    pub async fn plan_delete(
         &self,
-        since: &str,
-        until: &str,
-    ) -> anyhow::Result<Vec<EventItem>> {
-        let tz: Tz =
-            self.config.settings.tz.parse().unwrap_or_else(|_| {
-                panic!("Invalid time zone string '{}'", self.config.settings.tz)
-            });
+        calendar_id: Option<&str>,
+        event_id: &str,
+    ) -> anyhow::Result<Mutation> {
+        if event_id.is_empty() {
+            return Err(CalendarServiceError::NoEventId.into());
+        }
+        let calendar_id = self.target_calendar_id(calendar_id)?;
 
-        let since_naive_date = NaiveDate::parse_from_str(since, "%Y-%m-%d")?
-            .and_hms_opt(0, 0, 0)
-            .unwrap();
-        let until_naive_date = NaiveDate::parse_from_str(until, "%Y-%m-%d")?
-            .and_hms_opt(0, 0, 0)
-            .unwrap();
-        let since_with_tz = tz.from_local_datetime(&since_naive_date).unwrap();
-        let until_with_tz = tz.from_local_datetime(&until_naive_date).unwrap();
+        let current = self
+            .calendar_client
+            .get_calendar_event(&calendar_id, event_id)
+            .await?;
 
-        let until_plus_one = until_with_tz.checked_add_days(Days::new(1)).unwrap();
-        let since_rfc3339 = to_utc_start_of_start_rfc3339(since_with_tz);
-        let until_rfc3339 = to_utc_start_of_start_rfc3339(until_plus_one);
+        Ok(Mutation::Delete {
+            calendar_id,
+            event_id: event_id.to_string(),
+            current,
+        })
+    }
 
-        let mut fetch_futures = Vec::new();
-        for calendar_id in &self.config.source.google.calendar.get_events.calendar_ids {
-            let fut = self.calendar_client.fetch_calendar_events(
+    /// Sends `mutation`. Returns the written event, or `None` once deleted.
+    pub async fn execute(
+        &self,
+        mutation: &Mutation,
+    ) -> anyhow::Result<Option<CreatedEventResponse>> {
+        match mutation {
+            Mutation::Insert {
                 calendar_id,
-                &since_rfc3339,
-                &until_rfc3339,
-            );
+                event,
+                send_updates,
+            } => Ok(Some(
+                self.calendar_client
+                    .create_calendar_event(calendar_id, event, send_updates.as_deref())
+                    .await?,
+            )),
+            Mutation::Update {
+                calendar_id,
+                event_id,
+                patch,
+                ..
+            } => Ok(Some(
+                self.calendar_client
+                    .patch_calendar_event(calendar_id, event_id, patch)
+                    .await?,
+            )),
+            Mutation::Move {
+                calendar_id,
+                event_id,
+                patch,
+                destination,
+                ..
+            } => {
+                let mut res = None;
+                if let Some(patch) = patch {
+                    res = Some(
+                        self.calendar_client
+                            .patch_calendar_event(calendar_id, event_id, patch)
+                            .await?,
+                    );
+                }
+                if let Some(destination) = destination {
+                    let moved = self
+                        .calendar_client
+                        .move_calendar_event(calendar_id, event_id, destination)
+                        .await;
+                    res = Some(match moved {
+                        Ok(moved) => moved,
+                        // The patch has been applied and is not rolled back.
+                        Err(err) if res.is_some() => {
+                            return Err(CalendarServiceError::PartialMove {
+                                event_id: event_id.clone(),
+                                calendar_id: calendar_id.clone(),
+                                destination: destination.clone(),
+                                reason: format!("{:#}", err),
+                            }
+                            .into())
+                        }
+                        Err(err) => return Err(err),
+                    });
+                }
+                Ok(res)
+            }
+            Mutation::Delete {
+                calendar_id,
+                event_id,
+                ..
+            } => {
+                self.calendar_client
+                    .delete_calendar_event(calendar_id, event_id)
+                    .await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// The HTTP requests [`GoogleCalendarService::execute`] would send.
+    pub fn preview(&self, mutation: &Mutation) -> anyhow::Result<Vec<PlannedRequest>> {
+        let client = &self.calendar_client;
+        let requests = match mutation {
+            Mutation::Insert {
+                calendar_id,
+                event,
+                send_updates,
+            } => vec![PlannedRequest {
+                method: "POST",
+                url: client.events_url(calendar_id)?,
+                query: GoogleCalendarClient::insert_query(event, send_updates.as_deref())
+                    .into_iter()
+                    .map(|(name, value)| (name, value.to_string()))
+                    .collect(),
+                body: Some(serde_json::to_value(event)?),
+            }],
+            Mutation::Update {
+                calendar_id,
+                event_id,
+                patch,
+                ..
+            } => vec![PlannedRequest {
+                method: "PATCH",
+                url: client.event_url(calendar_id, event_id)?,
+                query: BTreeMap::new(),
+                body: Some(serde_json::to_value(patch)?),
+            }],
+            Mutation::Move {
+                calendar_id,
+                event_id,
+                patch,
+                destination,
+                ..
+            } => {
+                let mut requests = Vec::new();
+                if let Some(patch) = patch {
+                    requests.push(PlannedRequest {
+                        method: "PATCH",
+                        url: client.event_url(calendar_id, event_id)?,
+                        query: BTreeMap::new(),
+                        body: Some(serde_json::to_value(patch)?),
+                    });
+                }
+                if let Some(destination) = destination {
+                    requests.push(PlannedRequest {
+                        method: "POST",
+                        url: client.move_url(calendar_id, event_id)?,
+                        query: BTreeMap::from([("destination", destination.clone())]),
+                        body: None,
+                    });
+                }
+                requests
+            }
+            Mutation::Delete {
+                calendar_id,
+                event_id,
+                ..
+            } => vec![PlannedRequest {
+                method: "DELETE",
+                url: client.event_url(calendar_id, event_id)?,
+                query: BTreeMap::new(),
+                body: None,
+            }],
+        };
+        Ok(requests)
+    }
+
+    pub async fn get_event(&self, calendar_id: &str, event_id: &str) -> anyhow::Result<EventItem> {
+        self.calendar_client
+            .get_calendar_event(calendar_id, event_id)
+            .await
+    }
+
+    /// The calendar to write to: `calendar_id` when given, otherwise
+    /// `experimental.mcp.insertCalendarEvent.calendarID`.
+    fn target_calendar_id(&self, calendar_id: Option<&str>) -> anyhow::Result<String> {
+        match calendar_id {
+            Some(calendar_id) => Ok(calendar_id.to_string()),
+            None => self
+                .config
+                .experimental
+                .mcp
+                .insert_calendar_event
+                .calendar_id
+                .clone()
+                .ok_or_else(|| CalendarServiceError::NoCalendarId.into()),
+        }
+    }
+
+    /// `time_zone` when given, otherwise `settings.tz`.
+    fn event_tz(&self, time_zone: Option<&str>) -> Result<Tz, CalendarServiceError> {
+        match time_zone {
+            Some(time_zone) => {
+                time_zone
+                    .parse()
+                    .map_err(|_| CalendarServiceError::InvalidArgument {
+                        name: "timeZone",
+                        value: time_zone.to_string(),
+                        reason: "expected an IANA time zone such as Europe/Berlin".to_string(),
+                    })
+            }
+            None => Ok(self.config.settings.tz.parse().unwrap_or_else(|_| {
+                panic!("Invalid time zone string '{}'", self.config.settings.tz)
+            })),
+        }
+    }
+
+    /// Fetches events overlapping `[since, until)` from each of `calendar_ids`.
+    /// A calendar that fails is reported and skipped.
+    pub async fn get_calendar_events(
+        &self,
+        calendar_ids: &[String],
+        since: DateTime<Tz>,
+        until: DateTime<Tz>,
+        progress: Option<&FetchProgress>,
+    ) -> anyhow::Result<Vec<EventItem>> {
+        let since_rfc3339 = since.to_utc().to_rfc3339();
+        let until_rfc3339 = until.to_utc().to_rfc3339();
+
+        let fetched = AtomicUsize::new(0);
+        let mut fetch_futures = Vec::new();
+        for calendar_id in calendar_ids {
+            let fut = self
+                .calendar_client
+                .fetch_calendar_events(calendar_id, &since_rfc3339, &until_rfc3339)
+                .inspect(|_| {
+                    if let Some(progress) = progress {
+                        let fetched = fetched.fetch_add(1, Ordering::SeqCst) + 1;
+                        // Nobody is listening any more once the request is gone.
+                        let _ = progress.send((fetched, calendar_ids.len()));
+                    }
+                });
             fetch_futures.push(fut);
         }
 
         let results = future::join_all(fetch_futures).await;
 
         let mut all_events: Vec<EventItem> = Vec::new();
-        for (i, result) in results.into_iter().enumerate() {
+        for (calendar_id, result) in calendar_ids.iter().zip(results) {
             match result {
                 Ok(mut res) => {
+                    for item in &mut res.items {
+                        item.calendar_id = Some(calendar_id.clone());
+                    }
                     all_events.append(&mut res.items);
                 }
                 Err(e) => {
                     eprintln!(
                         "Error fetching events from calendar_id={}: {}",
-                        &self.config.source.google.calendar.get_events.calendar_ids[i], e
+                        calendar_id, e
                     );
                 }
             }
@@ -138,4 +646,657 @@ impl GoogleCalendarService {
 
         Ok(all_events)
     }
+
+    /// Queries the busy periods of `ids` (calendar IDs or email addresses)
+    /// within `[since, until)`.
+    pub async fn get_free_busy(
+        &self,
+        ids: &[String],
+        since: DateTime<Tz>,
+        until: DateTime<Tz>,
+    ) -> anyhow::Result<FreeBusyResponse> {
+        if ids.is_empty() {
+            return Err(CalendarServiceError::NoFreeBusyIds.into());
+        }
+
+        let request = FreeBusyRequest {
+            time_min: since.to_utc().to_rfc3339(),
+            time_max: until.to_utc().to_rfc3339(),
+            items: ids
+                .iter()
+                .map(|id| FreeBusyRequestItem { id: id.clone() })
+                .collect(),
+        };
+
+        self.calendar_client.query_free_busy(&request).await
+    }
+}
+
+/// Parses a `YYYY-MM-DD HH:MM` wall-clock time in `tz`.
+fn local_date_time(value: &str, tz: &Tz) -> anyhow::Result<DateTime<Tz>> {
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M")?;
+
+    // A skipped or repeated wall-clock time is refused rather than guessed,
+    // so the event never lands an hour away from what was asked for.
+    Ok(resolve_local(tz, naive, LocalResolution::Reject)?)
+}
+
+/// Rejects a timed range that does not end after it starts.
+fn ensure_end_after_start(
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+) -> Result<(), CalendarServiceError> {
+    if end <= start {
+        return Err(CalendarServiceError::EndBeforeStart {
+            start: start.format("%Y-%m-%d %H:%M").to_string(),
+            end: end.format("%Y-%m-%d %H:%M").to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// A timed start/end carrying the zone name, which Google needs to expand
+/// recurring events across DST changes.
+fn to_event_date_time(date_time: DateTime<Tz>) -> EventDateTime {
+    EventDateTime {
+        date_time: Some(date_time.to_rfc3339()),
+        time_zone: Some(date_time.timezone().name().to_string()),
+        date: None,
+    }
+}
+
+/// All-day `start`/`end` dates, with `end` moved to the exclusive next day
+/// Google expects.
+fn all_day_range(start: &str, end: &str) -> anyhow::Result<(EventDateTime, EventDateTime)> {
+    let parse = |name: &'static str, value: &str| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+            CalendarServiceError::InvalidArgument {
+                name,
+                value: value.to_string(),
+                reason: "all-day events take a YYYY-MM-DD date".to_string(),
+            }
+        })
+    };
+    let start_date = parse("start", start)?;
+    let end_date = parse("end", end)?;
+    if end_date < start_date {
+        return Err(CalendarServiceError::EndBeforeStart {
+            start: start.to_string(),
+            end: end.to_string(),
+        }
+        .into());
+    }
+
+    let date = |date: NaiveDate| EventDateTime {
+        date_time: None,
+        time_zone: None,
+        date: Some(date.to_string()),
+    };
+    Ok((date(start_date), date(end_date + Duration::days(1))))
+}
+
+fn validate_choice(
+    name: &'static str,
+    value: String,
+    choices: &[&str],
+) -> Result<String, CalendarServiceError> {
+    if choices.contains(&value.as_str()) {
+        Ok(value)
+    } else {
+        Err(CalendarServiceError::InvalidArgument {
+            name,
+            value,
+            reason: format!("expected one of {}", choices.join(", ")),
+        })
+    }
+}
+
+fn validate_color_id(value: String) -> Result<String, CalendarServiceError> {
+    match value.parse::<u8>() {
+        Ok(1..=11) => Ok(value),
+        _ => Err(CalendarServiceError::InvalidArgument {
+            name: "colorId",
+            value,
+            reason: "expected a number from 1 to 11".to_string(),
+        }),
+    }
+}
+
+fn validate_reminders(
+    reminders: Vec<ReminderOverride>,
+) -> Result<Vec<ReminderOverride>, CalendarServiceError> {
+    for reminder in &reminders {
+        validate_choice(
+            "reminder method",
+            reminder.method.clone(),
+            &REMINDER_METHODS,
+        )?;
+        if reminder.minutes > MAX_REMINDER_MINUTES {
+            return Err(CalendarServiceError::InvalidArgument {
+                name: "reminder minutes",
+                value: reminder.minutes.to_string(),
+                reason: format!("must be at most {}", MAX_REMINDER_MINUTES),
+            });
+        }
+    }
+    Ok(reminders)
+}
+
+fn normalize_recurrence(line: String) -> Result<String, CalendarServiceError> {
+    let line = line.trim();
+    let line = if line.contains(':') {
+        line.to_string()
+    } else {
+        format!("RRULE:{}", line)
+    };
+
+    // The property name ends at its value or at its first parameter.
+    let property = line.split([':', ';']).next().unwrap_or_default();
+    let valid = RECURRENCE_PROPERTIES.contains(&property)
+        && (property != "RRULE" || line.contains("FREQ="));
+    if valid {
+        Ok(line)
+    } else {
+        Err(CalendarServiceError::InvalidArgument {
+            name: "recurrence",
+            value: line,
+            reason: "expected an RFC 5545 line such as RRULE:FREQ=WEEKLY;BYDAY=MO".to_string(),
+        })
+    }
+}
+
+/// A random ID for a conference create request.
+fn conference_request_id() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("failed to generate a conference request ID"))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        Experimental, GoogleCalendar, GoogleCalendarGetEvents, GoogleOAuth2, GoogleSource,
+        InsertCalendarEvent, Mcp, McpHttp, McpResources, McpWrite, Output, Settings, Source,
+    };
+    use crate::shared::utils::stub_http;
+    use chrono::Weekday;
+    use tokio::net::TcpListener;
+
+    const CREATED_EVENT: &str =
+        r#"{"id":"evt1","htmlLink":"https://calendar.google.com/event?eid=evt1"}"#;
+    const EXISTING_EVENT: &str = r#"{
+        "id": "evt1",
+        "summary": "Planning",
+        "start": { "dateTime": "2025-03-03T10:00:00+09:00" },
+        "end": { "dateTime": "2025-03-03T11:00:00+09:00" }
+    }"#;
+
+    fn test_config(tz: &str) -> Config {
+        Config {
+            source: Source {
+                google: GoogleSource {
+                    oauth2: GoogleOAuth2 {
+                        client_id: String::new(),
+                        client_secret: String::new(),
+                        redirect_url: String::new(),
+                        scopes: vec![],
+                    },
+                    service_account: None,
+                    calendar: GoogleCalendar {
+                        get_events: GoogleCalendarGetEvents {
+                            calendar_ids: vec!["primary".to_string()],
+                        },
+                    },
+                },
+            },
+            output: Output {
+                template: String::new(),
+                include_empty_days: false,
+                templates: vec![],
+            },
+            settings: Settings {
+                tz: tz.to_string(),
+                oauth_file_path: String::new(),
+                token_key_command: None,
+                token_refresh_leeway_seconds: 60,
+                week_start: Weekday::Mon,
+                workdays: vec![],
+                display_timezones: vec![],
+                working_hours: vec![],
+                min_free_slot_minutes: 30,
+                meeting_budget_minutes: None,
+            },
+            experimental: Experimental {
+                mcp: Mcp {
+                    insert_calendar_event: InsertCalendarEvent {
+                        calendar_id: Some("work@example.com".to_string()),
+                    },
+                    write: McpWrite {
+                        dry_run: false,
+                        allowed_calendars: vec![],
+                        max_events_per_session: None,
+                        forbidden_ranges: vec![],
+                        audit_log_path: None,
+                    },
+                    resources: McpResources {
+                        poll_interval_seconds: 60,
+                    },
+                    http: McpHttp {
+                        bearer_token: None,
+                        allowed_origins: vec![],
+                        max_sessions: 32,
+                        session_idle_timeout_seconds: 1800,
+                    },
+                },
+            },
+        }
+    }
+
+    /// A service whose client talks to a stub listening on `listener`.
+    fn stub_service(tz: &str, listener: &TcpListener) -> GoogleCalendarService {
+        GoogleCalendarService {
+            config: Arc::new(test_config(tz)),
+            calendar_client: GoogleCalendarClient::with_base_url(
+                "test-token",
+                &format!("http://{}", listener.local_addr().unwrap()),
+            ),
+        }
+    }
+
+    fn timed_event(start: &str, end: &str) -> NewEvent {
+        NewEvent {
+            summary: "Planning".to_string(),
+            start: start.to_string(),
+            end: end.to_string(),
+            ..NewEvent::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_insert_event_uses_configured_time_zone() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = stub_service("America/New_York", &listener);
+        let server = tokio::spawn(stub_http::serve(listener, vec![("200 OK", CREATED_EVENT)]));
+
+        let mutation = service
+            .plan_insert(timed_event("2025-03-10 09:00", "2025-03-10 10:00"))
+            .unwrap();
+        let planned = service.preview(&mutation).unwrap();
+        let res = service.execute(&mutation).await.unwrap().unwrap();
+        let request = server.await.unwrap().remove(0);
+        let (body, request_line) = (request.json(), request.request_line);
+
+        assert_eq!(res.id, "evt1");
+        assert_eq!(
+            request_line,
+            "POST /calendars/work@example.com/events HTTP/1.1"
+        );
+        assert_eq!(body["summary"], "Planning");
+        // New York switched to daylight saving time the day before.
+        assert_eq!(body["start"]["dateTime"], "2025-03-10T09:00:00-04:00");
+        assert_eq!(body["start"]["timeZone"], "America/New_York");
+        assert_eq!(body["end"]["dateTime"], "2025-03-10T10:00:00-04:00");
+        assert_eq!(body["end"]["timeZone"], "America/New_York");
+        // A dry run reports exactly what was sent.
+        assert_eq!(planned.len(), 1);
+        assert_eq!(planned[0].method, "POST");
+        assert!(planned[0]
+            .url
+            .ends_with("/calendars/work@example.com/events"));
+        assert_eq!(planned[0].body.as_ref(), Some(&body));
+    }
+
+    #[tokio::test]
+    async fn test_insert_event_with_explicit_time_zone() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = stub_service("Asia/Tokyo", &listener);
+        let server = tokio::spawn(stub_http::serve(listener, vec![("200 OK", CREATED_EVENT)]));
+
+        let mutation = service
+            .plan_insert(NewEvent {
+                time_zone: Some("Europe/Berlin".to_string()),
+                recurrence: vec!["FREQ=WEEKLY;BYDAY=MO".to_string()],
+                ..timed_event("2025-01-06 09:00", "2025-01-06 09:30")
+            })
+            .unwrap();
+        service.execute(&mutation).await.unwrap();
+        let body = server.await.unwrap()[0].json();
+
+        assert_eq!(body["start"]["dateTime"], "2025-01-06T09:00:00+01:00");
+        assert_eq!(body["start"]["timeZone"], "Europe/Berlin");
+        assert_eq!(body["end"]["timeZone"], "Europe/Berlin");
+        assert_eq!(
+            body["recurrence"],
+            serde_json::json!(["RRULE:FREQ=WEEKLY;BYDAY=MO"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_insert_all_day_event_with_meet() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = stub_service("Asia/Tokyo", &listener);
+        let server = tokio::spawn(stub_http::serve(listener, vec![("200 OK", CREATED_EVENT)]));
+
+        let mutation = service
+            .plan_insert(NewEvent {
+                summary: "Offsite".to_string(),
+                start: "2025-05-01".to_string(),
+                end: "2025-05-02".to_string(),
+                all_day: true,
+                attendees: vec!["alice@example.com".to_string()],
+                reminders: Some(vec![]),
+                send_updates: Some("all".to_string()),
+                create_meet: true,
+                ..NewEvent::default()
+            })
+            .unwrap();
+        service.execute(&mutation).await.unwrap();
+        let request = server.await.unwrap().remove(0);
+        let (body, request_line) = (request.json(), request.request_line);
+
+        assert_eq!(
+            request_line,
+            "POST /calendars/work@example.com/events?sendUpdates=all&conferenceDataVersion=1 HTTP/1.1"
+        );
+        assert_eq!(body["start"], serde_json::json!({ "date": "2025-05-01" }));
+        assert_eq!(body["end"]["date"], "2025-05-03");
+        assert_eq!(
+            body["attendees"],
+            serde_json::json!([{ "email": "alice@example.com" }])
+        );
+        assert_eq!(
+            body["reminders"],
+            serde_json::json!({ "useDefault": false })
+        );
+        assert_eq!(
+            body["conferenceData"]["createRequest"]["conferenceSolutionKey"]["type"],
+            "hangoutsMeet"
+        );
+        assert!(body["conferenceData"]["createRequest"]["requestId"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_update_event() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = stub_service("Asia/Tokyo", &listener);
+        let server = tokio::spawn(stub_http::serve(
+            listener,
+            vec![("200 OK", EXISTING_EVENT), ("200 OK", CREATED_EVENT)],
+        ));
+
+        let mutation = service
+            .plan_update(
+                None,
+                "evt1",
+                EventChanges {
+                    summary: Some("Roadmap".to_string()),
+                    end: Some("2025-03-03 11:30".to_string()),
+                    ..EventChanges::default()
+                },
+            )
+            .await
+            .unwrap();
+        let res = service.execute(&mutation).await.unwrap().unwrap();
+        let requests = server.await.unwrap();
+
+        assert_eq!(res.id, "evt1");
+        assert_eq!(
+            requests[0].request_line,
+            "GET /calendars/work@example.com/events/evt1 HTTP/1.1"
+        );
+        assert_eq!(
+            requests[1].request_line,
+            "PATCH /calendars/work@example.com/events/evt1 HTTP/1.1"
+        );
+        let body = requests[1].json();
+        assert_eq!(body["summary"], "Roadmap");
+        assert_eq!(body["end"]["dateTime"], "2025-03-03T11:30:00+09:00");
+        // Unchanged fields are left out rather than cleared.
+        assert!(body.get("start").is_none());
+        assert!(body.get("location").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_update_event_rejects_inverted_range() {
+        // Only the end is patched; the start is taken from the current event.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = stub_service("Asia/Tokyo", &listener);
+        let server = tokio::spawn(stub_http::serve(listener, vec![("200 OK", EXISTING_EVENT)]));
+
+        let err = service
+            .plan_update(
+                None,
+                "evt1",
+                EventChanges {
+                    end: Some("2025-03-03 09:30".to_string()),
+                    ..EventChanges::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+
+        match err.downcast_ref::<CalendarServiceError>() {
+            Some(CalendarServiceError::EndBeforeStart { start, end }) => {
+                assert_eq!(start, "2025-03-03 10:00");
+                assert_eq!(end, "2025-03-03 09:30");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // Only the start is patched, past the current end.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = stub_service("Asia/Tokyo", &listener);
+        let server = tokio::spawn(stub_http::serve(listener, vec![("200 OK", EXISTING_EVENT)]));
+        let err = service
+            .plan_update(
+                None,
+                "evt1",
+                EventChanges {
+                    start: Some("2025-03-03 11:00".to_string()),
+                    ..EventChanges::default()
+                },
+            )
+            .await
+            .unwrap_err();
+        server.await.unwrap();
+        assert!(matches!(
+            err.downcast_ref::<CalendarServiceError>(),
+            Some(CalendarServiceError::EndBeforeStart { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_delete_event() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = stub_service("Asia/Tokyo", &listener);
+        let server = tokio::spawn(stub_http::serve(
+            listener,
+            vec![("200 OK", EXISTING_EVENT), ("204 No Content", "")],
+        ));
+
+        let mutation = service.plan_delete(None, "evt1").await.unwrap();
+        assert!(service.execute(&mutation).await.unwrap().is_none());
+        let requests = server.await.unwrap();
+
+        assert_eq!(
+            requests[1].request_line,
+            "DELETE /calendars/work@example.com/events/evt1 HTTP/1.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_move_event() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = stub_service("Asia/Tokyo", &listener);
+        let server = tokio::spawn(stub_http::serve(
+            listener,
+            vec![
+                ("200 OK", EXISTING_EVENT),
+                ("200 OK", CREATED_EVENT),
+                ("200 OK", CREATED_EVENT),
+            ],
+        ));
+
+        let mutation = service
+            .plan_move(
+                None,
+                "evt1",
+                Some("2025-03-04 14:00"),
+                Some("personal@example.com"),
+            )
+            .await
+            .unwrap();
+        service.execute(&mutation).await.unwrap();
+        let requests = server.await.unwrap();
+
+        assert_eq!(
+            requests[1].request_line,
+            "PATCH /calendars/work@example.com/events/evt1 HTTP/1.1"
+        );
+        // The event keeps its length.
+        let body = requests[1].json();
+        assert_eq!(body["start"]["dateTime"], "2025-03-04T14:00:00+09:00");
+        assert_eq!(body["end"]["dateTime"], "2025-03-04T15:00:00+09:00");
+        assert_eq!(
+            requests[2].request_line,
+            "POST /calendars/work@example.com/events/evt1/move?destination=personal%40example.com HTTP/1.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_move_event_failing_after_reschedule() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = stub_service("Asia/Tokyo", &listener);
+        let server = tokio::spawn(stub_http::serve(
+            listener,
+            vec![
+                ("200 OK", EXISTING_EVENT),
+                ("200 OK", CREATED_EVENT),
+                ("403 Forbidden", r#"{"error":{"code":403}}"#),
+            ],
+        ));
+
+        let mutation = service
+            .plan_move(
+                None,
+                "evt1",
+                Some("2025-03-04 14:00"),
+                Some("personal@example.com"),
+            )
+            .await
+            .unwrap();
+        let err = service.execute(&mutation).await.unwrap_err();
+        server.await.unwrap();
+
+        match err.downcast_ref::<CalendarServiceError>() {
+            Some(CalendarServiceError::PartialMove {
+                event_id,
+                calendar_id,
+                destination,
+                reason,
+            }) => {
+                assert_eq!(event_id, "evt1");
+                assert_eq!(calendar_id, "work@example.com");
+                assert_eq!(destination, "personal@example.com");
+                assert!(reason.contains("403"), "{reason}");
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+
+        // Without a reschedule, nothing was changed and the error is passed on.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let service = stub_service("Asia/Tokyo", &listener);
+        let server = tokio::spawn(stub_http::serve(
+            listener,
+            vec![
+                ("200 OK", EXISTING_EVENT),
+                ("403 Forbidden", r#"{"error":{"code":403}}"#),
+            ],
+        ));
+        let mutation = service
+            .plan_move(None, "evt1", None, Some("personal@example.com"))
+            .await
+            .unwrap();
+        let err = service.execute(&mutation).await.unwrap_err();
+        server.await.unwrap();
+        assert!(err.downcast_ref::<CalendarServiceError>().is_none());
+    }
+
+    #[test]
+    fn test_insert_event_rejects_unknown_time_zone() {
+        let service =
+            GoogleCalendarService::new(Arc::new(test_config("Asia/Tokyo")), String::new());
+
+        let err = service
+            .plan_insert(NewEvent {
+                time_zone: Some("Mars/Olympus_Mons".to_string()),
+                ..timed_event("2025-01-06 09:00", "2025-01-06 09:30")
+            })
+            .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<CalendarServiceError>(),
+            Some(CalendarServiceError::InvalidArgument {
+                name: "timeZone",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_all_day_range() {
+        let (start, end) = all_day_range("2025-03-10", "2025-03-12").unwrap();
+        assert_eq!(start.date.as_deref(), Some("2025-03-10"));
+        assert_eq!(end.date.as_deref(), Some("2025-03-13"));
+        assert!(start.date_time.is_none());
+
+        let err = all_day_range("2025-03-10", "2025-03-09").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CalendarServiceError>(),
+            Some(CalendarServiceError::EndBeforeStart { .. })
+        ));
+        let err = all_day_range("2025-03-10 09:00", "2025-03-10").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CalendarServiceError>(),
+            Some(CalendarServiceError::InvalidArgument { name: "start", .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_event_options() {
+        assert_eq!(
+            normalize_recurrence("FREQ=WEEKLY;BYDAY=MO".to_string()).unwrap(),
+            "RRULE:FREQ=WEEKLY;BYDAY=MO"
+        );
+        assert_eq!(
+            normalize_recurrence("EXDATE;VALUE=DATE:20250317".to_string()).unwrap(),
+            "EXDATE;VALUE=DATE:20250317"
+        );
+        assert!(normalize_recurrence("RRULE:BYDAY=MO".to_string()).is_err());
+        assert!(normalize_recurrence("every monday".to_string()).is_err());
+
+        assert!(validate_color_id("11".to_string()).is_ok());
+        assert!(validate_color_id("0".to_string()).is_err());
+        assert!(validate_color_id("red".to_string()).is_err());
+
+        assert!(validate_choice("visibility", "private".to_string(), &VISIBILITIES).is_ok());
+        assert_eq!(
+            validate_choice("sendUpdates", "everyone".to_string(), &SEND_UPDATES)
+                .unwrap_err()
+                .to_string(),
+            "Invalid sendUpdates 'everyone': expected one of all, externalOnly, none"
+        );
+
+        let reminder = |method: &str, minutes| ReminderOverride {
+            method: method.to_string(),
+            minutes,
+        };
+        assert!(validate_reminders(vec![reminder("popup", 10), reminder("email", 1440)]).is_ok());
+        assert!(validate_reminders(vec![reminder("sms", 10)]).is_err());
+        assert!(validate_reminders(vec![reminder("popup", 50000)]).is_err());
+    }
 }
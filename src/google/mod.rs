@@ -1,2 +1,3 @@
 pub mod calendar;
 pub mod oauth;
+pub mod service_account;
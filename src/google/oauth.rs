@@ -9,15 +9,16 @@ use oauth2::{
 };
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader as AsyncBufReader};
 use webbrowser;
 
 #[derive(thiserror::Error, Debug)]
 pub enum OAuth2Error {
     #[error(
-        "Port 9004 is already in use. Another instance of cal2prompt or Windsurf may be running."
+        "Port {0} is already in use. Another instance of cal2prompt or Windsurf may be running."
     )]
-    PortInUse,
+    PortInUse(u16),
 
     #[error("OAuth error: {0}")]
     Other(String),
@@ -36,6 +37,7 @@ pub struct OAuth2Client {
         EndpointSet,    // Revocation (not used)
         EndpointSet,    // Token URL
     >,
+    redirect_url: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,13 +63,15 @@ impl Token {
         }
     }
 
-    pub fn is_expired(&self) -> bool {
+    /// Returns true if the token is expired or will expire within
+    /// `leeway_seconds`, so it can be refreshed before requests start failing.
+    pub fn expires_within(&self, leeway_seconds: i64) -> bool {
         if let Some(exp) = self.expires_at {
             let now = std::time::SystemTime::now()
                 .duration_since(std::time::SystemTime::UNIX_EPOCH)
                 .unwrap_or_default()
                 .as_secs() as i64;
-            exp <= now
+            exp <= now + leeway_seconds
         } else {
             false
         }
@@ -87,19 +91,19 @@ impl OAuth2Client {
                     TokenUrl::new("https://www.googleapis.com/oauth2/v3/token".to_string())
                         .expect("Invalid token endpoint URL"),
                 )
-                .set_redirect_uri(
-                    RedirectUrl::new(redirect_url.to_string()).expect("Invalid redirect URL"),
-                )
                 .set_revocation_url(
                     RevocationUrl::new("https://oauth2.googleapis.com/revoke".to_string())
                         .expect("Invalid revocation endpoint URL"),
                 ),
+            redirect_url: redirect_url.to_string(),
         }
     }
 
     pub async fn oauth_flow(&self) -> anyhow::Result<Token> {
         let http_client = reqwest::Client::new();
 
+        let (listener, redirect_url) = bind_loopback(&self.redirect_url).await?;
+
         let (pkce_code_challenge, pkce_code_verifier) = PkceCodeChallenge::new_random_sha256();
 
         let (authorize_url, _csrf_state) = self
@@ -109,25 +113,9 @@ impl OAuth2Client {
                 "https://www.googleapis.com/auth/calendar.events".to_string(),
             )])
             .set_pkce_challenge(pkce_code_challenge)
+            .set_redirect_uri(Cow::Borrowed(&redirect_url))
             .url();
 
-        let redirect_url = self.client.redirect_uri().unwrap().to_string();
-        let redirect_url_host = redirect_url
-            .strip_prefix("http://")
-            .unwrap_or(&redirect_url);
-
-        // Try to bind to the port, handle the error if the port is already in use
-        let listener = match tokio::net::TcpListener::bind(redirect_url_host).await {
-            Ok(listener) => listener,
-            Err(e) => {
-                if e.kind() == std::io::ErrorKind::AddrInUse {
-                    return Err(OAuth2Error::PortInUse.into());
-                } else {
-                    return Err(OAuth2Error::Other(format!("Failed to bind to port: {}", e)).into());
-                }
-            }
-        };
-
         webbrowser::open(authorize_url.as_ref()).unwrap();
 
         let (mut stream, _) = listener.accept().await?;
@@ -159,6 +147,7 @@ impl OAuth2Client {
         let token_response = self
             .client
             .exchange_code(code)
+            .set_redirect_uri(Cow::Borrowed(&redirect_url))
             .set_pkce_verifier(pkce_code_verifier)
             .request_async(&http_client)
             .await?;
@@ -178,3 +167,141 @@ impl OAuth2Client {
         Ok(Token::from_token_response(&token_response))
     }
 }
+
+/// Binds the loopback listener that receives the OAuth redirect.
+///
+/// Port `0` in the configured redirect URL asks the OS for a free port; the
+/// returned redirect URL always carries the port that was actually bound, as
+/// Google accepts any loopback port for desktop clients. Otherwise the URL is
+/// kept exactly as configured, so it still matches the registered one.
+async fn bind_loopback(
+    configured_redirect_url: &str,
+) -> Result<(tokio::net::TcpListener, RedirectUrl), OAuth2Error> {
+    let mut url = Url::parse(configured_redirect_url).map_err(|e| {
+        OAuth2Error::Other(format!(
+            "Invalid redirect URL '{}': {}",
+            configured_redirect_url, e
+        ))
+    })?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| {
+            OAuth2Error::Other(format!(
+                "Redirect URL '{}' has no host",
+                configured_redirect_url
+            ))
+        })?
+        .to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let listener = match tokio::net::TcpListener::bind((host.as_str(), port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::AddrInUse {
+                return Err(OAuth2Error::PortInUse(port));
+            } else {
+                return Err(OAuth2Error::Other(format!("Failed to bind to port: {}", e)));
+            }
+        }
+    };
+
+    let bound_port = listener
+        .local_addr()
+        .map_err(|e| OAuth2Error::Other(format!("Failed to read bound address: {}", e)))?
+        .port();
+    let mut redirect_url = configured_redirect_url.to_string();
+    if bound_port != port {
+        url.set_port(Some(bound_port))
+            .map_err(|_| OAuth2Error::Other("Failed to set redirect URL port".to_string()))?;
+        redirect_url = url.to_string();
+        // `Url` writes an empty path as "/", which Google would not match
+        // against a redirect URI registered without one.
+        if url.path() == "/" && url.query().is_none() && !configured_redirect_url.ends_with('/') {
+            redirect_url.pop();
+        }
+    }
+
+    let redirect_url = RedirectUrl::new(redirect_url)
+        .map_err(|e| OAuth2Error::Other(format!("Invalid redirect URL: {}", e)))?;
+    Ok((listener, redirect_url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_expiring_in(seconds: i64) -> Token {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        Token {
+            access_token: "access".to_string(),
+            refresh_token: None,
+            expires_at: Some(now + seconds),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bind_loopback_ephemeral_port() {
+        let (listener, redirect_url) = bind_loopback("http://127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert_ne!(port, 0);
+        assert_eq!(redirect_url.as_str(), format!("http://127.0.0.1:{}", port));
+    }
+
+    #[tokio::test]
+    async fn test_bind_loopback_keeps_configured_url() {
+        let free = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = free.local_addr().unwrap().port();
+        drop(free);
+
+        let configured = format!("http://127.0.0.1:{}", port);
+        let (_listener, redirect_url) = bind_loopback(&configured).await.unwrap();
+
+        assert_eq!(redirect_url.as_str(), configured);
+    }
+
+    #[tokio::test]
+    async fn test_bind_loopback_keeps_path() {
+        let (listener, redirect_url) = bind_loopback("http://127.0.0.1:0/callback").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert_eq!(
+            redirect_url.as_str(),
+            format!("http://127.0.0.1:{}/callback", port)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_loopback_port_in_use() {
+        let busy = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = busy.local_addr().unwrap().port();
+
+        let err = bind_loopback(&format!("http://127.0.0.1:{}", port))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, OAuth2Error::PortInUse(p) if p == port));
+        assert!(err.to_string().starts_with(&format!("Port {} is", port)));
+    }
+
+    #[test]
+    fn test_expires_within() {
+        assert!(token_expiring_in(-10).expires_within(0));
+        assert!(!token_expiring_in(300).expires_within(0));
+        assert!(token_expiring_in(30).expires_within(60));
+        assert!(!token_expiring_in(120).expires_within(60));
+    }
+
+    #[test]
+    fn test_expires_within_without_expiry() {
+        let token = Token {
+            access_token: "access".to_string(),
+            refresh_token: None,
+            expires_at: None,
+        };
+        assert!(!token.expires_within(60));
+    }
+}
@@ -4,8 +4,14 @@ mod google;
 mod mcp;
 mod shared;
 
-use clap::{Parser, Subcommand};
+use chrono::Duration;
+use chrono_tz::Tz;
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 use core::cal2prompt::{Cal2Prompt, GetEventDuration};
+use core::stats::format_table;
+use shared::utils::date::parse_duration;
+use std::net::SocketAddr;
 
 const APP_VERSION: &str = concat!(
     env!("CARGO_PKG_NAME"),
@@ -27,35 +33,92 @@ const APP_VERSION: &str = concat!(
 pub struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+    #[command(flatten)]
+    window: RangeArgs,
+    #[arg(
+        long,
+        value_name = "TZ",
+        global = true,
+        value_parser = parse_tz,
+        help = "Time zone to render events in (e.g. America/Los_Angeles). Overrides settings.TZ."
+    )]
+    pub tz: Option<String>,
+    #[arg(long, short = 'V', help = "Print version")]
+    pub version: bool,
+}
+
+/// Flags selecting the time window to fetch, shared by the default command and
+/// `free`.
+#[derive(Debug, Args)]
+pub struct RangeArgs {
     #[arg(
         long,
         value_name = "DATE",
         requires = "until",
-        help = "Start date (YYYY-MM-DD). Requires --until."
+        help = "Start of the window (YYYY-MM-DD, \"YYYY-MM-DD HH:MM\" or RFC3339). Requires --until."
     )]
     pub since: Option<String>,
     #[arg(
         long,
         value_name = "DATE",
         requires = "since",
-        help = "End date (YYYY-MM-DD). Requires --since."
+        help = "End of the window (YYYY-MM-DD, \"YYYY-MM-DD HH:MM\" or RFC3339). A bare date is inclusive. Requires --since."
     )]
     pub until: Option<String>,
+    #[arg(
+        long,
+        value_name = "EXPR",
+        conflicts_with_all = [
+            "since", "until", "today", "tomorrow", "this_week", "this_workweek", "next_week",
+            "last_week", "this_month", "next_month", "last_month", "this_quarter", "next_days",
+            "rest_of_today", "next_hours"
+        ],
+        help = "Date range expression (e.g. tomorrow, \"last week\", \"next 3 days\", +7d, 2025-W05, 2025-03, monday..friday)."
+    )]
+    pub range: Option<String>,
     #[arg(long, help = "Fetch events for today only.")]
     pub today: bool,
-    #[arg(long, help = "Fetch events for the current week (Mon-Sun).")]
+    #[arg(long, help = "Fetch events for tomorrow only.")]
+    pub tomorrow: bool,
+    #[arg(
+        long,
+        help = "Fetch events for the current week (starting on settings.weekStart)."
+    )]
     pub this_week: bool,
+    #[arg(long, help = "Fetch events for the workdays of the current week.")]
+    pub this_workweek: bool,
+    #[arg(long, help = "Fetch events for the upcoming week.")]
+    pub next_week: bool,
+    #[arg(long, help = "Fetch events for the previous week.")]
+    pub last_week: bool,
     #[arg(long, help = "Fetch events for the current month (1st - end).")]
     pub this_month: bool,
-    #[arg(long, help = "Fetch events for the upcoming week (Mon-Sun).")]
-    pub next_week: bool,
-    #[arg(long, short = 'V', help = "Print version")]
-    pub version: bool,
+    #[arg(long, help = "Fetch events for the upcoming month.")]
+    pub next_month: bool,
+    #[arg(long, help = "Fetch events for the previous month.")]
+    pub last_month: bool,
+    #[arg(long, help = "Fetch events for the current quarter.")]
+    pub this_quarter: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Fetch events for the next N days, starting today."
+    )]
+    pub next_days: Option<u32>,
+    #[arg(long, help = "Fetch the events left today, from now until midnight.")]
+    pub rest_of_today: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Fetch events from now until N hours from now."
+    )]
+    pub next_hours: Option<u32>,
 }
 
 enum FetchMode {
     Shortcut(GetEventDuration),
     Range(String, String),
+    Expression(String),
 }
 
 #[derive(Debug, Subcommand)]
@@ -64,13 +127,68 @@ enum Commands {
         name = "mcp",
         about = "Launch cal2prompt as an MCP server (experimental)"
     )]
-    Mcp,
+    Mcp {
+        #[arg(
+            long,
+            value_name = "ADDR",
+            help = "Serve Streamable HTTP on ADDR (e.g. 127.0.0.1:8787) instead of stdio."
+        )]
+        http: Option<SocketAddr>,
+        #[arg(
+            long,
+            requires = "http",
+            help = "Allow --http on a non-loopback address without experimental.mcp.http.bearerToken."
+        )]
+        allow_unauthenticated: bool,
+    },
+    #[command(
+        name = "free",
+        about = "List free slots within working hours (settings.workingHours)"
+    )]
+    Free {
+        #[command(flatten)]
+        window: RangeArgs,
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = parse_min_duration,
+            help = "Minimum slot length (e.g. 30m, 1h, 1h30m). Defaults to settings.minFreeSlot."
+        )]
+        min: Option<Duration>,
+    },
+    #[command(
+        name = "stats",
+        about = "Summarize where the time went (per calendar, attendee domain, meetings vs focus, ...)"
+    )]
+    Stats {
+        #[command(flatten)]
+        window: RangeArgs,
+        #[arg(long, value_enum, default_value = "table", help = "Output format.")]
+        format: StatsFormat,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum StatsFormat {
+    Table,
+    Json,
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
 
+    // `free` and `stats` take their own range flags; ones given before the
+    // subcommand would otherwise be ignored.
+    if cli.command.is_some() && selected_window(&cli.window).is_some() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "range options go after the subcommand (e.g. `cal2prompt stats --today`)",
+            )
+            .exit();
+    }
+
     if cli.version {
         print!("{}", APP_VERSION);
         std::process::exit(0);
@@ -78,11 +196,22 @@ async fn main() {
 
     match &cli.command {
         Some(cmd) => match cmd {
-            Commands::Mcp => {
+            Commands::Mcp {
+                http,
+                allow_unauthenticated,
+            } => {
                 // For MCP mode, initialize without OAuth to allow proper error handling via JSON-RPC
-                match init_cal2prompt_without_oauth().await {
-                    Ok(mut cal2prompt) => {
-                        if let Err(err) = cal2prompt.launch_mcp().await {
+                match init_cal2prompt_without_oauth(cli.tz.as_deref()).await {
+                    Ok(cal2prompt) => {
+                        let result = match http {
+                            Some(addr) => {
+                                cal2prompt
+                                    .launch_mcp_http(*addr, *allow_unauthenticated)
+                                    .await
+                            }
+                            None => cal2prompt.launch_mcp().await,
+                        };
+                        if let Err(err) = result {
                             eprintln!("Error: {:?}", err);
                             std::process::exit(1);
                         }
@@ -93,10 +222,59 @@ async fn main() {
                     }
                 }
             }
+            Commands::Free { window, min } => {
+                let cal2prompt = match init_cal2prompt(cli.tz.as_deref()).await {
+                    Ok(cal2prompt) => cal2prompt,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                };
+
+                let (since, until) = resolve_window(&cal2prompt, window);
+
+                match cal2prompt.get_free_slots(since, until, *min).await {
+                    Ok(generate) => {
+                        print!("{}", generate);
+                    }
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            Commands::Stats { window, format } => {
+                let cal2prompt = match init_cal2prompt(cli.tz.as_deref()).await {
+                    Ok(cal2prompt) => cal2prompt,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                };
+
+                let (since, until) = resolve_window(&cal2prompt, window);
+
+                match cal2prompt.fetch_stats(&since, &until).await {
+                    Ok(stats) => match format {
+                        StatsFormat::Table => print!("{}", format_table(&stats)),
+                        StatsFormat::Json => match serde_json::to_string_pretty(&stats) {
+                            Ok(json) => println!("{}", json),
+                            Err(err) => {
+                                eprintln!("{}", err);
+                                std::process::exit(1);
+                            }
+                        },
+                    },
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                }
+            }
         },
         None => {
             // For CLI mode, initialize with OAuth as before
-            let cal2prompt = match init_cal2prompt().await {
+            let cal2prompt = match init_cal2prompt(cli.tz.as_deref()).await {
                 Ok(cal2prompt) => cal2prompt,
                 Err(e) => {
                     eprintln!("{}", e);
@@ -104,7 +282,7 @@ async fn main() {
                 }
             };
 
-            let fetch_mode = determine_duration_or_range(&cli);
+            let fetch_mode = determine_duration_or_range(&cli.window);
 
             match fetch_mode {
                 FetchMode::Shortcut(duration) => {
@@ -127,13 +305,24 @@ async fn main() {
                         }
                     }
                 }
+                FetchMode::Expression(expr) => {
+                    match cal2prompt.get_events_expression(&expr).await {
+                        Ok(generate) => {
+                            println!("{}", generate);
+                        }
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            std::process::exit(1);
+                        }
+                    }
+                }
             }
         }
     };
 }
 
-async fn init_cal2prompt() -> anyhow::Result<Cal2Prompt> {
-    let mut cal2prompt = Cal2Prompt::new()?;
+async fn init_cal2prompt(tz: Option<&str>) -> anyhow::Result<Cal2Prompt> {
+    let cal2prompt = init_cal2prompt_without_oauth(tz).await?;
     let _ = cal2prompt.oauth().await.map_err(|e| {
         eprintln!("{}", e);
         std::process::exit(1);
@@ -142,23 +331,83 @@ async fn init_cal2prompt() -> anyhow::Result<Cal2Prompt> {
     Ok(cal2prompt)
 }
 
-async fn init_cal2prompt_without_oauth() -> anyhow::Result<Cal2Prompt> {
+async fn init_cal2prompt_without_oauth(tz: Option<&str>) -> anyhow::Result<Cal2Prompt> {
     // Initialize Cal2Prompt without performing OAuth
-    Cal2Prompt::new()
+    let mut cal2prompt = Cal2Prompt::new()?;
+    if let Some(tz) = tz {
+        cal2prompt.set_tz(tz);
+    }
+    Ok(cal2prompt)
+}
+
+fn parse_min_duration(value: &str) -> Result<Duration, String> {
+    parse_duration(value).map_err(|err| err.to_string())
+}
+
+fn parse_tz(value: &str) -> Result<String, String> {
+    value
+        .parse::<Tz>()
+        .map(|_| value.to_string())
+        .map_err(|_| format!("unknown time zone '{}'", value))
+}
+
+/// Resolves the window flags of a subcommand into a `since`/`until` pair,
+/// exiting on an invalid `--range` expression or a shortcut that cannot be
+/// resolved in the configured time zone.
+fn resolve_window(cal2prompt: &Cal2Prompt, window: &RangeArgs) -> (String, String) {
+    let range = match determine_duration_or_range(window) {
+        FetchMode::Shortcut(duration) => cal2prompt.shortcut_range(&duration),
+        FetchMode::Range(since, until) => Ok((since, until)),
+        FetchMode::Expression(expr) => cal2prompt.expression_range(&expr),
+    };
+    match range {
+        Ok(range) => range,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The window selected by `window`, defaulting to today.
+fn determine_duration_or_range(window: &RangeArgs) -> FetchMode {
+    selected_window(window).unwrap_or(FetchMode::Shortcut(GetEventDuration::Today))
 }
 
-fn determine_duration_or_range(cli: &Cli) -> FetchMode {
-    if let (Some(since), Some(until)) = (&cli.since, &cli.until) {
+/// The window selected by `window`, or `None` when no flag was given.
+fn selected_window(window: &RangeArgs) -> Option<FetchMode> {
+    let mode = if let (Some(since), Some(until)) = (&window.since, &window.until) {
         FetchMode::Range(since.clone(), until.clone())
-    } else if cli.today {
+    } else if let Some(expr) = &window.range {
+        FetchMode::Expression(expr.clone())
+    } else if window.today {
         FetchMode::Shortcut(GetEventDuration::Today)
-    } else if cli.this_week {
+    } else if window.tomorrow {
+        FetchMode::Shortcut(GetEventDuration::Tomorrow)
+    } else if window.this_week {
         FetchMode::Shortcut(GetEventDuration::ThisWeek)
-    } else if cli.this_month {
-        FetchMode::Shortcut(GetEventDuration::ThisMonth)
-    } else if cli.next_week {
+    } else if window.this_workweek {
+        FetchMode::Shortcut(GetEventDuration::ThisWorkweek)
+    } else if window.next_week {
         FetchMode::Shortcut(GetEventDuration::NextWeek)
+    } else if window.last_week {
+        FetchMode::Shortcut(GetEventDuration::LastWeek)
+    } else if window.this_month {
+        FetchMode::Shortcut(GetEventDuration::ThisMonth)
+    } else if window.next_month {
+        FetchMode::Shortcut(GetEventDuration::NextMonth)
+    } else if window.last_month {
+        FetchMode::Shortcut(GetEventDuration::LastMonth)
+    } else if window.this_quarter {
+        FetchMode::Shortcut(GetEventDuration::ThisQuarter)
+    } else if let Some(days) = window.next_days {
+        FetchMode::Shortcut(GetEventDuration::NextNDays(days))
+    } else if window.rest_of_today {
+        FetchMode::Shortcut(GetEventDuration::RestOfToday)
+    } else if let Some(hours) = window.next_hours {
+        FetchMode::Shortcut(GetEventDuration::NextHours(hours))
     } else {
-        FetchMode::Shortcut(GetEventDuration::Today)
-    }
+        return None;
+    };
+    Some(mode)
 }
@@ -1,208 +1,498 @@
 use crate::core::cal2prompt::{Cal2Prompt, Cal2PromptError, JsonRpcErrorCode};
-use crate::google::calendar::service::CalendarServiceError;
-use crate::mcp::stdio::{Message, StdioTransport, Transport};
-use futures::StreamExt;
+use crate::core::date_expr::DateExprError;
+use crate::core::template::generate;
+use crate::core::write_guard::WriteGuardError;
+use crate::google::calendar::model::ReminderOverride;
+use crate::google::calendar::service::{
+    CalendarServiceError, EventChanges, FetchProgress, NewEvent,
+};
+use crate::mcp::prompts::{self, PromptError};
+use crate::mcp::resources::{self, ResourceError, ResourceFormat, ResourceRange, ResourceUri};
+use crate::mcp::stdio::{Error, Message, RequestId, Transport};
+use crate::shared::utils::date::{parse_duration, DateError};
+use async_trait::async_trait;
+use futures::future::{AbortHandle, Abortable};
+use futures::stream::FuturesUnordered;
+use futures::{stream, Future, FutureExt, Stream, StreamExt};
 use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use tokio::sync::mpsc;
 
-static TOOLS_JSON: &str = include_str!("./tools.json");
+pub(crate) static TOOLS_JSON: &str = include_str!("./tools.json");
 
+/// The protocol versions this server speaks, newest first.
+pub const SUPPORTED_PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// Serves one MCP session. Requests are handled concurrently, so the state
+/// they share lives behind atomics and locks.
 pub struct McpHandler<'a> {
-    cal2prompt: &'a mut Cal2Prompt,
-    initialized: bool,
+    cal2prompt: &'a Cal2Prompt,
+    initialized: AtomicBool,
+    /// Set by `notifications/initialized`; nothing is sent unprompted before.
+    client_initialized: AtomicBool,
+    /// Subscribed resource URIs and the contents last sent for each.
+    subscriptions: Mutex<BTreeMap<String, String>>,
+    /// Requests still being handled, so `notifications/cancelled` can stop them.
+    in_flight: Mutex<HashMap<RequestId, AbortHandle>>,
+    polling: AtomicBool,
+}
+
+/// What the server loop waits for.
+enum Incoming {
+    Message(Result<Message, Error>),
+    /// Time to re-read subscribed resources.
+    Poll,
+    Closed,
 }
 
 impl<'a> McpHandler<'a> {
-    pub fn new(cal2prompt: &'a mut Cal2Prompt) -> Self {
+    pub fn new(cal2prompt: &'a Cal2Prompt) -> Self {
         Self {
             cal2prompt,
-            initialized: false,
+            initialized: AtomicBool::new(false),
+            client_initialized: AtomicBool::new(false),
+            subscriptions: Mutex::new(BTreeMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+            polling: AtomicBool::new(false),
         }
     }
 
-    pub async fn launch_mcp(&mut self, transport: &StdioTransport) -> anyhow::Result<()> {
-        let mut stream = transport.receive();
-
-        eprintln!("MCP stdio transport server started. Waiting for JSON messages on stdin...");
+    pub async fn launch_mcp(&self, transport: &dyn Transport) -> anyhow::Result<()> {
+        let messages = transport
+            .receive()
+            .map(Incoming::Message)
+            .chain(stream::iter([Incoming::Closed]));
+        let mut interval = tokio::time::interval(self.cal2prompt.resource_poll_interval());
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let polls = stream::unfold(interval, |mut interval| async move {
+            interval.tick().await;
+            Some((Incoming::Poll, interval))
+        });
+        let mut stream = stream::select(messages, polls.boxed());
 
-        while let Some(msg_result) = stream.next().await {
-            match msg_result {
-                Ok(Message::Request {
+        // A slow request, such as one waiting for the first OAuth flow, does
+        // not hold up the others or their cancellation.
+        let mut in_flight = FuturesUnordered::new();
+        loop {
+            let incoming = tokio::select! {
+                Some(result) = in_flight.next() => {
+                    result?;
+                    continue;
+                }
+                incoming = stream.next() => incoming,
+            };
+            match incoming {
+                Some(Incoming::Message(Ok(Message::Notification { method, params, .. }))) => {
+                    self.handle_notification(&method, params.as_ref())
+                }
+                // Nothing may overtake initialization, so it is finished
+                // before the next message is read.
+                Some(Incoming::Message(Ok(Message::Request {
                     id, method, params, ..
-                }) => {
-                    eprintln!(
-                        "[SERVER] Got Request: id={}, method={}, params={:?}",
-                        id, method, params
-                    );
-
-                    // Handle initialization request first
-                    if method == "initialize" {
-                        if let Err(err) = self.handle_initialize(transport, id).await {
-                            eprintln!("[SERVER] Error handling initialize: {:?}", err);
-                            self.send_error_response(
-                                transport,
-                                id,
-                                JsonRpcErrorCode::InternalError,
-                                format!("Failed to initialize: {}", err),
-                            )
-                            .await?;
+                }))) if method == "initialize" || !self.initialized.load(Ordering::SeqCst) => {
+                    self.handle_request_message(transport, &id, method, params)
+                        .await?
+                }
+                Some(Incoming::Message(msg_result)) => {
+                    in_flight.push(self.handle_incoming(transport, msg_result).boxed())
+                }
+                Some(Incoming::Poll) if !self.polling.swap(true, Ordering::SeqCst) => in_flight
+                    .push(
+                        async {
+                            let result = self.poll_subscriptions(transport).await;
+                            self.polling.store(false, Ordering::SeqCst);
+                            result
                         }
-                        self.initialized = true;
-                        continue;
-                    }
+                        .boxed(),
+                    ),
+                // The previous poll is still running.
+                Some(Incoming::Poll) => {}
+                Some(Incoming::Closed) | None => break,
+            }
+        }
 
-                    // For all other requests, ensure we're initialized
-                    if !self.initialized {
-                        self.send_error_response(
-                            transport,
-                            id,
-                            JsonRpcErrorCode::InvalidRequest,
-                            "Server not initialized. Send 'initialize' request first.".to_string(),
-                        )
-                        .await?;
-                        continue;
-                    }
+        // Answer everything that was received before the client went away.
+        while let Some(result) = in_flight.next().await {
+            result?;
+        }
 
-                    // Allow tools/list without requiring OAuth
-                    if method == "tools/list" {
-                        if let Err(err) = self.handle_tools_list(transport, id).await {
-                            eprintln!("[SERVER] Error handling tools/list: {:?}", err);
-                            self.send_error_response(
-                                transport,
-                                id,
-                                JsonRpcErrorCode::InternalError,
-                                format!("Failed to list tools: {}", err),
-                            )
-                            .await?;
-                        }
-                        continue;
-                    }
+        Ok(())
+    }
 
-                    // For tools/call and other methods that require authentication
-                    if method == "tools/call" {
-                        // Perform OAuth if not done yet
-                        if self.cal2prompt.token.is_none() {
-                            if let Err(err) = self.cal2prompt.oauth().await {
-                                // Check for OAuth2PortInUse error using proper type checking
-                                if let Some(Cal2PromptError::OAuth2PortInUse(_)) =
-                                    err.downcast_ref::<Cal2PromptError>()
-                                {
-                                    self.send_error_response(
-                                        transport,
-                                        id,
-                                        JsonRpcErrorCode::PortInUse,
-                                        "Port 9004 is already in use. Another instance of cal2prompt or Windsurf may be running.".to_string(),
-                                    )
-                                    .await?;
-                                    continue;
-                                }
-
-                                self.send_error_response(
-                                    transport,
-                                    id,
-                                    JsonRpcErrorCode::InternalError,
-                                    format!("Failed to authenticate: {}", err),
-                                )
-                                .await?;
-                                continue;
-                            }
-                        }
+    async fn handle_incoming(
+        &self,
+        transport: &dyn Transport,
+        msg_result: Result<Message, Error>,
+    ) -> anyhow::Result<()> {
+        match msg_result {
+            Ok(Message::Batch(messages)) => self.handle_batch(transport, messages).await?,
+            Ok(message) => self.handle_message(transport, message).await?,
+            Err(Error::Serialization(err)) => {
+                eprintln!("[SERVER] Error parsing message: {}", err);
+                // The ID of a message that cannot be parsed is unknown.
+                transport
+                    .send(error_response(
+                        None,
+                        JsonRpcErrorCode::ParseError,
+                        format!("Parse error: {}", err),
+                    ))
+                    .await?;
+            }
+            Err(e) => {
+                eprintln!("[SERVER] Error receiving message: {:?}", e);
+            }
+        }
 
-                        // Ensure token is valid for all requests that need authentication
-                        if let Err(err) = self.cal2prompt.ensure_valid_token().await {
-                            // Check for OAuth2PortInUse error using proper type checking
-                            if let Some(Cal2PromptError::OAuth2PortInUse(_)) =
-                                err.downcast_ref::<Cal2PromptError>()
-                            {
-                                self.send_error_response(
-                                    transport,
-                                    id,
-                                    JsonRpcErrorCode::PortInUse,
-                                    "Port 9004 is already in use. Another instance of cal2prompt or Windsurf may be running.".to_string(),
-                                )
-                                .await?;
-                                continue;
-                            }
-
-                            self.send_error_response(
-                                transport,
-                                id,
-                                JsonRpcErrorCode::InternalError,
-                                format!("Failed to refresh token: {}", err),
-                            )
-                            .await?;
-                            continue;
-                        }
-                    }
+        Ok(())
+    }
 
-                    if let Err(err) = self.handle_request(transport, id, method, params).await {
-                        eprintln!("[SERVER] Error handling request: {:?}", err);
-                        self.send_error_response(
-                            transport,
-                            id,
-                            JsonRpcErrorCode::InternalError,
-                            format!("Failed to handle request: {}", err),
-                        )
-                        .await?;
-                    }
-                }
-                Ok(Message::Notification { method, params, .. }) => {
-                    eprintln!(
-                        "[SERVER] Got Notification: method={}, params={:?}",
-                        method, params
-                    );
-                }
-                Ok(Message::Response {
-                    id, result, error, ..
-                }) => {
-                    eprintln!(
-                        "[SERVER] Got Response: id={}, result={:?}, error={:?}",
-                        id, result, error
-                    );
-                }
-                Err(e) => {
-                    eprintln!("[SERVER] Error receiving message: {:?}", e);
+    /// Handles the messages of a JSON-RPC batch in order and answers with a
+    /// single array of their responses, if any.
+    async fn handle_batch(
+        &self,
+        transport: &dyn Transport,
+        messages: Vec<Message>,
+    ) -> anyhow::Result<()> {
+        if messages.is_empty() {
+            transport
+                .send(error_response(
+                    None,
+                    JsonRpcErrorCode::InvalidRequest,
+                    "Empty batch".to_string(),
+                ))
+                .await?;
+            return Ok(());
+        }
+
+        let batch = BatchTransport::new(transport);
+        for message in messages {
+            self.handle_message(&batch, message).await?;
+        }
+        let responses = batch.into_responses();
+        if !responses.is_empty() {
+            transport.send(Message::Batch(responses)).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_message(
+        &self,
+        transport: &dyn Transport,
+        message: Message,
+    ) -> anyhow::Result<()> {
+        match message {
+            Message::Request {
+                id, method, params, ..
+            } => {
+                let request = self.handle_request_message(transport, &id, method, params);
+                // A cancelled request gets no response.
+                self.cancellable(&id, request).await.unwrap_or(Ok(()))
+            }
+            Message::Notification { method, params, .. } => {
+                self.handle_notification(&method, params.as_ref());
+                Ok(())
+            }
+            Message::Response {
+                id, result, error, ..
+            } => {
+                eprintln!(
+                    "[SERVER] Got Response: id={:?}, result={:?}, error={:?}",
+                    id, result, error
+                );
+                Ok(())
+            }
+            Message::Batch(_) => {
+                transport
+                    .send(error_response(
+                        None,
+                        JsonRpcErrorCode::InvalidRequest,
+                        "Batches cannot be nested".to_string(),
+                    ))
+                    .await?;
+                Ok(())
+            }
+            Message::Invalid { id, reason } => {
+                transport
+                    .send(error_response(
+                        id,
+                        JsonRpcErrorCode::InvalidRequest,
+                        format!("Invalid Request: {}", reason),
+                    ))
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn handle_request_message(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+        method: String,
+        params: Option<serde_json::Value>,
+    ) -> anyhow::Result<()> {
+        eprintln!(
+            "[SERVER] Got Request: id={}, method={}, params={:?}",
+            id, method, params
+        );
+
+        // Allowed at any time, even before initialization.
+        if method == "ping" {
+            return self.send_result(transport, id, json!({})).await;
+        }
+
+        // Handle initialization request first
+        if method == "initialize" {
+            if let Err(err) = self.handle_initialize(transport, id, params.as_ref()).await {
+                eprintln!("[SERVER] Error handling initialize: {:?}", err);
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to initialize: {}", err),
+                )
+                .await?;
+            }
+            self.initialized.store(true, Ordering::SeqCst);
+            return Ok(());
+        }
+
+        // For all other requests, ensure we're initialized
+        if !self.initialized.load(Ordering::SeqCst) {
+            self.send_error_response(
+                transport,
+                id,
+                JsonRpcErrorCode::InvalidRequest,
+                "Server not initialized. Send 'initialize' request first.".to_string(),
+            )
+            .await?;
+            return Ok(());
+        }
+
+        // Allow tools/list without requiring OAuth
+        if method == "tools/list" {
+            if let Err(err) = self.handle_tools_list(transport, id).await {
+                eprintln!("[SERVER] Error handling tools/list: {:?}", err);
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to list tools: {}", err),
+                )
+                .await?;
+            }
+            return Ok(());
+        }
+
+        if method == "resources/list" || method == "resources/templates/list" {
+            let result = if method == "resources/list" {
+                resources::list(self.cal2prompt.calendar_ids())
+            } else {
+                resources::templates()
+            };
+            self.send_result(transport, id, result).await?;
+            return Ok(());
+        }
+
+        if method == "prompts/list" {
+            let result = prompts::list(&prompts::all(self.cal2prompt.output()));
+            self.send_result(transport, id, result).await?;
+            return Ok(());
+        }
+
+        // Unknown tools are rejected before authenticating.
+        if method == "tools/call" {
+            if let Err(message) = check_tool_name(params.as_ref()) {
+                return self
+                    .send_error_response(transport, id, JsonRpcErrorCode::InvalidParams, message)
+                    .await;
+            }
+        }
+
+        // For tools/call and other methods that require authentication
+        if matches!(
+            method.as_str(),
+            "tools/call" | "resources/read" | "resources/subscribe" | "prompts/get"
+        ) {
+            // Runs the OAuth flow the first time; requests that arrive
+            // meanwhile wait for it instead of starting their own.
+            if let Err(err) = self.cal2prompt.ensure_valid_token().await {
+                // Check for OAuth2PortInUse error using proper type checking
+                if let Some(Cal2PromptError::OAuth2PortInUse(port_err)) =
+                    err.downcast_ref::<Cal2PromptError>()
+                {
+                    self.send_error_response(
+                        transport,
+                        id,
+                        JsonRpcErrorCode::PortInUse,
+                        port_err.to_string(),
+                    )
+                    .await?;
+                    return Ok(());
                 }
+
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::InternalError,
+                    format!("Failed to authenticate: {}", err),
+                )
+                .await?;
+                return Ok(());
             }
         }
 
+        if let Err(err) = self.handle_request(transport, id, method, params).await {
+            eprintln!("[SERVER] Error handling request: {:?}", err);
+            self.send_error_response(
+                transport,
+                id,
+                JsonRpcErrorCode::InternalError,
+                format!("Failed to handle request: {}", err),
+            )
+            .await?;
+        }
+
         Ok(())
     }
 
+    fn handle_notification(&self, method: &str, params: Option<&Value>) {
+        match method {
+            "notifications/initialized" => self.client_initialized.store(true, Ordering::SeqCst),
+            "notifications/cancelled" => {
+                let Some(id) = params
+                    .and_then(|params| params.get("requestId"))
+                    .and_then(|id| serde_json::from_value::<RequestId>(id.clone()).ok())
+                else {
+                    eprintln!("[SERVER] Ignoring notifications/cancelled without a requestId");
+                    return;
+                };
+                let reason = params
+                    .and_then(|params| params.get("reason"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("no reason given");
+                let handle = self
+                    .in_flight
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .get(&id)
+                    .cloned();
+                match handle {
+                    Some(handle) => {
+                        eprintln!("[SERVER] Cancelling request {}: {}", id, reason);
+                        handle.abort();
+                    }
+                    // Already answered, or never sent.
+                    None => eprintln!(
+                        "[SERVER] Ignoring cancellation of request {}, which is not in flight",
+                        id
+                    ),
+                }
+            }
+            _ => eprintln!(
+                "[SERVER] Got Notification: method={}, params={:?}",
+                method, params
+            ),
+        }
+    }
+
     async fn handle_request(
         &self,
-        transport: &StdioTransport,
-        id: u64,
+        transport: &dyn Transport,
+        id: &RequestId,
         method: String,
         params: Option<serde_json::Value>,
     ) -> anyhow::Result<()> {
         match &*method {
-            "initialize" => self.handle_initialize(transport, id).await?,
             "tools/list" => self.handle_tools_list(transport, id).await?,
             "tools/call" => {
-                if let Some(params_val) = params {
-                    self.handle_tools_call(transport, id, params_val).await?;
+                self.handle_tools_call(transport, id, params.unwrap_or_default())
+                    .await?
+            }
+            "resources/read" => {
+                let uri = resource_uri(params.as_ref());
+                match self
+                    .with_progress(transport, params.as_ref(), |progress| {
+                        self.read_resource(&uri, progress)
+                    })
+                    .await
+                {
+                    Ok((mime_type, text)) => {
+                        let result = json!({
+                            "contents": [{
+                                "uri": uri,
+                                "mimeType": mime_type,
+                                "text": text,
+                            }],
+                        });
+                        self.send_result(transport, id, result).await?;
+                    }
+                    Err(err) => self.send_calendar_error(transport, id, err).await?,
                 }
             }
-            _ => {}
+            "resources/subscribe" => {
+                let uri = resource_uri(params.as_ref());
+                match self.read_resource(&uri, None).await {
+                    Ok((_, text)) => {
+                        self.subscriptions().insert(uri, text);
+                        self.send_result(transport, id, json!({})).await?;
+                    }
+                    Err(err) => self.send_calendar_error(transport, id, err).await?,
+                }
+            }
+            "prompts/get" => match self
+                .with_progress(transport, params.as_ref(), |progress| {
+                    self.get_prompt(params.as_ref(), progress)
+                })
+                .await
+            {
+                Ok(result) => self.send_result(transport, id, result).await?,
+                Err(err) => self.send_calendar_error(transport, id, err).await?,
+            },
+            "resources/unsubscribe" => {
+                let uri = resource_uri(params.as_ref());
+                self.subscriptions().remove(&uri);
+                self.send_result(transport, id, json!({})).await?;
+            }
+            _ => {
+                self.send_error_response(
+                    transport,
+                    id,
+                    JsonRpcErrorCode::MethodNotFound,
+                    format!("Method not found: {}", method),
+                )
+                .await?
+            }
         }
         Ok(())
     }
 
-    async fn handle_initialize(&self, transport: &StdioTransport, id: u64) -> anyhow::Result<()> {
+    async fn handle_initialize(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+        params: Option<&Value>,
+    ) -> anyhow::Result<()> {
+        let requested = params
+            .and_then(|params| params.get("protocolVersion"))
+            .and_then(Value::as_str);
         let response = Message::Response {
             jsonrpc: "2.0".to_string(),
-            id,
+            id: Some(id.clone()),
             result: Some(json!({
                 "capabilities": {
                     "experimental": {},
                     "prompts": { "listChanged": false },
-                    "resources": { "listChanged": false, "subscribe": false },
+                    "resources": { "listChanged": false, "subscribe": true },
                     "tools": { "listChanged": false }
                 },
-                "protocolVersion": "2024-11-05",
+                "protocolVersion": negotiate_protocol_version(requested),
                 "serverInfo": {
                     "name": "cal2prompt",
-                    "version": "0.1.0"
+                    "version": env!("CARGO_PKG_VERSION")
                 }
             })),
             error: None,
@@ -211,13 +501,17 @@ impl<'a> McpHandler<'a> {
         Ok(())
     }
 
-    async fn handle_tools_list(&self, transport: &StdioTransport, id: u64) -> anyhow::Result<()> {
+    async fn handle_tools_list(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+    ) -> anyhow::Result<()> {
         let tools_value: serde_json::Value =
             serde_json::from_str(TOOLS_JSON).expect("tools.json must be valid JSON");
 
         let response = Message::Response {
             jsonrpc: "2.0".to_string(),
-            id,
+            id: Some(id.clone()),
             result: Some(tools_value),
             error: None,
         };
@@ -228,24 +522,49 @@ impl<'a> McpHandler<'a> {
 
     async fn handle_tools_call(
         &self,
-        transport: &StdioTransport,
-        id: u64,
+        transport: &dyn Transport,
+        id: &RequestId,
         params_val: serde_json::Value,
     ) -> anyhow::Result<()> {
-        let tool_name = match params_val.get("name").and_then(Value::as_str) {
-            Some(name) => name,
-            None => return Ok(()),
-        };
+        // check_tool_name has already made sure the tool exists.
+        let tool_name = params_val
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
 
         match tool_name {
             "list_calendar_events" => {
                 self.handle_list_calendar_events(transport, id, &params_val)
                     .await?
             }
+            "find_free_slots" => {
+                self.handle_find_free_slots(transport, id, &params_val)
+                    .await?
+            }
+            "get_free_busy" => {
+                self.handle_get_free_busy(transport, id, &params_val)
+                    .await?
+            }
+            "find_common_free_time" => {
+                self.handle_find_common_free_time(transport, id, &params_val)
+                    .await?
+            }
             "insert_calendar_event" => {
                 self.handle_insert_calendar_event(transport, id, &params_val)
                     .await?
             }
+            "update_calendar_event" => {
+                self.handle_update_calendar_event(transport, id, &params_val)
+                    .await?
+            }
+            "move_calendar_event" => {
+                self.handle_move_calendar_event(transport, id, &params_val)
+                    .await?
+            }
+            "delete_calendar_event" => {
+                self.handle_delete_calendar_event(transport, id, &params_val)
+                    .await?
+            }
             _ => {}
         }
 
@@ -254,8 +573,8 @@ impl<'a> McpHandler<'a> {
 
     async fn handle_list_calendar_events(
         &self,
-        transport: &StdioTransport,
-        id: u64,
+        transport: &dyn Transport,
+        id: &RequestId,
         params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
         let since_str = params_val
@@ -267,115 +586,1095 @@ impl<'a> McpHandler<'a> {
             .and_then(Value::as_str)
             .unwrap_or("");
 
-        match self.cal2prompt.fetch_days(since_str, until_str).await {
+        let fetch = self.with_progress(transport, Some(params_val), |progress| async move {
+            self.cal2prompt
+                .fetch_days(since_str, until_str, progress.as_ref())
+                .await
+        });
+        match fetch.await {
             Ok(days) => {
-                let result_json = json!({ "days": days });
-                let obj_as_str = serde_json::to_string(&result_json)?;
-                self.send_text_response(transport, id, &obj_as_str).await?;
+                self.send_structured_response(transport, id, json!({ "days": days }))
+                    .await?
             }
             Err(err) => {
-                self.send_error_response(
-                    transport,
-                    id,
-                    JsonRpcErrorCode::InternalError,
-                    format!("Failed to fetch calendar events: {}", err),
-                )
-                .await?;
+                self.send_tool_error(transport, id, tool_error_message(&err))
+                    .await?
             }
         }
 
         Ok(())
     }
 
-    async fn handle_insert_calendar_event(
+    async fn handle_find_free_slots(
         &self,
-        transport: &StdioTransport,
-        id: u64,
+        transport: &dyn Transport,
+        id: &RequestId,
         params_val: &serde_json::Value,
     ) -> anyhow::Result<()> {
-        let summary_str = params_val
-            .pointer("/arguments/summary")
+        let since_str = params_val
+            .pointer("/arguments/since")
             .and_then(Value::as_str)
             .unwrap_or("");
-        let description_str: Option<String> = params_val
-            .pointer("/arguments/description")
+        let until_str = params_val
+            .pointer("/arguments/until")
             .and_then(Value::as_str)
-            .map(String::from);
-        let start_str = params_val
-            .pointer("/arguments/start")
+            .unwrap_or("");
+        let min = match params_val.pointer("/arguments/min").and_then(Value::as_str) {
+            Some(min_str) => match parse_duration(min_str) {
+                Ok(min) => Some(min),
+                Err(err) => {
+                    self.send_tool_error(transport, id, err.to_string()).await?;
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+
+        match self
+            .cal2prompt
+            .find_free_slots(since_str, until_str, min)
+            .await
+        {
+            Ok(free_slots) => {
+                self.send_structured_response(transport, id, json!({ "free_slots": free_slots }))
+                    .await?
+            }
+            Err(err) => {
+                self.send_tool_error(transport, id, tool_error_message(&err))
+                    .await?
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_get_free_busy(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let emails = string_array_argument(params_val, "emails");
+        let since_str = params_val
+            .pointer("/arguments/since")
             .and_then(Value::as_str)
             .unwrap_or("");
-        let end_str = params_val
-            .pointer("/arguments/end")
+        let until_str = params_val
+            .pointer("/arguments/until")
             .and_then(Value::as_str)
             .unwrap_or("");
 
         match self
             .cal2prompt
-            .insert_event(summary_str, description_str, start_str, end_str)
+            .get_free_busy(&emails, since_str, until_str)
             .await
         {
+            Ok(calendars) => {
+                self.send_structured_response(transport, id, json!({ "calendars": calendars }))
+                    .await?
+            }
+            Err(err) => {
+                self.send_tool_error(transport, id, tool_error_message(&err))
+                    .await?
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_find_common_free_time(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let emails = string_array_argument(params_val, "emails");
+        let since_str = params_val
+            .pointer("/arguments/since")
+            .and_then(Value::as_str)
+            .unwrap_or("");
+        let until_str = params_val
+            .pointer("/arguments/until")
+            .and_then(Value::as_str)
+            .unwrap_or("");
+        let include_self = params_val
+            .pointer("/arguments/includeSelf")
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+        let min = match params_val.pointer("/arguments/min").and_then(Value::as_str) {
+            Some(min_str) => match parse_duration(min_str) {
+                Ok(min) => Some(min),
+                Err(err) => {
+                    self.send_tool_error(transport, id, err.to_string()).await?;
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+
+        match self
+            .cal2prompt
+            .find_common_free_time(&emails, since_str, until_str, min, include_self)
+            .await
+        {
+            Ok(common) => {
+                self.send_structured_response(transport, id, serde_json::to_value(&common)?)
+                    .await?
+            }
+            Err(err) => {
+                self.send_tool_error(transport, id, tool_error_message(&err))
+                    .await?
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_insert_calendar_event(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let reminders = match params_val.pointer("/arguments/reminders") {
+            Some(value) => match serde_json::from_value::<Vec<ReminderOverride>>(value.clone()) {
+                Ok(reminders) => Some(reminders),
+                Err(err) => {
+                    self.send_tool_error(transport, id, format!("Invalid reminders: {}", err))
+                        .await?;
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+
+        let event = NewEvent {
+            summary: optional_string_argument(params_val, "summary").unwrap_or_default(),
+            description: optional_string_argument(params_val, "description"),
+            start: optional_string_argument(params_val, "start").unwrap_or_default(),
+            end: optional_string_argument(params_val, "end").unwrap_or_default(),
+            time_zone: optional_string_argument(params_val, "timeZone"),
+            all_day: params_val
+                .pointer("/arguments/allDay")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+            location: optional_string_argument(params_val, "location"),
+            attendees: string_array_argument(params_val, "attendees"),
+            reminders,
+            color_id: optional_string_argument(params_val, "colorId"),
+            visibility: optional_string_argument(params_val, "visibility"),
+            recurrence: string_array_argument(params_val, "recurrence"),
+            send_updates: optional_string_argument(params_val, "sendUpdates"),
+            create_meet: params_val
+                .pointer("/arguments/createMeet")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+        };
+
+        match self.cal2prompt.insert_event(event).await {
             Ok(res) => {
-                let obj_as_str = serde_json::to_string(&res)?;
-                self.send_text_response(transport, id, &obj_as_str).await?;
+                self.send_structured_response(transport, id, serde_json::to_value(&res)?)
+                    .await?
             }
             Err(err) => {
-                let (code, message) = match err.downcast_ref::<CalendarServiceError>() {
-                    Some(CalendarServiceError::NoCalendarId) => {
-                        (JsonRpcErrorCode::InvalidParams, err.to_string())
-                    }
-                    None => (
-                        JsonRpcErrorCode::InternalError,
-                        format!("Unexpected error: {}", err),
-                    ),
-                };
+                self.send_tool_error(transport, id, tool_error_message(&err))
+                    .await?
+            }
+        }
 
-                self.send_error_response(transport, id, code, message)
-                    .await?;
+        Ok(())
+    }
+
+    async fn handle_update_calendar_event(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let event_id = optional_string_argument(params_val, "eventId").unwrap_or_default();
+        let calendar_id = optional_string_argument(params_val, "calendarId");
+        let changes = EventChanges {
+            summary: optional_string_argument(params_val, "summary"),
+            description: optional_string_argument(params_val, "description"),
+            start: optional_string_argument(params_val, "start"),
+            end: optional_string_argument(params_val, "end"),
+            location: optional_string_argument(params_val, "location"),
+            attendees: params_val
+                .pointer("/arguments/attendees")
+                .is_some()
+                .then(|| string_array_argument(params_val, "attendees")),
+            time_zone: optional_string_argument(params_val, "timeZone"),
+        };
+
+        match self
+            .cal2prompt
+            .update_event(calendar_id.as_deref(), &event_id, changes)
+            .await
+        {
+            Ok(res) => {
+                self.send_structured_response(transport, id, serde_json::to_value(&res)?)
+                    .await?
+            }
+            Err(err) => {
+                self.send_tool_error(transport, id, tool_error_message(&err))
+                    .await?
             }
         }
 
         Ok(())
     }
 
-    async fn send_text_response(
+    async fn handle_move_calendar_event(
         &self,
-        transport: &StdioTransport,
-        id: u64,
-        text: &str,
+        transport: &dyn Transport,
+        id: &RequestId,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let event_id = optional_string_argument(params_val, "eventId").unwrap_or_default();
+        let calendar_id = optional_string_argument(params_val, "calendarId");
+        let start = optional_string_argument(params_val, "start");
+        let destination = optional_string_argument(params_val, "destinationCalendarId");
+
+        match self
+            .cal2prompt
+            .move_event(
+                calendar_id.as_deref(),
+                &event_id,
+                start.as_deref(),
+                destination.as_deref(),
+            )
+            .await
+        {
+            Ok(res) => {
+                self.send_structured_response(transport, id, serde_json::to_value(&res)?)
+                    .await?
+            }
+            Err(err) => {
+                self.send_tool_error(transport, id, tool_error_message(&err))
+                    .await?
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_delete_calendar_event(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+        params_val: &serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let event_id = optional_string_argument(params_val, "eventId").unwrap_or_default();
+        let calendar_id = optional_string_argument(params_val, "calendarId");
+
+        match self
+            .cal2prompt
+            .delete_event(calendar_id.as_deref(), &event_id)
+            .await
+        {
+            Ok(res) => {
+                self.send_structured_response(transport, id, serde_json::to_value(&res)?)
+                    .await?
+            }
+            Err(err) => {
+                self.send_tool_error(transport, id, tool_error_message(&err))
+                    .await?
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reports invalid arguments as `InvalidParams`, unknown resources as
+    /// `ResourceNotFound` and anything else as an internal error.
+    async fn send_calendar_error(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+        err: anyhow::Error,
+    ) -> anyhow::Result<()> {
+        let (code, message) = if err.is::<CalendarServiceError>()
+            || err.is::<DateError>()
+            || err.is::<DateExprError>()
+            || err.is::<PromptError>()
+        {
+            (JsonRpcErrorCode::InvalidParams, err.to_string())
+        } else if err.is::<ResourceError>() {
+            (JsonRpcErrorCode::ResourceNotFound, err.to_string())
+        } else {
+            (
+                JsonRpcErrorCode::InternalError,
+                format!("Unexpected error: {}", err),
+            )
+        };
+
+        self.send_error_response(transport, id, code, message).await
+    }
+
+    /// Reads `uri`, returning its MIME type and contents.
+    async fn read_resource(
+        &self,
+        uri: &str,
+        progress: Option<FetchProgress>,
+    ) -> anyhow::Result<(&'static str, String)> {
+        let resource = ResourceUri::parse(uri, self.cal2prompt.calendar_ids())?;
+        let (since, until) = match &resource.range {
+            ResourceRange::Shortcut(duration) => self.cal2prompt.shortcut_range(duration)?,
+            ResourceRange::Day(date) => (date.to_string(), date.to_string()),
+            ResourceRange::Range(since, until) => (since.clone(), until.clone()),
+        };
+        let calendar_ids = match resource.calendar_id {
+            Some(calendar_id) => vec![calendar_id],
+            None => self.cal2prompt.calendar_ids().to_vec(),
+        };
+
+        let agenda = self
+            .cal2prompt
+            .fetch_agenda_from(&calendar_ids, &since, &until, progress.as_ref())
+            .await?;
+        let text = match resource.format {
+            ResourceFormat::Text => self.cal2prompt.render(&agenda)?,
+            ResourceFormat::Json => serde_json::to_string(&agenda)?,
+        };
+        Ok((resource.format.mime_type(), text))
+    }
+
+    /// Renders the prompt named in `params` over its `range` and `calendar`
+    /// arguments.
+    async fn get_prompt(
+        &self,
+        params: Option<&Value>,
+        progress: Option<FetchProgress>,
+    ) -> anyhow::Result<Value> {
+        let argument = |name: &str| {
+            params
+                .and_then(|params| params.pointer(&format!("/arguments/{}", name)))
+                .and_then(Value::as_str)
+        };
+        let name = params
+            .and_then(|params| params.get("name"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+
+        let all = prompts::all(self.cal2prompt.output());
+        let prompt = all
+            .iter()
+            .find(|prompt| prompt.name == name)
+            .ok_or_else(|| PromptError::UnknownPrompt(name.to_string()))?;
+
+        let range = argument("range").unwrap_or(prompt.default_range);
+        let (since, until) = self.cal2prompt.expression_range(range)?;
+        let calendar_ids = match argument("calendar") {
+            Some(calendar_id) => {
+                if !self
+                    .cal2prompt
+                    .calendar_ids()
+                    .iter()
+                    .any(|id| id == calendar_id)
+                {
+                    return Err(PromptError::UnknownCalendar(calendar_id.to_string()).into());
+                }
+                vec![calendar_id.to_string()]
+            }
+            None => self.cal2prompt.calendar_ids().to_vec(),
+        };
+
+        let agenda = self
+            .cal2prompt
+            .fetch_agenda_from(&calendar_ids, &since, &until, progress.as_ref())
+            .await?;
+        let text = generate(prompt.template, &agenda)?;
+        Ok(prompts::message(prompt, text))
+    }
+
+    /// Re-reads every subscribed resource and sends
+    /// `notifications/resources/updated` for those whose contents changed.
+    async fn poll_subscriptions(&self, transport: &dyn Transport) -> anyhow::Result<()> {
+        if !self.client_initialized.load(Ordering::SeqCst) || self.subscriptions().is_empty() {
+            return Ok(());
+        }
+        if let Err(err) = self.cal2prompt.ensure_valid_token().await {
+            eprintln!(
+                "[SERVER] Skipping resource poll, failed to refresh token: {}",
+                err
+            );
+            return Ok(());
+        }
+
+        let uris: Vec<String> = self.subscriptions().keys().cloned().collect();
+        for uri in uris {
+            let text = match self.read_resource(&uri, None).await {
+                Ok((_, text)) => text,
+                Err(err) => {
+                    eprintln!("[SERVER] Failed to poll resource {}: {}", uri, err);
+                    continue;
+                }
+            };
+            // Unsubscribed while it was being read, or unchanged.
+            match self.subscriptions().get_mut(&uri) {
+                Some(last) if *last != text => *last = text,
+                _ => continue,
+            }
+
+            let notification = Message::Notification {
+                jsonrpc: "2.0".to_string(),
+                method: "notifications/resources/updated".to_string(),
+                params: Some(json!({ "uri": uri })),
+            };
+            transport.send(notification).await?;
+        }
+
+        Ok(())
+    }
+
+    fn subscriptions(&self) -> MutexGuard<'_, BTreeMap<String, String>> {
+        self.subscriptions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Runs `future` unless `notifications/cancelled` names request `id`
+    /// first, in which case it is dropped and `None` returned.
+    async fn cancellable<F: Future>(&self, id: &RequestId, future: F) -> Option<F::Output> {
+        let (handle, registration) = AbortHandle::new_pair();
+        self.in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id.clone(), handle);
+        let result = Abortable::new(future, registration).await;
+        self.in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(id);
+        result.ok()
+    }
+
+    /// Runs `fetch`, and when the request carries a `_meta.progressToken`
+    /// passes it a [`FetchProgress`] whose reports are sent on as
+    /// `notifications/progress`.
+    async fn with_progress<F, Fut, T>(
+        &self,
+        transport: &dyn Transport,
+        params: Option<&Value>,
+        fetch: F,
+    ) -> T
+    where
+        F: FnOnce(Option<FetchProgress>) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let Some(token) = params
+            .and_then(|params| params.pointer("/_meta/progressToken"))
+            .cloned()
+        else {
+            return fetch(None).await;
+        };
+
+        let (progress, mut updates) = mpsc::unbounded_channel();
+        // Ends once `fetch` is done and has dropped its sender.
+        let report = async {
+            while let Some((fetched, total)) = updates.recv().await {
+                let notification = Message::Notification {
+                    jsonrpc: "2.0".to_string(),
+                    method: "notifications/progress".to_string(),
+                    params: Some(json!({
+                        "progressToken": token,
+                        "progress": fetched,
+                        "total": total,
+                        "message": format!("Read {} of {} calendars", fetched, total),
+                    })),
+                };
+                if let Err(err) = transport.send(notification).await {
+                    eprintln!("[SERVER] Failed to send progress: {}", err);
+                }
+            }
+        };
+        let (result, ()) = futures::join!(fetch(Some(progress)), report);
+        result
+    }
+
+    async fn send_result(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+        result: Value,
     ) -> anyhow::Result<()> {
         let response = Message::Response {
             jsonrpc: "2.0".to_string(),
+            id: Some(id.clone()),
+            result: Some(result),
+            error: None,
+        };
+        transport.send(response).await?;
+        Ok(())
+    }
+
+    /// Sends a tool's output both as `structuredContent`, matching its
+    /// `outputSchema`, and as JSON text for clients that only read `content`.
+    async fn send_structured_response(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+        value: Value,
+    ) -> anyhow::Result<()> {
+        let text = serde_json::to_string(&value)?;
+        self.send_result(
+            transport,
             id,
-            result: Some(json!({
+            json!({
                 "content": [{
                     "type": "text",
                     "text": text,
                 }],
-            })),
-            error: None,
-        };
-        transport.send(response).await?;
-        Ok(())
+                "structuredContent": value,
+            }),
+        )
+        .await
+    }
+
+    /// Reports a failed tool call as a result with `isError`, so the model
+    /// sees what went wrong and can correct its arguments.
+    async fn send_tool_error(
+        &self,
+        transport: &dyn Transport,
+        id: &RequestId,
+        message: String,
+    ) -> anyhow::Result<()> {
+        self.send_result(
+            transport,
+            id,
+            json!({
+                "content": [{
+                    "type": "text",
+                    "text": message,
+                }],
+                "isError": true,
+            }),
+        )
+        .await
     }
 
     async fn send_error_response(
         &self,
-        transport: &StdioTransport,
-        id: u64,
+        transport: &dyn Transport,
+        id: &RequestId,
         code: JsonRpcErrorCode,
         message: String,
     ) -> anyhow::Result<()> {
-        let response = Message::Response {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: None,
-            error: Some(json!({
-                "code": code as i32,
-                "message": message,
-            })),
+        transport
+            .send(error_response(Some(id.clone()), code, message))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Explains a tool failure to the model: bad arguments and refused writes in
+/// their own words, anything else as unexpected.
+fn tool_error_message(err: &anyhow::Error) -> String {
+    if err.is::<CalendarServiceError>()
+        || err.is::<DateError>()
+        || err.is::<DateExprError>()
+        || err.is::<WriteGuardError>()
+    {
+        err.to_string()
+    } else {
+        format!("Unexpected error: {}", err)
+    }
+}
+
+/// The client's protocol version if we speak it, otherwise our newest one,
+/// which the client may then decline by disconnecting.
+fn negotiate_protocol_version(requested: Option<&str>) -> &'static str {
+    SUPPORTED_PROTOCOL_VERSIONS
+        .into_iter()
+        .find(|&version| Some(version) == requested)
+        .unwrap_or(SUPPORTED_PROTOCOL_VERSIONS[0])
+}
+
+fn error_response(id: Option<RequestId>, code: JsonRpcErrorCode, message: String) -> Message {
+    Message::Response {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: None,
+        error: Some(json!({
+            "code": code as i32,
+            "message": message,
+        })),
+    }
+}
+
+/// Checks that a `tools/call` names one of the tools in tools.json.
+fn check_tool_name(params: Option<&Value>) -> Result<(), String> {
+    let name = params
+        .and_then(|params| params.get("name"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| "Missing tool name".to_string())?;
+    let tools: Value = serde_json::from_str(TOOLS_JSON).expect("tools.json must be valid JSON");
+    let known = tools["tools"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|tool| tool["name"] == name);
+    if known {
+        Ok(())
+    } else {
+        Err(format!("Unknown tool: {}", name))
+    }
+}
+
+/// Collects the responses sent while handling a batch so they can be sent
+/// back together; notifications still go out as they happen.
+struct BatchTransport<'a> {
+    inner: &'a dyn Transport,
+    responses: std::sync::Mutex<Vec<Message>>,
+}
+
+impl<'a> BatchTransport<'a> {
+    fn new(inner: &'a dyn Transport) -> Self {
+        Self {
+            inner,
+            responses: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    fn into_responses(self) -> Vec<Message> {
+        self.responses
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[async_trait]
+impl Transport for BatchTransport<'_> {
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        match message {
+            Message::Response { .. } => {
+                self.responses
+                    .lock()
+                    .map_err(|_| Error::Other("Failed to lock batch responses".into()))?
+                    .push(message);
+                Ok(())
+            }
+            message => self.inner.send(message).await,
+        }
+    }
+
+    fn receive(&self) -> Pin<Box<dyn Stream<Item = Result<Message, Error>> + Send>> {
+        Box::pin(stream::empty())
+    }
+
+    async fn close(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+fn optional_string_argument(params_val: &serde_json::Value, name: &str) -> Option<String> {
+    params_val
+        .pointer(&format!("/arguments/{}", name))
+        .and_then(Value::as_str)
+        .map(String::from)
+}
+
+fn string_array_argument(params_val: &serde_json::Value, name: &str) -> Vec<String> {
+    params_val
+        .pointer(&format!("/arguments/{}", name))
+        .and_then(Value::as_array)
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn resource_uri(params: Option<&serde_json::Value>) -> String {
+    params
+        .and_then(|params| params.get("uri"))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::load_config;
+    use crate::core::cal2prompt::CommonFreeTime;
+    use crate::core::schedule::{Availability, BusySpan, FreeSlot};
+    use crate::core::write_guard::WriteOutcome;
+    use crate::google::calendar::service::PlannedRequest;
+    use crate::mcp::schema::assert_matches_output_schema;
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    /// Feeds raw lines to the handler the way `StdioTransport` would, and
+    /// keeps everything it sends.
+    struct MemoryTransport {
+        incoming: std::sync::Mutex<Vec<Result<Message, Error>>>,
+        outgoing: mpsc::UnboundedSender<Message>,
+    }
+
+    #[async_trait]
+    impl Transport for MemoryTransport {
+        async fn send(&self, message: Message) -> Result<(), Error> {
+            self.outgoing
+                .send(message)
+                .map_err(|e| Error::Other(e.to_string()))
+        }
+
+        fn receive(&self) -> Pin<Box<dyn Stream<Item = Result<Message, Error>> + Send>> {
+            let incoming = std::mem::take(&mut *self.incoming.lock().unwrap());
+            Box::pin(stream::iter(incoming))
+        }
+
+        async fn close(&self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn test_cal2prompt() -> anyhow::Result<Cal2Prompt> {
+        let temp_dir = tempfile::tempdir()?;
+        let config_file_path = temp_dir.path().join("config.lua");
+        std::fs::write(
+            &config_file_path,
+            r#"
+return {
+  source = {
+    google = {
+      oauth2 = { clientID = "id", clientSecret = "secret", redirectURL = "http://127.0.0.1:9004" },
+      calendar = { getEvents = { calendarIDs = { "test@example.com" } } },
+    },
+  },
+  output = { template = "{{ days|length }} days" },
+}
+"#,
+        )?;
+        Ok(Cal2Prompt::from_config(Arc::new(load_config(
+            &config_file_path,
+        )?)))
+    }
+
+    /// Runs a session over `lines` and returns what the server sent back.
+    async fn run(lines: &[&str]) -> anyhow::Result<Vec<Value>> {
+        let cal2prompt = test_cal2prompt()?;
+
+        let (outgoing, mut sent) = mpsc::unbounded_channel();
+        let transport = MemoryTransport {
+            incoming: std::sync::Mutex::new(
+                lines
+                    .iter()
+                    .map(|line| {
+                        serde_json::from_str::<Message>(line)
+                            .map_err(|e| Error::Serialization(e.to_string()))
+                    })
+                    .collect(),
+            ),
+            outgoing,
         };
-        transport.send(response).await?;
+        McpHandler::new(&cal2prompt).launch_mcp(&transport).await?;
+        drop(transport);
+
+        let mut responses = Vec::new();
+        while let Some(message) = sent.recv().await {
+            responses.push(serde_json::to_value(message)?);
+        }
+        Ok(responses)
+    }
+
+    /// The response to request `id`; requests after initialization are
+    /// answered in whatever order they finish.
+    fn response(responses: &[Value], id: Value) -> &Value {
+        responses
+            .iter()
+            .find(|response| response.is_object() && response["id"] == id)
+            .unwrap_or_else(|| panic!("no response to {id} in {responses:#?}"))
+    }
+
+    const INITIALIZE: &str = r#"{"jsonrpc":"2.0","id":"init","method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"0"}}}"#;
+    const INITIALIZED: &str = r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#;
+
+    #[tokio::test]
+    async fn test_lifecycle_and_errors() -> anyhow::Result<()> {
+        let responses = run(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#,
+            INITIALIZE,
+            INITIALIZED,
+            r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":"init"}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"no/such/method"}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{"name":"no_such_tool","arguments":{}}}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"tools/call"}"#,
+            r#"{"jsonrpc":"2.0","id":"#,
+            r#"{"jsonrpc":"2.0","id":6,"method":5}"#,
+            r#"{"jsonrpc":"2.0","id":7}"#,
+            r#"{"jsonrpc":"2.0","id":8,"result":{}}"#,
+        ])
+        .await?;
+
+        // Notifications and responses are never answered.
+        assert_eq!(responses.len(), 9, "{responses:#?}");
+
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["error"]["code"], -32600);
+        assert_eq!(
+            responses[1],
+            json!({ "jsonrpc": "2.0", "id": 2, "result": {} })
+        );
+        assert_eq!(responses[2]["id"], "init");
+        assert_eq!(responses[2]["result"]["serverInfo"]["name"], "cal2prompt");
+        assert_eq!(
+            responses[2]["result"]["serverInfo"]["version"],
+            env!("CARGO_PKG_VERSION")
+        );
+        assert_eq!(responses[2]["result"]["protocolVersion"], "2024-11-05");
+        assert_eq!(response(&responses, json!(3))["error"]["code"], -32601);
+        assert_eq!(response(&responses, json!(4))["error"]["code"], -32602);
+        assert_eq!(response(&responses, json!(5))["error"]["code"], -32602);
+        assert_eq!(response(&responses, Value::Null)["error"]["code"], -32700);
+        // Not a request, but not a response either.
+        assert_eq!(response(&responses, json!(6))["error"]["code"], -32600);
+        assert_eq!(response(&responses, json!(7))["error"]["code"], -32600);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_protocol_version_and_tools() -> anyhow::Result<()> {
+        let responses = run(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2099-01-01"}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+        ])
+        .await?;
+
+        // An unknown version is answered with the newest one we speak.
+        assert_eq!(
+            responses[0]["result"]["protocolVersion"],
+            SUPPORTED_PROTOCOL_VERSIONS[0]
+        );
+        assert_eq!(negotiate_protocol_version(Some("2025-03-26")), "2025-03-26");
+
+        for tool in responses[1]["result"]["tools"].as_array().unwrap() {
+            let annotations = &tool["annotations"];
+            assert!(annotations["readOnlyHint"].is_boolean(), "{tool}");
+            if annotations["readOnlyHint"] == false {
+                assert!(annotations["destructiveHint"].is_boolean(), "{tool}");
+            }
+            assert_eq!(tool["outputSchema"]["type"], "object", "{tool}");
+        }
+
+        // What the tools return, built from the types they serialize.
+        let slot = FreeSlot {
+            date: "2025-03-03".to_string(),
+            weekday: "Monday".to_string(),
+            start: "10:00".to_string(),
+            end: "11:30".to_string(),
+            minutes: 90,
+        };
+        assert_matches_output_schema("find_free_slots", &json!({ "free_slots": [&slot] }));
+        let availability = Availability {
+            id: "alice@example.com".to_string(),
+            busy: vec![BusySpan {
+                start: "2025-03-03 09:00".to_string(),
+                end: "2025-03-03 10:00".to_string(),
+            }],
+            errors: vec![],
+        };
+        assert_matches_output_schema("get_free_busy", &json!({ "calendars": [availability] }));
+        assert_matches_output_schema(
+            "find_common_free_time",
+            &serde_json::to_value(CommonFreeTime {
+                free_slots: vec![slot],
+                unavailable: vec!["bob@example.com".to_string()],
+            })?,
+        );
+
+        let dry_run = || WriteOutcome::DryRun {
+            dry_run: true,
+            requests: vec![PlannedRequest {
+                method: "DELETE",
+                url:
+                    "https://www.googleapis.com/calendar/v3/calendars/work@example.com/events/evt1"
+                        .to_string(),
+                query: BTreeMap::new(),
+                body: None,
+            }],
+        };
+        let written = || {
+            serde_json::from_value(json!({
+                "id": "evt1",
+                "summary": "Planning",
+                "htmlLink": "https://www.google.com/calendar/event?eid=evt1",
+                "start": { "dateTime": "2025-03-03T10:00:00+09:00" },
+                "end": { "dateTime": "2025-03-03T11:00:00+09:00" },
+            }))
+            .map(WriteOutcome::Written)
+        };
+        for tool in [
+            "insert_calendar_event",
+            "update_calendar_event",
+            "move_calendar_event",
+        ] {
+            assert_matches_output_schema(tool, &serde_json::to_value(dry_run())?);
+            assert_matches_output_schema(tool, &serde_json::to_value(written()?)?);
+        }
+        assert_matches_output_schema("delete_calendar_event", &serde_json::to_value(dry_run())?);
+        assert_matches_output_schema(
+            "delete_calendar_event",
+            &serde_json::to_value(WriteOutcome::Deleted {
+                id: "evt1".to_string(),
+                deleted: true,
+            })?,
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_batches() -> anyhow::Result<()> {
+        let responses = run(&[
+            INITIALIZE,
+            r#"[
+                {"jsonrpc":"2.0","id":1,"method":"resources/templates/list"},
+                {"jsonrpc":"2.0","method":"notifications/initialized"},
+                {"jsonrpc":"2.0","id":"two","method":"prompts/list"},
+                {"jsonrpc":"2.0","id":3,"method":"no/such/method"}
+            ]"#,
+            r#"[{"jsonrpc":"2.0","method":"notifications/initialized"}]"#,
+            "[]",
+        ])
+        .await?;
+
+        // A batch of notifications gets no answer at all.
+        assert_eq!(responses.len(), 3, "{responses:#?}");
+
+        let batch = responses
+            .iter()
+            .find_map(Value::as_array)
+            .expect("an array of responses");
+        // Within a batch, responses keep the order of the requests.
+        assert_eq!(batch.len(), 3);
+        assert_eq!(batch[0]["id"], 1);
+        assert!(batch[0]["result"]["resourceTemplates"].is_array());
+        assert_eq!(batch[1]["id"], "two");
+        assert_eq!(batch[1]["result"]["prompts"][0]["name"], "schedule");
+        assert_eq!(batch[2]["id"], 3);
+        assert_eq!(batch[2]["error"]["code"], -32601);
+
+        assert_eq!(response(&responses, Value::Null)["error"]["code"], -32600);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cancellation() -> anyhow::Result<()> {
+        let cal2prompt = test_cal2prompt()?;
+        let handler = McpHandler::new(&cal2prompt);
+        let id = RequestId::String("slow".to_string());
+
+        let (result, ()) = futures::join!(
+            handler.cancellable(&id, futures::future::pending::<()>()),
+            async {
+                tokio::task::yield_now().await;
+                handler.handle_notification(
+                    "notifications/cancelled",
+                    Some(&json!({ "requestId": "slow", "reason": "took too long" })),
+                );
+            }
+        );
+        assert_eq!(result, None);
+        assert!(handler.in_flight.lock().unwrap().is_empty());
+
+        // Finished requests are forgotten, so a late cancellation is ignored.
+        let result = handler.cancellable(&id, async { 42 }).await;
+        assert_eq!(result, Some(42));
+        handler.handle_notification(
+            "notifications/cancelled",
+            Some(&json!({ "requestId": "slow" })),
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_free_busy_tools_report_bad_dates() -> anyhow::Result<()> {
+        let cal2prompt = test_cal2prompt()?;
+        let handler = McpHandler::new(&cal2prompt);
+        let (outgoing, mut sent) = mpsc::unbounded_channel();
+        let transport = MemoryTransport {
+            incoming: std::sync::Mutex::new(vec![]),
+            outgoing,
+        };
+        let calls = [
+            json!({ "name": "find_free_slots", "arguments": { "since": "soon", "until": "2025-01-31" } }),
+            json!({ "name": "find_free_slots", "arguments": { "since": "2025-01-27", "until": "2025-01-31", "min": "a while" } }),
+            json!({ "name": "get_free_busy", "arguments": { "emails": ["a@example.com"], "since": "2025-01-27", "until": "later" } }),
+            json!({ "name": "find_common_free_time", "arguments": { "emails": ["a@example.com"], "since": "2025-02-30", "until": "2025-03-01" } }),
+        ];
+        for (i, params) in calls.into_iter().enumerate() {
+            handler
+                .handle_tools_call(&transport, &RequestId::Number(i as i64), params)
+                .await?;
+        }
+
+        drop(transport);
+        let mut responses = Vec::new();
+        while let Some(message) = sent.recv().await {
+            responses.push(serde_json::to_value(message)?);
+        }
+        assert_eq!(responses.len(), 4, "{responses:#?}");
+        // Like every other tool, bad arguments are a tool error the model
+        // can read, not a protocol error.
+        for (i, response) in responses.iter().enumerate() {
+            assert_eq!(response["id"], i, "{response:#?}");
+            assert_eq!(response["result"]["isError"], true, "{response:#?}");
+            let text = response["result"]["content"][0]["text"].as_str().unwrap();
+            assert!(text.starts_with("Invalid"), "{text}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_progress() -> anyhow::Result<()> {
+        let cal2prompt = test_cal2prompt()?;
+        let handler = McpHandler::new(&cal2prompt);
+        let (outgoing, mut sent) = mpsc::unbounded_channel();
+        let transport = MemoryTransport {
+            incoming: std::sync::Mutex::new(vec![]),
+            outgoing,
+        };
+        let fetch = |progress: Option<FetchProgress>| async move {
+            let Some(progress) = progress else {
+                return "no progress";
+            };
+            progress.send((1, 2)).unwrap();
+            tokio::task::yield_now().await;
+            progress.send((2, 2)).unwrap();
+            "done"
+        };
+
+        let result = handler.with_progress(&transport, None, fetch).await;
+        assert_eq!(result, "no progress");
+
+        let params = json!({ "name": "list_calendar_events", "_meta": { "progressToken": 7 } });
+        let result = handler
+            .with_progress(&transport, Some(&params), fetch)
+            .await;
+        assert_eq!(result, "done");
+
+        drop(transport);
+        let mut notifications = Vec::new();
+        while let Some(message) = sent.recv().await {
+            notifications.push(serde_json::to_value(message)?);
+        }
+        assert_eq!(notifications.len(), 2, "{notifications:#?}");
+        assert_eq!(notifications[0]["method"], "notifications/progress");
+        assert_eq!(notifications[0]["params"]["progressToken"], 7);
+        assert_eq!(notifications[0]["params"]["progress"], 1);
+        assert_eq!(notifications[1]["params"]["progress"], 2);
+        assert_eq!(notifications[1]["params"]["total"], 2);
+
         Ok(())
     }
 }
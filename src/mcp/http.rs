@@ -0,0 +1,1152 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use futures::Stream;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::config::McpHttp;
+use crate::core::cal2prompt::JsonRpcErrorCode;
+use crate::mcp::handler::SUPPORTED_PROTOCOL_VERSIONS;
+use crate::mcp::stdio::{Error, Message, RequestId, Transport};
+
+/// The single Streamable HTTP endpoint.
+const ENDPOINT: &str = "/mcp";
+const SESSION_HEADER: &str = "mcp-session-id";
+const PROTOCOL_VERSION_HEADER: &str = "mcp-protocol-version";
+const MAX_BODY_BYTES: usize = 4 * 1024 * 1024;
+/// The request line and headers together.
+const MAX_HEAD_BYTES: usize = 16 * 1024;
+const MAX_HEADERS: usize = 64;
+/// How long a client may take to send its whole request.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const SSE_KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// How long a POST waits for its responses before the requests are cancelled.
+/// Long enough for a first-run OAuth flow in the browser.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(300);
+
+type Pending = Arc<Mutex<HashMap<RequestId, oneshot::Sender<Message>>>>;
+
+/// The server end of one MCP session over HTTP.
+///
+/// Responses go back on the POST that carried the request; everything else
+/// (e.g. `notifications/resources/updated`) goes to the session's SSE streams.
+pub struct SessionTransport {
+    incoming: Mutex<Option<mpsc::UnboundedReceiver<Result<Message, Error>>>>,
+    pending: Pending,
+    events: broadcast::Sender<Message>,
+}
+
+#[async_trait]
+impl Transport for SessionTransport {
+    async fn send(&self, message: Message) -> Result<(), Error> {
+        // The responses to a batch are routed one by one, and put back
+        // together by the POST that carried it.
+        let messages = match message {
+            Message::Batch(messages) => messages,
+            message => vec![message],
+        };
+        for message in messages {
+            if let Message::Response { id: Some(id), .. } = &message {
+                let waiter = self
+                    .pending
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .remove(id);
+                if let Some(waiter) = waiter {
+                    let _ = waiter.send(message);
+                    continue;
+                }
+            }
+            // Dropped when no SSE stream is open, as the client is not listening.
+            let _ = self.events.send(message);
+        }
+        Ok(())
+    }
+
+    /// The messages POSTed to this session. Only the first call gets them.
+    fn receive(&self) -> Pin<Box<dyn Stream<Item = Result<Message, Error>> + Send>> {
+        let receiver = self
+            .incoming
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        match receiver {
+            Some(receiver) => Box::pin(futures::stream::unfold(
+                receiver,
+                |mut receiver| async move { receiver.recv().await.map(|msg| (msg, receiver)) },
+            )),
+            None => Box::pin(futures::stream::empty()),
+        }
+    }
+
+    async fn close(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+struct Session {
+    inbox: mpsc::UnboundedSender<Result<Message, Error>>,
+    pending: Pending,
+    events: broadcast::Sender<Message>,
+    task: JoinHandle<()>,
+    /// When the session last got a request or kept a stream alive.
+    last_active: Arc<Mutex<Instant>>,
+}
+
+impl Session {
+    fn touch(last_active: &Mutex<Instant>) {
+        *last_active.lock().unwrap_or_else(PoisonError::into_inner) = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_active
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .elapsed()
+    }
+
+    /// Stops the session's task and wakes any POST still waiting on it.
+    fn close(&self) {
+        self.task.abort();
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// Stops waiting for the requests that `message` cancels; their POSTs
+    /// leave them out, as the handler will not answer them.
+    fn release_cancelled(&self, message: &Message) {
+        let messages = match message {
+            Message::Batch(messages) => messages.as_slice(),
+            message => std::slice::from_ref(message),
+        };
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        for message in messages {
+            if let Message::Notification { method, params, .. } = message {
+                if method != "notifications/cancelled" {
+                    continue;
+                }
+                let id = params
+                    .as_ref()
+                    .and_then(|params| params.get("requestId"))
+                    .and_then(|id| serde_json::from_value::<RequestId>(id.clone()).ok());
+                if let Some(id) = id {
+                    pending.remove(&id);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum RequestError {
+    #[error("Malformed request: {0}")]
+    Malformed(String),
+
+    #[error("Request headers too large")]
+    HeadersTooLarge,
+
+    #[error("Request body too large")]
+    BodyTooLarge,
+
+    #[error("Transfer-Encoding is not supported; send Content-Length")]
+    LengthRequired,
+
+    #[error("Timed out reading the request")]
+    Timeout,
+
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl RequestError {
+    fn status(&self) -> &'static str {
+        match self {
+            RequestError::Malformed(_) | RequestError::Io(_) => "400 Bad Request",
+            RequestError::HeadersTooLarge => "431 Request Header Fields Too Large",
+            RequestError::BodyTooLarge => "413 Content Too Large",
+            RequestError::LengthRequired => "411 Length Required",
+            RequestError::Timeout => "408 Request Timeout",
+        }
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    /// Header names are lowercased.
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+struct Server<F> {
+    policy: McpHttp,
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+    start_session: F,
+}
+
+/// Aborts the task when dropped, so it ends with the future that owns it.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Serves MCP over Streamable HTTP on `listener` until it fails.
+///
+/// `start_session` is called with the transport of each new session (an
+/// `initialize` POST without `Mcp-Session-Id`) and returns the task handling
+/// it, which is aborted when the client deletes the session or it has been
+/// idle for `policy.session_idle_timeout_seconds`.
+pub async fn serve<F>(
+    listener: TcpListener,
+    policy: McpHttp,
+    start_session: F,
+) -> anyhow::Result<()>
+where
+    F: Fn(SessionTransport) -> JoinHandle<()> + Send + Sync + 'static,
+{
+    let idle_timeout = Duration::from_secs(policy.session_idle_timeout_seconds);
+    let server = Arc::new(Server {
+        policy,
+        sessions: Mutex::new(HashMap::new()),
+        start_session,
+    });
+
+    let reaper = tokio::spawn({
+        let server = server.clone();
+        async move {
+            let mut ticks = tokio::time::interval(idle_timeout.min(Duration::from_secs(60)));
+            loop {
+                ticks.tick().await;
+                server.close_idle_sessions(idle_timeout);
+            }
+        }
+    });
+    let _reaper = AbortOnDrop(reaper);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(err) = server.handle_connection(stream).await {
+                eprintln!("[HTTP] Error handling connection: {:?}", err);
+            }
+        });
+    }
+}
+
+impl<F> Server<F>
+where
+    F: Fn(SessionTransport) -> JoinHandle<()>,
+{
+    /// Handles a single request; every connection is closed afterwards.
+    async fn handle_connection(&self, stream: TcpStream) -> anyhow::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let read = tokio::time::timeout(READ_TIMEOUT, read_request(&mut reader))
+            .await
+            .unwrap_or(Err(RequestError::Timeout));
+        let request = match read {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                return respond(reader.get_mut(), err.status(), &[], &err.to_string()).await
+            }
+        };
+        let stream = reader.get_mut();
+
+        if request.path.split('?').next() != Some(ENDPOINT) {
+            return respond(stream, "404 Not Found", &[], "Not Found").await;
+        }
+        if !self.origin_allowed(request.header("origin")) {
+            return respond(stream, "403 Forbidden", &[], "Origin not allowed").await;
+        }
+        if !self.authorized(request.header("authorization")) {
+            return respond(
+                stream,
+                "401 Unauthorized",
+                &[("www-authenticate", "Bearer")],
+                "Unauthorized",
+            )
+            .await;
+        }
+        // Clients send the negotiated version after initializing; a missing
+        // header means an older client.
+        if let Some(version) = request.header(PROTOCOL_VERSION_HEADER) {
+            if !SUPPORTED_PROTOCOL_VERSIONS.contains(&version) {
+                return respond(
+                    stream,
+                    "400 Bad Request",
+                    &[],
+                    &format!("Unsupported MCP-Protocol-Version: {}", version),
+                )
+                .await;
+            }
+        }
+
+        match request.method.as_str() {
+            "POST" => self.handle_post(stream, &request).await,
+            "GET" => self.handle_get(stream, &request).await,
+            "DELETE" => self.handle_delete(stream, &request).await,
+            _ => {
+                respond(
+                    stream,
+                    "405 Method Not Allowed",
+                    &[("allow", "GET, POST, DELETE")],
+                    "Method Not Allowed",
+                )
+                .await
+            }
+        }
+    }
+
+    async fn handle_post(&self, stream: &mut TcpStream, request: &Request) -> anyhow::Result<()> {
+        let message: Message = match serde_json::from_slice(&request.body) {
+            // Nothing to route an answer by.
+            Ok(Message::Invalid { id: None, reason }) => {
+                let body = json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {
+                        "code": JsonRpcErrorCode::InvalidRequest as i32,
+                        "message": format!("Invalid Request: {}", reason),
+                    },
+                });
+                return respond(
+                    stream,
+                    "400 Bad Request",
+                    &[("content-type", "application/json")],
+                    &body.to_string(),
+                )
+                .await;
+            }
+            Ok(message) => message,
+            Err(err) => {
+                let body = json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {
+                        "code": JsonRpcErrorCode::ParseError as i32,
+                        "message": format!("Parse error: {}", err),
+                    },
+                });
+                return respond(
+                    stream,
+                    "400 Bad Request",
+                    &[("content-type", "application/json")],
+                    &body.to_string(),
+                )
+                .await;
+            }
+        };
+
+        let (session_id, session) = match request.header(SESSION_HEADER) {
+            Some(session_id) => match self.session(session_id) {
+                Some(session) => (session_id.to_string(), session),
+                None => return respond(stream, "404 Not Found", &[], "Session not found").await,
+            },
+            None if matches!(&message, Message::Request { method, .. } if method == "initialize") => {
+                match self.create_session()? {
+                    Some(session) => session,
+                    None => {
+                        return respond(
+                            stream,
+                            "503 Service Unavailable",
+                            &[],
+                            "Too many open sessions",
+                        )
+                        .await
+                    }
+                }
+            }
+            None => {
+                return respond(
+                    stream,
+                    "400 Bad Request",
+                    &[],
+                    "Missing Mcp-Session-Id header",
+                )
+                .await
+            }
+        };
+
+        // Requests, and invalid messages with an ID, are answered.
+        let answered_id = |message: &Message| match message {
+            Message::Request { id, .. } | Message::Invalid { id: Some(id), .. } => Some(id.clone()),
+            _ => None,
+        };
+        let request_ids: Vec<RequestId> = match &message {
+            Message::Batch(messages) => messages.iter().filter_map(answered_id).collect(),
+            message => answered_id(message).into_iter().collect(),
+        };
+        session.release_cancelled(&message);
+        if request_ids.is_empty() {
+            // Notifications and responses need no answer.
+            session.inbox.send(Ok(message))?;
+            return respond(stream, "202 Accepted", &[], "").await;
+        }
+
+        let waiters: Vec<(RequestId, oneshot::Receiver<Message>)> = {
+            let mut pending = session
+                .pending
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            request_ids
+                .into_iter()
+                .map(|id| {
+                    let (waiter, response) = oneshot::channel();
+                    pending.insert(id.clone(), waiter);
+                    (id, response)
+                })
+                .collect()
+        };
+        let is_batch = matches!(message, Message::Batch(_));
+        session.inbox.send(Ok(message))?;
+
+        let deadline = tokio::time::Instant::now() + RESPONSE_TIMEOUT;
+        let mut responses = Vec::with_capacity(waiters.len());
+        for (id, response) in waiters {
+            match tokio::time::timeout_at(deadline, response).await {
+                Ok(Ok(response)) => responses.push(response),
+                // Cancelled by the client, or the session has gone.
+                Ok(Err(_)) => {}
+                Err(_) => {
+                    session
+                        .pending
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .remove(&id);
+                    // Stop the handler too; nothing is waiting for its answer.
+                    let _ = session.inbox.send(Ok(Message::Notification {
+                        jsonrpc: "2.0".to_string(),
+                        method: "notifications/cancelled".to_string(),
+                        params: Some(json!({"requestId": id, "reason": "Request timed out"})),
+                    }));
+                    responses.push(Message::Response {
+                        jsonrpc: "2.0".to_string(),
+                        id: Some(id),
+                        result: None,
+                        error: Some(json!({
+                            "code": JsonRpcErrorCode::InternalError as i32,
+                            "message": "Request timed out",
+                        })),
+                    });
+                }
+            }
+        }
+        if self.session(&session_id).is_none() {
+            return respond(stream, "404 Not Found", &[], "Session not found").await;
+        }
+        if responses.is_empty() {
+            // Every request was cancelled, so there is nothing to send back.
+            return respond(
+                stream,
+                "204 No Content",
+                &[(SESSION_HEADER, &session_id)],
+                "",
+            )
+            .await;
+        }
+        let body = if is_batch {
+            serde_json::to_string(&responses)?
+        } else {
+            serde_json::to_string(&responses[0])?
+        };
+        respond(
+            stream,
+            "200 OK",
+            &[
+                ("content-type", "application/json"),
+                (SESSION_HEADER, &session_id),
+            ],
+            &body,
+        )
+        .await
+    }
+
+    /// Streams the session's server-to-client messages as server-sent events.
+    async fn handle_get(&self, stream: &mut TcpStream, request: &Request) -> anyhow::Result<()> {
+        if !request
+            .header("accept")
+            .is_some_and(|accept| accept.contains("text/event-stream"))
+        {
+            return respond(stream, "406 Not Acceptable", &[], "Not Acceptable").await;
+        }
+        let Some(session) = request
+            .header(SESSION_HEADER)
+            .and_then(|session_id| self.session(session_id))
+        else {
+            return respond(stream, "404 Not Found", &[], "Session not found").await;
+        };
+
+        let mut events = session.events.subscribe();
+        let last_active = session.last_active.clone();
+        // Deleting the session ends the stream, rather than the stream
+        // keeping it around.
+        drop(session);
+
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncache-control: no-cache\r\n\r\n",
+            )
+            .await?;
+        let mut keep_alive = tokio::time::interval(SSE_KEEP_ALIVE);
+        loop {
+            let chunk = tokio::select! {
+                event = events.recv() => match event {
+                    Ok(message) => format!("event: message\ndata: {}\n\n", serde_json::to_string(&message)?),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = keep_alive.tick() => ": keep-alive\n\n".to_string(),
+            };
+            // The client went away.
+            if stream.write_all(chunk.as_bytes()).await.is_err() {
+                break;
+            }
+            Session::touch(&last_active);
+        }
+        Ok(())
+    }
+
+    async fn handle_delete(&self, stream: &mut TcpStream, request: &Request) -> anyhow::Result<()> {
+        let removed = request.header(SESSION_HEADER).and_then(|session_id| {
+            self.sessions
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(session_id)
+        });
+        match removed {
+            Some(session) => {
+                session.close();
+                respond(stream, "200 OK", &[], "").await
+            }
+            None => respond(stream, "404 Not Found", &[], "Session not found").await,
+        }
+    }
+
+    /// Looks up a session, which counts as activity on it.
+    fn session(&self, session_id: &str) -> Option<Arc<Session>> {
+        let session = self
+            .sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(session_id)
+            .cloned()?;
+        Session::touch(&session.last_active);
+        Some(session)
+    }
+
+    fn close_idle_sessions(&self, idle_timeout: Duration) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        sessions.retain(|session_id, session| {
+            let idle = session.idle_for() >= idle_timeout;
+            if idle {
+                eprintln!("[HTTP] Closing idle session {}", session_id);
+                session.close();
+            }
+            !idle
+        });
+    }
+
+    /// Starts a session, or returns `None` when `policy.max_sessions` are
+    /// already open.
+    fn create_session(&self) -> anyhow::Result<Option<(String, Arc<Session>)>> {
+        if self
+            .sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+            >= self.policy.max_sessions
+        {
+            return Ok(None);
+        }
+
+        let mut bytes = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| anyhow::anyhow!("failed to generate a session ID"))?;
+        let session_id = URL_SAFE_NO_PAD.encode(bytes);
+
+        let (inbox, incoming) = mpsc::unbounded_channel();
+        let pending = Pending::default();
+        let (events, _) = broadcast::channel(100);
+        let task = (self.start_session)(SessionTransport {
+            incoming: Mutex::new(Some(incoming)),
+            pending: pending.clone(),
+            events: events.clone(),
+        });
+
+        let session = Arc::new(Session {
+            inbox,
+            pending,
+            events,
+            task,
+            last_active: Arc::new(Mutex::new(Instant::now())),
+        });
+        self.sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(session_id.clone(), session.clone());
+        Ok(Some((session_id, session)))
+    }
+
+    /// Browsers always send `Origin`; only localhost and
+    /// `experimental.mcp.http.allowedOrigins` may call us, which keeps other
+    /// web pages (and DNS rebinding) out. Other clients send no `Origin`.
+    fn origin_allowed(&self, origin: Option<&str>) -> bool {
+        let Some(origin) = origin else {
+            return true;
+        };
+        is_loopback_origin(origin)
+            || self
+                .policy
+                .allowed_origins
+                .iter()
+                .any(|allowed| allowed == origin)
+    }
+
+    fn authorized(&self, authorization: Option<&str>) -> bool {
+        let Some(token) = &self.policy.bearer_token else {
+            return true;
+        };
+        let expected = format!("Bearer {}", token);
+        authorization.is_some_and(|authorization| {
+            // Compare in constant time so the token cannot be guessed byte by byte.
+            authorization.len() == expected.len()
+                && authorization
+                    .bytes()
+                    .zip(expected.bytes())
+                    .fold(0, |diff, (a, b)| diff | (a ^ b))
+                    == 0
+        })
+    }
+}
+
+fn is_loopback_origin(origin: &str) -> bool {
+    let Some((_, authority)) = origin.split_once("://") else {
+        return false;
+    };
+    let host = match authority.strip_prefix('[') {
+        Some(ipv6) => ipv6.split(']').next(),
+        None => authority.split(':').next(),
+    };
+    matches!(host, Some("localhost" | "127.0.0.1" | "::1"))
+}
+
+/// Reads the request line, headers and `Content-Length` body. Returns `None`
+/// when the client closed the connection without sending anything.
+async fn read_request(reader: &mut BufReader<TcpStream>) -> Result<Option<Request>, RequestError> {
+    let mut head_budget = MAX_HEAD_BYTES;
+    let mut request_line = String::new();
+    if read_head_line(reader, &mut request_line, &mut head_budget).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(RequestError::Malformed("bad request line".to_string()));
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if read_head_line(reader, &mut line, &mut head_budget).await? == 0
+            || line == "\r\n"
+            || line == "\n"
+        {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(RequestError::HeadersTooLarge);
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    // Only Content-Length framing is read, so a chunked body would be taken
+    // for the next request.
+    if headers.contains_key("transfer-encoding") {
+        return Err(RequestError::LengthRequired);
+    }
+    let content_length: usize = match headers.get("content-length") {
+        Some(value) => value
+            .parse()
+            .map_err(|_| RequestError::Malformed("bad Content-Length".to_string()))?,
+        None => 0,
+    };
+    if content_length > MAX_BODY_BYTES {
+        return Err(RequestError::BodyTooLarge);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Some(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        headers,
+        body,
+    }))
+}
+
+/// Reads one line of the request head, failing once the head as a whole
+/// goes over `MAX_HEAD_BYTES`.
+async fn read_head_line(
+    reader: &mut BufReader<TcpStream>,
+    line: &mut String,
+    budget: &mut usize,
+) -> Result<usize, RequestError> {
+    let read = (&mut *reader).take(*budget as u64).read_line(line).await?;
+    if read == *budget && !line.ends_with('\n') {
+        return Err(RequestError::HeadersTooLarge);
+    }
+    *budget -= read;
+    Ok(read)
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> anyhow::Result<()> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\ncontent-length: {}\r\nconnection: close\r\n",
+        status,
+        body.len()
+    );
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+    response.push_str(body);
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use serde_json::Value;
+
+    fn echo(id: RequestId, method: String) -> Message {
+        Message::Response {
+            jsonrpc: "2.0".to_string(),
+            id: Some(id),
+            result: Some(json!({ "method": method })),
+            error: None,
+        }
+    }
+
+    /// A session that answers every request with its method, and also sends
+    /// a notification for `notify`. `hang` is never answered.
+    fn echo_session(transport: SessionTransport) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut messages = transport.receive();
+            while let Some(Ok(message)) = messages.next().await {
+                let (id, method) = match message {
+                    Message::Request { id, method, .. } => (id, method),
+                    Message::Batch(messages) => {
+                        let responses = messages
+                            .into_iter()
+                            .filter_map(|message| match message {
+                                Message::Request { id, method, .. } if method != "hang" => {
+                                    Some(echo(id, method))
+                                }
+                                _ => None,
+                            })
+                            .collect();
+                        transport.send(Message::Batch(responses)).await.unwrap();
+                        continue;
+                    }
+                    _ => continue,
+                };
+                if method == "hang" {
+                    continue;
+                }
+                if method == "notify" {
+                    let notification = Message::Notification {
+                        jsonrpc: "2.0".to_string(),
+                        method: "notifications/resources/updated".to_string(),
+                        params: Some(json!({ "uri": "cal2prompt://today" })),
+                    };
+                    transport.send(notification).await.unwrap();
+                }
+                transport.send(echo(id, method)).await.unwrap();
+            }
+        })
+    }
+
+    fn open_policy() -> McpHttp {
+        McpHttp {
+            bearer_token: None,
+            allowed_origins: vec![],
+            max_sessions: 32,
+            session_idle_timeout_seconds: 1800,
+        }
+    }
+
+    async fn start_server(policy: McpHttp) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(serve(listener, policy, echo_session));
+        addr
+    }
+
+    struct Response {
+        status: u16,
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    async fn send(addr: &str, method: &str, headers: &[(&str, &str)], body: &str) -> Response {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut request = format!(
+            "{} /mcp HTTP/1.1\r\nhost: {}\r\ncontent-length: {}\r\n",
+            method,
+            addr,
+            body.len()
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        request.push_str(body);
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).await.unwrap();
+        let (head, body) = raw.split_once("\r\n\r\n").unwrap();
+        let mut lines = head.lines();
+        let status = lines.next().unwrap().split_whitespace().nth(1).unwrap();
+        Response {
+            status: status.parse().unwrap(),
+            headers: lines
+                .filter_map(|line| line.split_once(": "))
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: body.to_string(),
+        }
+    }
+
+    fn request(id: u64, method: &str) -> String {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method }).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_sessions() {
+        let addr = start_server(open_policy()).await;
+
+        let res = send(&addr, "POST", &[], &request(1, "tools/list")).await;
+        assert_eq!(res.status, 400);
+
+        let res = send(&addr, "POST", &[], &request(1, "initialize")).await;
+        assert_eq!(res.status, 200);
+        let session_id = res.headers[SESSION_HEADER].clone();
+        let body: Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(body["id"], 1);
+        assert_eq!(body["result"]["method"], "initialize");
+
+        let session = [(SESSION_HEADER, session_id.as_str())];
+        let res = send(&addr, "POST", &session, &request(2, "tools/list")).await;
+        assert_eq!(res.status, 200);
+        let body: Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(body["result"]["method"], "tools/list");
+
+        let res = send(
+            &addr,
+            "POST",
+            &[
+                (SESSION_HEADER, session_id.as_str()),
+                (PROTOCOL_VERSION_HEADER, "1999-01-01"),
+            ],
+            &request(3, "tools/list"),
+        )
+        .await;
+        assert_eq!(res.status, 400);
+
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": "a", "method": "resources/list" },
+            { "jsonrpc": "2.0", "method": "notifications/initialized" },
+            { "jsonrpc": "2.0", "id": 4, "method": "prompts/list" },
+        ])
+        .to_string();
+        let res = send(&addr, "POST", &session, &batch).await;
+        assert_eq!(res.status, 200);
+        let body: Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(body[0]["id"], "a");
+        assert_eq!(body[1]["id"], 4);
+        assert_eq!(body[1]["result"]["method"], "prompts/list");
+
+        let notification =
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }).to_string();
+        let res = send(&addr, "POST", &session, &notification).await;
+        assert_eq!(res.status, 202);
+
+        let res = send(&addr, "POST", &session, "{").await;
+        assert_eq!(res.status, 400);
+        let body: Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(body["error"]["code"], -32700);
+
+        assert_eq!(send(&addr, "DELETE", &session, "").await.status, 200);
+        let res = send(&addr, "POST", &session, &request(3, "tools/list")).await;
+        assert_eq!(res.status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_requests_are_left_out() {
+        let addr = start_server(open_policy()).await;
+        let res = send(&addr, "POST", &[], &request(1, "initialize")).await;
+        let session_id = res.headers[SESSION_HEADER].clone();
+
+        let cancel = |id: u64| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "notifications/cancelled",
+                "params": { "requestId": id },
+            })
+            .to_string()
+        };
+
+        let pending = tokio::spawn({
+            let (addr, session_id) = (addr.clone(), session_id.clone());
+            async move {
+                send(
+                    &addr,
+                    "POST",
+                    &[(SESSION_HEADER, session_id.as_str())],
+                    &request(2, "hang"),
+                )
+                .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let session = [(SESSION_HEADER, session_id.as_str())];
+        let res = send(&addr, "POST", &session, &cancel(2)).await;
+        assert_eq!(res.status, 202);
+        let res = pending.await.unwrap();
+        assert_eq!(res.status, 204);
+
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 3, "method": "hang" },
+            { "jsonrpc": "2.0", "id": 4, "method": "tools/list" },
+        ])
+        .to_string();
+        let pending = tokio::spawn({
+            let (addr, session_id) = (addr.clone(), session_id.clone());
+            async move {
+                send(
+                    &addr,
+                    "POST",
+                    &[(SESSION_HEADER, session_id.as_str())],
+                    &batch,
+                )
+                .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        send(&addr, "POST", &session, &cancel(3)).await;
+        let res = pending.await.unwrap();
+        assert_eq!(res.status, 200);
+        let body: Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["id"], 4);
+
+        // Deleting the session answers a POST still waiting on it.
+        let pending = tokio::spawn({
+            let (addr, session_id) = (addr.clone(), session_id.clone());
+            async move {
+                send(
+                    &addr,
+                    "POST",
+                    &[(SESSION_HEADER, session_id.as_str())],
+                    &request(5, "hang"),
+                )
+                .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        send(&addr, "DELETE", &session, "").await;
+        let res = pending.await.unwrap();
+        assert_eq!(res.status, 404);
+    }
+
+    #[tokio::test]
+    async fn test_session_limits() {
+        let addr = start_server(McpHttp {
+            max_sessions: 1,
+            session_idle_timeout_seconds: 1,
+            ..open_policy()
+        })
+        .await;
+
+        let res = send(&addr, "POST", &[], &request(1, "initialize")).await;
+        assert_eq!(res.status, 200);
+        let session_id = res.headers[SESSION_HEADER].clone();
+        let res = send(&addr, "POST", &[], &request(1, "initialize")).await;
+        assert_eq!(res.status, 503);
+
+        // Requests keep the session open...
+        for id in 2..5 {
+            tokio::time::sleep(Duration::from_millis(400)).await;
+            let session = [(SESSION_HEADER, session_id.as_str())];
+            let res = send(&addr, "POST", &session, &request(id, "tools/list")).await;
+            assert_eq!(res.status, 200);
+        }
+
+        // ...until it has been idle too long, which frees its slot.
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        let session = [(SESSION_HEADER, session_id.as_str())];
+        let res = send(&addr, "POST", &session, &request(5, "tools/list")).await;
+        assert_eq!(res.status, 404);
+        let res = send(&addr, "POST", &[], &request(1, "initialize")).await;
+        assert_eq!(res.status, 200);
+    }
+
+    async fn send_raw(addr: &str, request: &str) -> u16 {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut raw = String::new();
+        stream.read_to_string(&mut raw).await.unwrap();
+        raw.split_whitespace().nth(1).unwrap().parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_malformed_requests() {
+        let addr = start_server(open_policy()).await;
+
+        let chunked =
+            "POST /mcp HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+        assert_eq!(send_raw(&addr, chunked).await, 411);
+
+        let long_header = format!(
+            "POST /mcp HTTP/1.1\r\nx-padding: {}\r\n\r\n",
+            "a".repeat(MAX_HEAD_BYTES)
+        );
+        assert_eq!(send_raw(&addr, &long_header).await, 431);
+
+        let mut many_headers = "POST /mcp HTTP/1.1\r\n".to_string();
+        for i in 0..=MAX_HEADERS {
+            many_headers.push_str(&format!("x-header-{}: 1\r\n", i));
+        }
+        many_headers.push_str("\r\n");
+        assert_eq!(send_raw(&addr, &many_headers).await, 431);
+
+        let bad_length = "POST /mcp HTTP/1.1\r\ncontent-length: lots\r\n\r\n";
+        assert_eq!(send_raw(&addr, bad_length).await, 400);
+
+        // Valid JSON, but not a message; without an ID it is answered directly.
+        let res = send(&addr, "POST", &[], r#"{"jsonrpc":"2.0","method":5}"#).await;
+        assert_eq!(res.status, 400);
+        let body: Value = serde_json::from_str(&res.body).unwrap();
+        assert_eq!(body["error"]["code"], -32600);
+    }
+
+    #[tokio::test]
+    async fn test_sse_stream() {
+        let addr = start_server(open_policy()).await;
+        let res = send(&addr, "POST", &[], &request(1, "initialize")).await;
+        let session_id = res.headers[SESSION_HEADER].clone();
+
+        let mut sse = TcpStream::connect(&addr).await.unwrap();
+        sse.write_all(
+            format!(
+                "GET /mcp HTTP/1.1\r\naccept: text/event-stream\r\n{}: {}\r\n\r\n",
+                SESSION_HEADER, session_id
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+        let mut sse = BufReader::new(sse);
+        let mut line = String::new();
+        sse.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "HTTP/1.1 200 OK\r\n");
+        // Skip the headers so the stream is known to be subscribed.
+        while line != "\r\n" {
+            line.clear();
+            sse.read_line(&mut line).await.unwrap();
+        }
+
+        let res = send(
+            &addr,
+            "POST",
+            &[(SESSION_HEADER, &session_id)],
+            &request(2, "notify"),
+        )
+        .await;
+        assert_eq!(res.status, 200);
+
+        loop {
+            line.clear();
+            sse.read_line(&mut line).await.unwrap();
+            if let Some(data) = line.strip_prefix("data: ") {
+                let message: Value = serde_json::from_str(data).unwrap();
+                assert_eq!(message["method"], "notifications/resources/updated");
+                assert_eq!(message["params"]["uri"], "cal2prompt://today");
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token_and_origin() {
+        let addr = start_server(McpHttp {
+            bearer_token: Some("s3cret".to_string()),
+            allowed_origins: vec!["https://app.example.com".to_string()],
+            ..open_policy()
+        })
+        .await;
+        let initialize = request(1, "initialize");
+
+        let res = send(&addr, "POST", &[], &initialize).await;
+        assert_eq!(res.status, 401);
+        let res = send(
+            &addr,
+            "POST",
+            &[("authorization", "Bearer wrong!")],
+            &initialize,
+        )
+        .await;
+        assert_eq!(res.status, 401);
+
+        let auth = ("authorization", "Bearer s3cret");
+        for (origin, status) in [
+            ("https://evil.example.com", 403),
+            ("http://localhost:6274", 200),
+            ("http://[::1]:6274", 200),
+            ("https://app.example.com", 200),
+        ] {
+            let res = send(&addr, "POST", &[auth, ("origin", origin)], &initialize).await;
+            assert_eq!(res.status, status, "{origin}");
+        }
+    }
+}
@@ -1,2 +1,7 @@
 pub mod handler;
+pub mod http;
+pub mod prompts;
+pub mod resources;
+#[cfg(test)]
+pub mod schema;
 pub mod stdio;
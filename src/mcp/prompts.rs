@@ -0,0 +1,154 @@
+use serde_json::{json, Value};
+
+use crate::config::templates::google::WEEKLY_REVIEW;
+use crate::config::Output;
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum PromptError {
+    #[error("Unknown prompt '{0}'. See prompts/list.")]
+    UnknownPrompt(String),
+
+    #[error("Calendar '{0}' is not one of source.google.calendar.getEvents.calendarIDs.")]
+    UnknownCalendar(String),
+}
+
+/// A template offered through `prompts/list`.
+#[derive(Debug, PartialEq)]
+pub struct Prompt<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub template: &'a str,
+    /// Used when `prompts/get` is called without a `range` argument.
+    pub default_range: &'a str,
+}
+
+/// The built-in prompts followed by `output.templates`. A user template with
+/// the name of a built-in one replaces it.
+pub fn all(output: &Output) -> Vec<Prompt<'_>> {
+    let mut prompts = vec![
+        Prompt {
+            name: "schedule",
+            description: "Your schedule, rendered with output.template",
+            template: &output.template,
+            default_range: "today",
+        },
+        Prompt {
+            name: "weekly-review",
+            description: "A review of where your time went, with totals, top collaborators and every day's events",
+            template: WEEKLY_REVIEW,
+            default_range: "this week",
+        },
+    ];
+    prompts.retain(|builtin| output.templates.iter().all(|t| t.name != builtin.name));
+    prompts.extend(output.templates.iter().map(|t| Prompt {
+        name: &t.name,
+        description: t.description.as_deref().unwrap_or(""),
+        template: &t.template,
+        default_range: t.range.as_deref().unwrap_or("today"),
+    }));
+    prompts
+}
+
+/// The result of `prompts/list`.
+pub fn list(prompts: &[Prompt]) -> Value {
+    let prompts: Vec<Value> = prompts
+        .iter()
+        .map(|prompt| {
+            json!({
+                "name": prompt.name,
+                "description": prompt.description,
+                "arguments": [
+                    {
+                        "name": "range",
+                        "description": format!("The dates to include, e.g. today, next week, next 3 days, 2025-03 or 2025-03-01..2025-03-07 (default: {})", prompt.default_range),
+                        "required": false
+                    },
+                    {
+                        "name": "calendar",
+                        "description": "Only include this configured calendar ID (default: all of them)",
+                        "required": false
+                    }
+                ]
+            })
+        })
+        .collect();
+
+    json!({ "prompts": prompts })
+}
+
+/// The result of `prompts/get`, with `text` as the single user message.
+pub fn message(prompt: &Prompt, text: String) -> Value {
+    json!({
+        "description": prompt.description,
+        "messages": [{
+            "role": "user",
+            "content": {
+                "type": "text",
+                "text": text,
+            },
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NamedTemplate;
+    use crate::core::cal2prompt::Agenda;
+    use crate::core::stats::Stats;
+    use crate::core::template::generate;
+
+    #[test]
+    fn test_all_prompts() {
+        let output = Output {
+            template: "{{ days|length }} days".to_string(),
+            include_empty_days: false,
+            templates: vec![
+                NamedTemplate {
+                    name: "standup".to_string(),
+                    description: Some("What is on today".to_string()),
+                    template: "standup".to_string(),
+                    range: None,
+                },
+                NamedTemplate {
+                    name: "weekly-review".to_string(),
+                    description: None,
+                    template: "my review".to_string(),
+                    range: Some("last week".to_string()),
+                },
+            ],
+        };
+
+        let prompts = all(&output);
+        let names: Vec<&str> = prompts.iter().map(|prompt| prompt.name).collect();
+        assert_eq!(names, ["schedule", "standup", "weekly-review"]);
+        assert_eq!(prompts[0].template, "{{ days|length }} days");
+        assert_eq!(prompts[2].template, "my review");
+        assert_eq!(prompts[2].default_range, "last week");
+
+        let listed = list(&prompts);
+        assert_eq!(listed["prompts"][1]["name"], "standup");
+        assert_eq!(listed["prompts"][1]["description"], "What is on today");
+        assert_eq!(listed["prompts"][1]["arguments"][0]["name"], "range");
+    }
+
+    #[test]
+    fn test_weekly_review_renders() {
+        let agenda = Agenda {
+            days: vec![],
+            free_slots: vec![],
+            stats: Stats {
+                total_events: 3,
+                total_minutes: 150,
+                ..Stats::default()
+            },
+        };
+
+        let rendered = generate(WEEKLY_REVIEW, &agenda).unwrap();
+        assert!(rendered.contains("- Events: 3 (2h 30m)\n"), "{rendered}");
+        assert!(
+            rendered.contains("## Top collaborators\n(None)\n"),
+            "{rendered}"
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use chrono::NaiveDate;
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::core::cal2prompt::GetEventDuration;
+
+const SCHEME: &str = "cal2prompt://";
+
+/// The relative ranges that can be read as `cal2prompt://{name}`.
+const SHORTCUTS: [(&str, &str); 10] = [
+    ("today", "Today"),
+    ("tomorrow", "Tomorrow"),
+    ("this-week", "This week"),
+    ("next-week", "Next week"),
+    ("last-week", "Last week"),
+    ("this-workweek", "This workweek"),
+    ("this-month", "This month"),
+    ("next-month", "Next month"),
+    ("last-month", "Last month"),
+    ("this-quarter", "This quarter"),
+];
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum ResourceError {
+    #[error("Unknown resource '{0}'. See resources/list and resources/templates/list.")]
+    UnknownUri(String),
+
+    #[error("Calendar '{0}' is not one of source.google.calendar.getEvents.calendarIDs.")]
+    UnknownCalendar(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ResourceRange {
+    Shortcut(GetEventDuration),
+    Day(NaiveDate),
+    /// `since` and `until` as accepted by `list_calendar_events`.
+    Range(String, String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ResourceFormat {
+    /// Rendered with `output.template`.
+    Text,
+    /// The days, free slots and stats the template is rendered from.
+    Json,
+}
+
+impl ResourceFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ResourceFormat::Text => "text/plain",
+            ResourceFormat::Json => "application/json",
+        }
+    }
+}
+
+/// A parsed resource URI:
+/// `cal2prompt://[calendar/{calendarId}/]{shortcut|day/{date}|range/{since}/{until}}[?format=json]`.
+#[derive(Debug, PartialEq)]
+pub struct ResourceUri {
+    /// `None` reads every configured calendar.
+    pub calendar_id: Option<String>,
+    pub range: ResourceRange,
+    pub format: ResourceFormat,
+}
+
+impl ResourceUri {
+    pub fn parse(uri: &str, calendar_ids: &[String]) -> Result<Self, ResourceError> {
+        let unknown = || ResourceError::UnknownUri(uri.to_string());
+
+        let rest = uri.strip_prefix(SCHEME).ok_or_else(unknown)?;
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+        let format = match query {
+            None | Some("format=text") => ResourceFormat::Text,
+            Some("format=json") => ResourceFormat::Json,
+            Some(_) => return Err(unknown()),
+        };
+
+        let segments = path
+            .split('/')
+            .map(|segment| Some(percent_decode_str(segment).decode_utf8().ok()?.into_owned()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(unknown)?;
+        let (calendar_id, segments) = match segments.as_slice() {
+            [calendar, id, rest @ ..] if calendar == "calendar" => {
+                if !calendar_ids.contains(id) {
+                    return Err(ResourceError::UnknownCalendar(id.clone()));
+                }
+                (Some(id.clone()), rest)
+            }
+            segments => (None, segments),
+        };
+
+        let range = match segments {
+            [name] => ResourceRange::Shortcut(shortcut(name).ok_or_else(unknown)?),
+            [day, date] if day == "day" => ResourceRange::Day(
+                NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| unknown())?,
+            ),
+            [range, since, until] if range == "range" => {
+                ResourceRange::Range(since.clone(), until.clone())
+            }
+            _ => return Err(unknown()),
+        };
+
+        Ok(Self {
+            calendar_id,
+            range,
+            format,
+        })
+    }
+}
+
+fn shortcut(name: &str) -> Option<GetEventDuration> {
+    Some(match name {
+        "today" => GetEventDuration::Today,
+        "tomorrow" => GetEventDuration::Tomorrow,
+        "this-week" => GetEventDuration::ThisWeek,
+        "next-week" => GetEventDuration::NextWeek,
+        "last-week" => GetEventDuration::LastWeek,
+        "this-workweek" => GetEventDuration::ThisWorkweek,
+        "this-month" => GetEventDuration::ThisMonth,
+        "next-month" => GetEventDuration::NextMonth,
+        "last-month" => GetEventDuration::LastMonth,
+        "this-quarter" => GetEventDuration::ThisQuarter,
+        _ => return None,
+    })
+}
+
+/// The result of `resources/list`: every shortcut over all calendars, and
+/// today for each calendar on its own.
+pub fn list(calendar_ids: &[String]) -> Value {
+    let mut resources: Vec<Value> = SHORTCUTS
+        .iter()
+        .map(|(name, title)| {
+            json!({
+                "uri": format!("{}{}", SCHEME, name),
+                "name": *title,
+                "description": format!("{}'s events from all configured calendars, rendered with output.template. Append ?format=json for the raw data.", title),
+                "mimeType": ResourceFormat::Text.mime_type(),
+            })
+        })
+        .collect();
+    resources.extend(calendar_ids.iter().map(|calendar_id| {
+        json!({
+            "uri": calendar_uri(calendar_id, "today"),
+            "name": format!("Today ({})", calendar_id),
+            "description": format!("Today's events from {}, rendered with output.template. Append ?format=json for the raw data.", calendar_id),
+            "mimeType": ResourceFormat::Text.mime_type(),
+        })
+    }));
+
+    json!({ "resources": resources })
+}
+
+/// `cal2prompt://calendar/{calendar_id}/{shortcut}`, with `calendar_id`
+/// escaped so that it stays one path segment.
+fn calendar_uri(calendar_id: &str, shortcut: &str) -> String {
+    let mut uri = Url::parse(&format!("{}calendar", SCHEME)).unwrap();
+    uri.path_segments_mut()
+        .expect("the calendar URI has a host")
+        .pop_if_empty()
+        .extend([calendar_id, shortcut]);
+    uri.into()
+}
+
+/// The result of `resources/templates/list`.
+pub fn templates() -> Value {
+    json!({
+        "resourceTemplates": [
+            {
+                "uriTemplate": "cal2prompt://day/{date}{?format}",
+                "name": "Day",
+                "description": "The events of one day (yyyy-MM-dd) from all configured calendars. format=json returns the raw data instead of the rendered template.",
+                "mimeType": ResourceFormat::Text.mime_type(),
+            },
+            {
+                "uriTemplate": "cal2prompt://range/{since}/{until}{?format}",
+                "name": "Range",
+                "description": "The events between since and until (yyyy-MM-dd, yyyy-MM-dd HH:mm or RFC3339, percent-encoded) from all configured calendars.",
+                "mimeType": ResourceFormat::Text.mime_type(),
+            },
+            {
+                "uriTemplate": "cal2prompt://calendar/{calendarId}/day/{date}{?format}",
+                "name": "Calendar day",
+                "description": "The events of one day from a single configured calendar.",
+                "mimeType": ResourceFormat::Text.mime_type(),
+            },
+            {
+                "uriTemplate": "cal2prompt://calendar/{calendarId}/{shortcut}{?format}",
+                "name": "Calendar range",
+                "description": "A relative range (today, tomorrow, this-week, next-week, last-week, this-workweek, this-month, next-month, last-month or this-quarter) from a single configured calendar.",
+                "mimeType": ResourceFormat::Text.mime_type(),
+            }
+        ]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_resource_uri() {
+        let calendar_ids = vec![
+            "work@example.com".to_string(),
+            "ja.japanese#holiday@group.v.calendar.google.com".to_string(),
+        ];
+        let parse = |uri: &str| ResourceUri::parse(uri, &calendar_ids);
+
+        assert_eq!(
+            parse("cal2prompt://today"),
+            Ok(ResourceUri {
+                calendar_id: None,
+                range: ResourceRange::Shortcut(GetEventDuration::Today),
+                format: ResourceFormat::Text,
+            })
+        );
+        assert_eq!(
+            parse("cal2prompt://calendar/work@example.com/day/2025-03-01?format=json"),
+            Ok(ResourceUri {
+                calendar_id: Some("work@example.com".to_string()),
+                range: ResourceRange::Day(NaiveDate::from_ymd_opt(2025, 3, 1).unwrap()),
+                format: ResourceFormat::Json,
+            })
+        );
+        assert_eq!(
+            parse("cal2prompt://range/2025-03-03%2009%3A00/2025-03-03%2018%3A00"),
+            Ok(ResourceUri {
+                calendar_id: None,
+                range: ResourceRange::Range(
+                    "2025-03-03 09:00".to_string(),
+                    "2025-03-03 18:00".to_string()
+                ),
+                format: ResourceFormat::Text,
+            })
+        );
+
+        // Listed URIs parse back to the calendar they were built from.
+        let holiday_uri = calendar_uri(&calendar_ids[1], "this-month");
+        assert_eq!(
+            holiday_uri,
+            "cal2prompt://calendar/ja.japanese%23holiday@group.v.calendar.google.com/this-month"
+        );
+        assert_eq!(
+            parse(&holiday_uri).unwrap().calendar_id.as_deref(),
+            Some(calendar_ids[1].as_str())
+        );
+
+        for uri in [
+            "https://today",
+            "cal2prompt://yesterday",
+            "cal2prompt://day/2025-02-30",
+            "cal2prompt://today?format=xml",
+            "cal2prompt://day/2025-03-01/extra",
+        ] {
+            assert_eq!(parse(uri), Err(ResourceError::UnknownUri(uri.to_string())));
+        }
+        assert_eq!(
+            parse("cal2prompt://calendar/other@example.com/today"),
+            Err(ResourceError::UnknownCalendar(
+                "other@example.com".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Checks tool results against the `outputSchema`s in tools.json, for tests.
+//!
+//! Covers the parts of JSON Schema that tools.json uses: `type` (a name or a
+//! list of them), `properties`, `required`, `items` and `oneOf`.
+
+use serde_json::Value;
+
+use crate::mcp::handler::TOOLS_JSON;
+
+/// The `outputSchema` of `tool`.
+pub fn output_schema(tool: &str) -> Value {
+    let tools: Value = serde_json::from_str(TOOLS_JSON).expect("tools.json must be valid JSON");
+    tools["tools"]
+        .as_array()
+        .and_then(|tools| tools.iter().find(|t| t["name"] == tool))
+        .unwrap_or_else(|| panic!("no tool named {tool}"))["outputSchema"]
+        .clone()
+}
+
+/// Panics with every mismatch when `value` does not match `tool`'s
+/// `outputSchema`.
+pub fn assert_matches_output_schema(tool: &str, value: &Value) {
+    let mut errors = Vec::new();
+    check(value, &output_schema(tool), "$", &mut errors);
+    assert!(errors.is_empty(), "{tool}: {errors:#?}\n{value:#}");
+}
+
+fn check(value: &Value, schema: &Value, path: &str, errors: &mut Vec<String>) {
+    if let Some(types) = schema.get("type") {
+        let types: Vec<&str> = match types {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            types => types.as_str().into_iter().collect(),
+        };
+        if !types.iter().any(|name| has_type(value, name)) {
+            errors.push(format!("{path}: expected {types:?}, got {value}"));
+            return;
+        }
+    }
+
+    if let Some(variants) = schema.get("oneOf").and_then(Value::as_array) {
+        let matching = variants
+            .iter()
+            .filter(|variant| {
+                let mut variant_errors = Vec::new();
+                check(value, variant, path, &mut variant_errors);
+                variant_errors.is_empty()
+            })
+            .count();
+        if matching != 1 {
+            errors.push(format!(
+                "{path}: matches {matching} of the oneOf variants, not 1"
+            ));
+        }
+    }
+
+    if let Value::Object(object) = value {
+        for name in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !object.contains_key(name) {
+                errors.push(format!("{path}: missing required {name}"));
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (name, property) in properties {
+                if let Some(value) = object.get(name) {
+                    check(value, property, &format!("{path}.{name}"), errors);
+                }
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            check(item, item_schema, &format!("{path}[{i}]"), errors);
+        }
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use futures::Stream;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::{
     io::Write,
     pin::Pin,
@@ -20,13 +21,30 @@ pub enum Error {
     Other(String),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A JSON-RPC request ID, which clients may send as a number or a string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RequestId {
+    Number(i64),
+    String(String),
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestId::Number(id) => write!(f, "{}", id),
+            RequestId::String(id) => write!(f, "{:?}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub enum Message {
     Request {
         jsonrpc: String,
         method: String,
-        id: u64,
+        id: RequestId,
         #[serde(skip_serializing_if = "Option::is_none")]
         params: Option<serde_json::Value>,
     },
@@ -38,12 +56,92 @@ pub enum Message {
     },
     Response {
         jsonrpc: String,
-        id: u64,
+        /// `None` (sent as `null`) when the request's ID could not be read.
+        id: Option<RequestId>,
         #[serde(skip_serializing_if = "Option::is_none")]
         result: Option<serde_json::Value>,
         #[serde(skip_serializing_if = "Option::is_none")]
         error: Option<serde_json::Value>,
     },
+    /// Several messages sent as one JSON array.
+    Batch(Vec<Message>),
+    /// Valid JSON that is not a valid message, kept with the ID it carried so
+    /// it can be answered with an Invalid Request error. Never sent.
+    #[serde(skip_serializing)]
+    Invalid {
+        id: Option<RequestId>,
+        reason: String,
+    },
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Message::from_value(Value::deserialize(deserializer)?))
+    }
+}
+
+impl Message {
+    /// Tells requests, notifications and responses apart by their members:
+    /// a request has a `method` and an `id`, a notification a `method` only,
+    /// and a response a `result` or an `error` but no `method`.
+    fn from_value(value: Value) -> Message {
+        let mut object = match value {
+            Value::Array(values) => {
+                return Message::Batch(values.into_iter().map(Message::from_value).collect())
+            }
+            Value::Object(object) => object,
+            _ => return Message::invalid(None, "A message must be an object or an array"),
+        };
+
+        let id = object.remove("id").map(serde_json::from_value::<RequestId>);
+        let readable_id = id.as_ref().and_then(|id| id.as_ref().ok()).cloned();
+        let jsonrpc = match object.remove("jsonrpc") {
+            Some(Value::String(jsonrpc)) if jsonrpc == "2.0" => jsonrpc,
+            _ => return Message::invalid(readable_id, "jsonrpc must be \"2.0\""),
+        };
+        let params = object.remove("params");
+
+        match object.remove("method") {
+            Some(Value::String(method)) => match id {
+                None => Message::Notification {
+                    jsonrpc,
+                    method,
+                    params,
+                },
+                Some(Ok(id)) => Message::Request {
+                    jsonrpc,
+                    method,
+                    id,
+                    params,
+                },
+                Some(Err(_)) => Message::invalid(None, "id must be a string or an integer"),
+            },
+            Some(_) => Message::invalid(readable_id, "method must be a string"),
+            None => {
+                let result = object.remove("result");
+                let error = object.remove("error");
+                if result.is_none() && error.is_none() {
+                    return Message::invalid(
+                        readable_id,
+                        "A message needs a method, a result or an error",
+                    );
+                }
+                Message::Response {
+                    jsonrpc,
+                    id: readable_id,
+                    result,
+                    error,
+                }
+            }
+        }
+    }
+
+    fn invalid(id: Option<RequestId>, reason: &str) -> Message {
+        Message::Invalid {
+            id,
+            reason: reason.to_string(),
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -77,6 +175,8 @@ impl StdioTransport {
                 line.clear();
                 match reader.read_line(&mut line).await {
                     Ok(0) => break,
+                    // Not a message, so not a parse error either.
+                    Ok(_) if line.trim().is_empty() => continue,
                     Ok(_) => {
                         let parsed = match serde_json::from_str::<Message>(&line) {
                             Ok(msg) => Ok(msg),
@@ -1,14 +1,148 @@
-use chrono::{DateTime, NaiveDate, TimeZone};
+use chrono::{
+    DateTime, Days, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone, Weekday,
+};
 use chrono_tz::Tz;
 
-pub fn to_utc_start_of_start_rfc3339(datetime_tz: DateTime<Tz>) -> String {
-    let tz = datetime_tz.timezone();
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum DateError {
+    #[error("Invalid date/time '{0}'. Use YYYY-MM-DD, YYYY-MM-DD HH:MM or RFC3339 (e.g. 2025-01-01T10:00:00+09:00).")]
+    Invalid(String),
 
-    let local_start_naive_date_time = datetime_tz.date_naive().and_hms_opt(0, 0, 0).unwrap();
-    let local_start_date_time = tz
-        .from_local_datetime(&local_start_naive_date_time)
-        .unwrap();
-    local_start_date_time.to_utc().to_rfc3339()
+    #[error("'{0}' does not exist in {1} because clocks jump forward at that time (DST gap). Choose a time outside the gap or give an explicit offset.")]
+    Nonexistent(String, String),
+
+    #[error("Invalid duration '{0}'. Use minutes or a value like 30m, 1h or 1h30m.")]
+    InvalidDuration(String),
+
+    #[error("'{0}' occurs twice in {1} because clocks fall back at that time (DST overlap). Give an explicit offset to pick one.")]
+    Ambiguous(String, String),
+}
+
+/// How a wall-clock time that does not map to exactly one instant is turned
+/// into one.
+///
+/// For a repeated time (fold), `Earliest` and `Latest` pick the first or
+/// second occurrence. For a skipped time (gap), both shift the time forward by
+/// the length of the gap, so `02:30` on a spring-forward day becomes `03:30`.
+/// `Reject` returns an error in either case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalResolution {
+    Earliest,
+    Latest,
+    Reject,
+}
+
+/// Resolves a local wall-clock time in `tz` according to `policy`.
+pub fn resolve_local(
+    tz: &Tz,
+    naive: NaiveDateTime,
+    policy: LocalResolution,
+) -> Result<DateTime<Tz>, DateError> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(datetime) => Ok(datetime),
+        LocalResult::Ambiguous(earliest, latest) => match policy {
+            LocalResolution::Earliest => Ok(earliest),
+            LocalResolution::Latest => Ok(latest),
+            LocalResolution::Reject => Err(DateError::Ambiguous(
+                naive.to_string(),
+                tz.name().to_string(),
+            )),
+        },
+        LocalResult::None => match policy {
+            LocalResolution::Reject => Err(DateError::Nonexistent(
+                naive.to_string(),
+                tz.name().to_string(),
+            )),
+            LocalResolution::Earliest | LocalResolution::Latest => {
+                // Interpret the time with the offset in effect before the gap,
+                // which lands the same distance past the transition.
+                let before = tz.offset_from_utc_datetime(&(naive - Duration::days(1)));
+                let utc = naive - Duration::seconds(before.fix().local_minus_utc().into());
+                Ok(tz.from_utc_datetime(&utc))
+            }
+        },
+    }
+}
+
+/// Which end of a time window a user supplied value describes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    Start,
+    End,
+}
+
+/// The first instant of `date` in `tz`. When midnight is skipped by a DST
+/// transition, this is the first instant after the gap.
+pub fn start_of_day(date: NaiveDate, tz: &Tz) -> DateTime<Tz> {
+    let local_start_naive_date_time = date.and_hms_opt(0, 0, 0).unwrap();
+    resolve_local(tz, local_start_naive_date_time, LocalResolution::Earliest)
+        .expect("only LocalResolution::Reject can fail")
+}
+
+/// Parses `YYYY-MM-DD`, `YYYY-MM-DD HH:MM` or RFC3339 into an instant in `tz`.
+///
+/// A bare date covers the whole day: as a start bound it is the beginning of
+/// that day, as an end bound the (exclusive) beginning of the next day.
+/// Local times in a DST overlap resolve so the window is as wide as possible.
+pub fn parse_bound(input: &str, tz: &Tz, bound: Bound) -> Result<DateTime<Tz>, DateError> {
+    let input = input.trim();
+    let invalid = || DateError::Invalid(input.to_string());
+
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        let date = match bound {
+            Bound::Start => date,
+            Bound::End => date.checked_add_days(Days::new(1)).ok_or_else(invalid)?,
+        };
+        return Ok(start_of_day(date, tz));
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(input) {
+        return Ok(datetime.with_timezone(tz));
+    }
+
+    for format in ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(input, format) {
+            let policy = match bound {
+                Bound::Start => LocalResolution::Earliest,
+                Bound::End => LocalResolution::Latest,
+            };
+            return resolve_local(tz, naive, policy);
+        }
+    }
+
+    Err(invalid())
+}
+
+/// Parses a duration such as `30m`, `1h`, `1h30m` or a bare number of minutes.
+pub fn parse_duration(input: &str) -> Result<Duration, DateError> {
+    let input = input.trim();
+    let invalid = || DateError::InvalidDuration(input.to_string());
+
+    if let Ok(minutes) = input.parse::<u32>() {
+        return Ok(Duration::minutes(minutes.into()));
+    }
+
+    let mut total = Duration::zero();
+    let mut digits = String::new();
+    for c in input.to_lowercase().chars() {
+        match c {
+            '0'..='9' => digits.push(c),
+            'h' | 'm' => {
+                let value: u32 = digits.parse().map_err(|_| invalid())?;
+                total += match c {
+                    'h' => Duration::hours(value.into()),
+                    _ => Duration::minutes(value.into()),
+                };
+                digits.clear();
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    if !digits.is_empty() || total.is_zero() {
+        return Err(invalid());
+    }
+    Ok(total)
 }
 
 pub fn intersection_days(
@@ -34,6 +168,25 @@ pub fn intersection_days(
     days
 }
 
+/// Parses a weekday name such as `monday` or `Mon`.
+pub fn parse_weekday(name: &str) -> Option<Weekday> {
+    match name.trim().to_lowercase().as_str() {
+        "mon" | "monday" => Some(Weekday::Mon),
+        "tue" | "tues" | "tuesday" => Some(Weekday::Tue),
+        "wed" | "wednesday" => Some(Weekday::Wed),
+        "thu" | "thur" | "thurs" | "thursday" => Some(Weekday::Thu),
+        "fri" | "friday" => Some(Weekday::Fri),
+        "sat" | "saturday" => Some(Weekday::Sat),
+        "sun" | "sunday" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// Number of days between `week_start` and `weekday` going forward (0..=6).
+pub fn days_since_week_start(weekday: Weekday, week_start: Weekday) -> u32 {
+    (weekday.num_days_from_monday() + 7 - week_start.num_days_from_monday()) % 7
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -49,7 +202,9 @@ mod tests {
             .unwrap();
         let local_datetime = tz.from_local_datetime(&naive_datetime).single().unwrap();
 
-        let result = to_utc_start_of_start_rfc3339(local_datetime);
+        let result = start_of_day(local_datetime.date_naive(), &tz)
+            .to_utc()
+            .to_rfc3339();
 
         let expected_str = "2023-01-01T15:00:00+00:00";
 
@@ -67,7 +222,9 @@ mod tests {
             .unwrap();
         let local_datetime = tz.from_local_datetime(&naive_datetime).single().unwrap();
 
-        let result = to_utc_start_of_start_rfc3339(local_datetime);
+        let result = start_of_day(local_datetime.date_naive(), &tz)
+            .to_utc()
+            .to_rfc3339();
 
         let expected_str = "2023-07-10T07:00:00+00:00";
 
@@ -75,4 +232,167 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_parse_bound_date_only() {
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+
+        let start = parse_bound("2025-03-01", &tz, Bound::Start).unwrap();
+        let end = parse_bound("2025-03-01", &tz, Bound::End).unwrap();
+
+        assert_eq!(start.to_rfc3339(), "2025-03-01T00:00:00+09:00");
+        assert_eq!(end.to_rfc3339(), "2025-03-02T00:00:00+09:00");
+    }
+
+    #[test]
+    fn test_parse_bound_local_time() {
+        let tz: Tz = "America/Los_Angeles".parse().unwrap();
+
+        let start = parse_bound("2025-03-01 14:30", &tz, Bound::Start).unwrap();
+        let end = parse_bound("2025-03-01T18:00", &tz, Bound::End).unwrap();
+
+        assert_eq!(start.to_rfc3339(), "2025-03-01T14:30:00-08:00");
+        assert_eq!(end.to_rfc3339(), "2025-03-01T18:00:00-08:00");
+    }
+
+    #[test]
+    fn test_parse_bound_rfc3339() {
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+
+        let start = parse_bound("2025-03-01T10:00:00Z", &tz, Bound::Start).unwrap();
+
+        assert_eq!(start.to_rfc3339(), "2025-03-01T19:00:00+09:00");
+    }
+
+    #[test]
+    fn test_parse_bound_invalid() {
+        let tz: Tz = "Asia/Tokyo".parse().unwrap();
+
+        assert_eq!(
+            parse_bound("tomorrow", &tz, Bound::Start),
+            Err(DateError::Invalid("tomorrow".to_string()))
+        );
+    }
+
+    fn naive(input: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(input, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_start_of_day_skipped_midnight() {
+        // Brazil started DST at midnight on 2018-11-04, so 00:00 never happened.
+        let tz: Tz = "America/Sao_Paulo".parse().unwrap();
+        let date = NaiveDate::from_ymd_opt(2018, 11, 4).unwrap();
+
+        assert_eq!(
+            start_of_day(date, &tz).to_rfc3339(),
+            "2018-11-04T01:00:00-02:00"
+        );
+        assert_eq!(
+            parse_bound("2018-11-03", &tz, Bound::End)
+                .unwrap()
+                .to_rfc3339(),
+            "2018-11-04T01:00:00-02:00"
+        );
+    }
+
+    #[test]
+    fn test_resolve_local_gap() {
+        let cases = [
+            (
+                "America/Los_Angeles",
+                "2025-03-09 02:30",
+                "2025-03-09T03:30:00-07:00",
+            ),
+            (
+                "Europe/Berlin",
+                "2025-03-30 02:30",
+                "2025-03-30T03:30:00+02:00",
+            ),
+            (
+                "Australia/Sydney",
+                "2025-10-05 02:15",
+                "2025-10-05T03:15:00+11:00",
+            ),
+        ];
+
+        for (zone, local, expected) in cases {
+            let tz: Tz = zone.parse().unwrap();
+            for policy in [LocalResolution::Earliest, LocalResolution::Latest] {
+                let resolved = resolve_local(&tz, naive(local), policy).unwrap();
+                assert_eq!(resolved.to_rfc3339(), expected, "{zone} {policy:?}");
+            }
+            assert!(matches!(
+                resolve_local(&tz, naive(local), LocalResolution::Reject),
+                Err(DateError::Nonexistent(_, _))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_resolve_local_fold() {
+        let cases = [
+            (
+                "America/Los_Angeles",
+                "2025-11-02 01:30",
+                "2025-11-02T01:30:00-07:00",
+                "2025-11-02T01:30:00-08:00",
+            ),
+            (
+                "Europe/Berlin",
+                "2025-10-26 02:30",
+                "2025-10-26T02:30:00+02:00",
+                "2025-10-26T02:30:00+01:00",
+            ),
+            (
+                "America/Sao_Paulo",
+                "2019-02-16 23:30",
+                "2019-02-16T23:30:00-02:00",
+                "2019-02-16T23:30:00-03:00",
+            ),
+        ];
+
+        for (zone, local, earliest, latest) in cases {
+            let tz: Tz = zone.parse().unwrap();
+            let resolve = |policy| resolve_local(&tz, naive(local), policy);
+
+            assert_eq!(
+                resolve(LocalResolution::Earliest).unwrap().to_rfc3339(),
+                earliest
+            );
+            assert_eq!(
+                resolve(LocalResolution::Latest).unwrap().to_rfc3339(),
+                latest
+            );
+            assert!(matches!(
+                resolve(LocalResolution::Reject),
+                Err(DateError::Ambiguous(_, _))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_bound_fold_widens_window() {
+        let tz: Tz = "America/Los_Angeles".parse().unwrap();
+
+        let start = parse_bound("2025-11-02 01:30", &tz, Bound::Start).unwrap();
+        let end = parse_bound("2025-11-02 01:30", &tz, Bound::End).unwrap();
+
+        assert_eq!(end - start, Duration::hours(1));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30m"), Ok(Duration::minutes(30)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::hours(1)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::minutes(90)));
+        assert_eq!(parse_duration("45"), Ok(Duration::minutes(45)));
+
+        for input in ["", "m", "30x", "1h30", "0m"] {
+            assert!(
+                matches!(parse_duration(input), Err(DateError::InvalidDuration(_))),
+                "{input}"
+            );
+        }
+    }
 }
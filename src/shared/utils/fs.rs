@@ -0,0 +1,218 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+
+const PRIVATE_DIR_MODE: u32 = 0o700;
+const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// Creates `dir` and any missing parents with owner-only permissions.
+/// An existing `dir` is made owner-only as well; its parents are left alone.
+pub fn create_private_dir_all(dir: &Path) -> std::io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(PRIVATE_DIR_MODE);
+    builder.create(dir)?;
+
+    #[cfg(unix)]
+    if fs::metadata(dir)?.permissions().mode() & 0o777 != PRIVATE_DIR_MODE {
+        fs::set_permissions(dir, fs::Permissions::from_mode(PRIVATE_DIR_MODE))?;
+    }
+
+    Ok(())
+}
+
+/// Writes `contents` to `path` readable only by the current user.
+///
+/// The data is written to a temporary file in the same directory, synced and
+/// then renamed over `path`, so a crash never leaves a truncated file behind.
+pub fn write_private_file_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let dir = path.parent().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("'{}' has no parent directory", path.display()),
+        )
+    })?;
+    create_private_dir_all(dir)?;
+
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp_path = dir.join(format!(".{}.tmp.{}", file_name, std::process::id()));
+
+    let result = (|| {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(PRIVATE_FILE_MODE);
+
+        let mut file = options.open(&tmp_path)?;
+        #[cfg(unix)]
+        file.set_permissions(fs::Permissions::from_mode(PRIVATE_FILE_MODE))?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        fs::rename(&tmp_path, path)?;
+
+        // Persist the rename itself.
+        File::open(dir)?.sync_all()
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+
+    result
+}
+
+/// Appends `line` and a newline to `path`, creating it readable only by the
+/// current user. Existing contents are never rewritten.
+///
+/// A missing parent directory is created owner-only, but an existing one is
+/// left as it is, since a user-chosen log location may be shared.
+pub fn append_private_line(path: &Path, line: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.exists()) {
+        create_private_dir_all(dir)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.append(true).create(true);
+    #[cfg(unix)]
+    options.mode(PRIVATE_FILE_MODE);
+
+    let mut file = options.open(path)?;
+    file.write_all(format!("{}\n", line).as_bytes())?;
+    file.sync_all()
+}
+
+/// An exclusive advisory lock held until the value is dropped.
+///
+/// The lock is taken on a separate `<path>.lock` file so the locked file
+/// itself can still be replaced by rename.
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Blocks until the lock for `path` is acquired.
+    pub fn acquire(path: &Path) -> std::io::Result<Self> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = Path::new(&lock_path);
+
+        if let Some(dir) = lock_path.parent() {
+            create_private_dir_all(dir)?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(false);
+        #[cfg(unix)]
+        options.mode(PRIVATE_FILE_MODE);
+        let file = options.open(lock_path)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            loop {
+                // SAFETY: the descriptor is owned by `file` and stays open for the
+                // lifetime of the returned `FileLock`.
+                if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+                    break;
+                }
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_private_file_atomic_creates_file() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("nested/dir/oauth");
+
+        write_private_file_atomic(&path, b"first")?;
+        write_private_file_atomic(&path, b"second")?;
+
+        assert_eq!(fs::read_to_string(&path)?, "second");
+
+        let leftovers: Vec<_> = fs::read_dir(path.parent().unwrap())?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name() != "oauth")
+            .collect();
+        assert!(
+            leftovers.is_empty(),
+            "temporary file should be renamed away"
+        );
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_lock_is_exclusive() -> anyhow::Result<()> {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("oauth");
+        let released = Arc::new(AtomicBool::new(false));
+
+        let lock = FileLock::acquire(&path)?;
+        let waiter = {
+            let path = path.clone();
+            let released = Arc::clone(&released);
+            std::thread::spawn(move || {
+                let _lock = FileLock::acquire(&path).unwrap();
+                released.load(Ordering::SeqCst)
+            })
+        };
+
+        std::thread::sleep(Duration::from_millis(100));
+        released.store(true, Ordering::SeqCst);
+        drop(lock);
+
+        assert!(
+            waiter.join().unwrap(),
+            "second lock must wait for the first to be released"
+        );
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_private_file_atomic_permissions() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let dir = temp_dir.path().join("share/cal2prompt");
+        let path = dir.join("oauth");
+
+        write_private_file_atomic(&path, b"secret")?;
+
+        let file_mode = fs::metadata(&path)?.permissions().mode() & 0o777;
+        let dir_mode = fs::metadata(&dir)?.permissions().mode() & 0o777;
+        assert_eq!(file_mode, 0o600);
+        assert_eq!(dir_mode, 0o700);
+
+        // A directory left world-readable by an older version is tightened.
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755))?;
+        write_private_file_atomic(&path, b"secret")?;
+        let dir_mode = fs::metadata(&dir)?.permissions().mode() & 0o777;
+        assert_eq!(dir_mode, 0o700);
+
+        Ok(())
+    }
+}
@@ -1,2 +1,5 @@
 pub mod date;
+pub mod fs;
 pub mod path;
+#[cfg(test)]
+pub mod stub_http;
@@ -0,0 +1,65 @@
+//! A bare HTTP/1.1 server for tests, standing in for Google endpoints.
+
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+/// A request the stub received.
+#[derive(Debug)]
+pub struct StubRequest {
+    /// E.g. `POST /calendars/work@example.com/events HTTP/1.1`.
+    pub request_line: String,
+    pub body: String,
+}
+
+impl StubRequest {
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+}
+
+/// Answers one request for each `(status line, body)` in `responses`, in
+/// order, and returns the requests. Every connection is closed after its
+/// response, so clients cannot send the next request on it.
+pub async fn serve(
+    listener: TcpListener,
+    responses: Vec<(&'static str, &'static str)>,
+) -> Vec<StubRequest> {
+    let mut requests = Vec::with_capacity(responses.len());
+    for (status_line, response_body) in responses {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut reader = BufReader::new(&mut stream);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).await.unwrap();
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).await.unwrap();
+
+        let response = format!(
+            "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            status_line,
+            response_body.len(),
+            response_body
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+
+        requests.push(StubRequest {
+            request_line: request_line.trim_end().to_string(),
+            body: String::from_utf8(body).unwrap(),
+        });
+    }
+    requests
+}